@@ -0,0 +1,697 @@
+// the command line interface of hidebox, the subcommands match the GUI operations.
+//  The progress is written to stderr and the result to stdout, as text or as JSON.
+//  The path "-" is stdin or stdout, the result and the error are written to stderr
+//  if stdout is the output.
+
+mod password;
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use hidebox_core::{
+    backend::jpeg, sniff, Backend, CancelHandle, EncodeOutcome, FileSpec, HideboxError, Output,
+    ProgressCb, ProgressInfo, StreamSpec,
+};
+use password::PasswordArgs;
+use serde_json::{json, Value};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, AsyncWriteExt};
+
+const EXIT_ERROR: u8 = 1;
+const EXIT_WRONG_PASSWORD: u8 = 3;
+const EXIT_NOT_A_CARRIER: u8 = 4;
+const EXIT_CORRUPT: u8 = 5;
+const EXIT_UNSUPPORTED: u8 = 6;
+const EXIT_TOO_LARGE: u8 = 7;
+const EXIT_BROKEN_CARRIER: u8 = 8;
+const EXIT_CANCELLED: u8 = 130;
+
+#[derive(Parser, Debug)]
+#[command(name = "hidebox-cli", version, about = "hide a file in another file")]
+struct Cli {
+    #[arg(long, global = true, help = "print the result as JSON")]
+    json: bool,
+
+    #[arg(short, long, global = true, help = "do not print the progress")]
+    quiet: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "hide the payload file in the carrier file")]
+    Encode(EncodeArgs),
+
+    #[command(about = "extract the payload file from the carrier file")]
+    Decode(DecodeArgs),
+
+    #[command(about = "show the payloads of the files without the password")]
+    Inspect {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    #[command(
+        about = "remove the trailer and the extended attributes payloads in place, the embedded payloads are not supported"
+    )]
+    Strip { file: PathBuf },
+}
+
+#[derive(Args, Debug)]
+struct EncodeArgs {
+    #[arg(help = "the carrier file, or - for stdin")]
+    carrier: PathBuf,
+
+    #[arg(help = "the payload file, or - for stdin")]
+    payload: PathBuf,
+
+    #[arg(short, long, help = "the output carrier file, or - for stdout")]
+    output: PathBuf,
+
+    #[arg(long, help = "the payload name which is stored in the carrier")]
+    name: Option<String>,
+
+    #[arg(long, conflicts_with = "xattr", help = "use the steganography backend")]
+    stego: bool,
+
+    #[arg(long, help = "store the payload in the extended attributes")]
+    xattr: bool,
+
+    #[arg(
+        long,
+        value_name = "MARKER",
+        value_parser = parse_jpeg_marker,
+        conflicts_with_all = ["stego", "xattr"],
+        help = "the segment of a JPEG carrier which stores the payload, APP11 to APP15 or COM"
+    )]
+    jpeg_segment: Option<jpeg::Marker>,
+
+    #[command(flatten)]
+    password: PasswordArgs,
+}
+
+#[derive(Args, Debug)]
+struct DecodeArgs {
+    #[arg(help = "the carrier file, or - for stdin")]
+    carrier: PathBuf,
+
+    #[arg(short, long, help = "the output payload file, or - for stdout")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    password: PasswordArgs,
+}
+
+// the result of a subcommand, the exit code is not zero if the command finished
+//  without doing its work, e.g. the file is not a carrier
+struct Report {
+    json: Value,
+    text: String,
+    code: u8,
+    is_stdout_used: bool,
+}
+
+impl Report {
+    fn ok(json: Value, text: String) -> Self {
+        Report {
+            json,
+            text,
+            code: 0,
+            is_stdout_used: false,
+        }
+    }
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+// the stdin is read into the memory, the carriers are sniffed and seeked
+async fn read_stdin() -> Result<Vec<u8>> {
+    let mut data = vec![];
+    tokio::io::stdin().read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+// the file is written into a temporary file which is renamed to the path by commit_output,
+//  so a failed or canceled job never leaves a truncated file and the path can be an input
+async fn create_output(
+    path: &Path,
+) -> Result<(Box<dyn AsyncWrite + Unpin + Send>, Option<Output>)> {
+    if is_stdio(path) {
+        Ok((Box::new(tokio::io::stdout()), None))
+    } else {
+        let output = Output::new(path);
+        Ok((Box::new(output.create().await?), Some(output)))
+    }
+}
+
+async fn commit_output(
+    mut writer: Box<dyn AsyncWrite + Unpin + Send>,
+    output: Option<Output>,
+) -> Result<()> {
+    writer.flush().await?;
+    drop(writer);
+
+    if let Some(output) = output {
+        output.commit().await?;
+    }
+    Ok(())
+}
+
+fn exit_code(e: &anyhow::Error) -> u8 {
+    match e.downcast_ref::<HideboxError>() {
+        Some(HideboxError::WrongPassword) => EXIT_WRONG_PASSWORD,
+        Some(HideboxError::NotACarrier) => EXIT_NOT_A_CARRIER,
+        Some(HideboxError::Corrupt(_)) => EXIT_CORRUPT,
+        Some(HideboxError::Unsupported(_)) => EXIT_UNSUPPORTED,
+        Some(HideboxError::TooLarge(_)) => EXIT_TOO_LARGE,
+        Some(HideboxError::BrokenCarrier(_)) => EXIT_BROKEN_CARRIER,
+        Some(HideboxError::Cancelled) => EXIT_CANCELLED,
+        Some(HideboxError::Io(_)) | None => EXIT_ERROR,
+    }
+}
+
+fn error_kind(e: &anyhow::Error) -> &'static str {
+    match e.downcast_ref::<HideboxError>() {
+        Some(HideboxError::WrongPassword) => "wrong-password",
+        Some(HideboxError::NotACarrier) => "not-a-carrier",
+        Some(HideboxError::Corrupt(_)) => "corrupt",
+        Some(HideboxError::Unsupported(_)) => "unsupported",
+        Some(HideboxError::TooLarge(_)) => "too-large",
+        Some(HideboxError::BrokenCarrier(_)) => "broken-carrier",
+        Some(HideboxError::Cancelled) => "cancelled",
+        Some(HideboxError::Io(_)) => "io",
+        None => "error",
+    }
+}
+
+// the total of the streams is unknown until they end
+fn progress_line(info: &ProgressInfo) -> String {
+    let mut line = if info.total == 0 {
+        format!("{} {} bytes", info.phase.name(), info.done)
+    } else {
+        format!(
+            "{} {}% {}/{} bytes",
+            info.phase.name(),
+            info.percent(),
+            info.done,
+            info.total
+        )
+    };
+
+    if info.throughput > 0.0 {
+        line.push_str(&format!(", {:.0} bytes/s", info.throughput));
+    }
+
+    if let Some(eta) = info.eta {
+        line.push_str(&format!(", eta {}s", eta.as_secs()));
+    }
+
+    line
+}
+
+fn progress_callback(quiet: bool) -> ProgressCb {
+    Arc::new(move |info: ProgressInfo| {
+        if quiet {
+            return;
+        }
+
+        // pad the line to clear the end of the previous longer line
+        let mut stderr = std::io::stderr().lock();
+        _ = write!(stderr, "\r{:<72}", progress_line(&info));
+        _ = stderr.flush();
+    })
+}
+
+fn end_progress(quiet: bool) {
+    if !quiet {
+        eprintln!();
+    }
+}
+
+// cancel the job on ctrl-c, the temporary output file is removed when the job ends
+fn cancel_on_ctrl_c(cancel_handle: CancelHandle) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel_handle.cancel();
+        }
+    });
+}
+
+async fn file_spec(path: &Path) -> Result<FileSpec> {
+    let meta = tokio::fs::metadata(path).await?;
+    if !meta.is_file() {
+        return Err(anyhow!("{} is not a regular file", path.display()));
+    }
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid file name {}", path.display()))?
+        .to_string();
+
+    Ok(FileSpec {
+        path: path.to_string_lossy().to_string(),
+        name,
+        size: meta.len(),
+    })
+}
+
+fn parse_jpeg_marker(name: &str) -> Result<jpeg::Marker, String> {
+    jpeg::Marker::from_name(name).ok_or_else(|| format!("{name} is not APP11 to APP15 or COM"))
+}
+
+fn select_backend(file_type: sniff::FileType, args: &EncodeArgs) -> Result<Backend> {
+    if args.xattr {
+        Ok(Backend::Xattr)
+    } else if args.stego {
+        file_type.stego_backend().ok_or_else(|| {
+            HideboxError::unsupported("the steganography does not support the carrier format")
+                .into()
+        })
+    } else if let Some(marker) = args.jpeg_segment {
+        match file_type.backend() {
+            Backend::JpegSegment(_) => Ok(Backend::JpegSegment(marker)),
+            _ => Err(HideboxError::unsupported("the JPEG segment needs a JPEG carrier").into()),
+        }
+    } else {
+        Ok(file_type.backend())
+    }
+}
+
+// the files are encoded by the job which verifies the output and replaces it atomically
+async fn encode_file(
+    args: &EncodeArgs,
+    password: String,
+    quiet: bool,
+) -> Result<(Backend, EncodeOutcome)> {
+    let src_spec = file_spec(&args.carrier).await?;
+    let mut append_spec = file_spec(&args.payload).await?;
+    if let Some(name) = &args.name {
+        append_spec.name = name.clone();
+    }
+
+    let file_type = sniff::sniff_file(&args.carrier).await?;
+    let backend = select_backend(file_type, args)?;
+
+    let job = hidebox_core::encode::spawn(
+        src_spec,
+        append_spec,
+        args.output.clone(),
+        password,
+        backend,
+        progress_callback(quiet),
+    );
+    cancel_on_ctrl_c(job.cancel_handle());
+    let outcome = job.wait().await;
+    end_progress(quiet);
+    Ok((backend, outcome?))
+}
+
+async fn encode_stream(
+    args: &EncodeArgs,
+    password: String,
+    quiet: bool,
+) -> Result<(Backend, EncodeOutcome)> {
+    if is_stdio(&args.carrier) && is_stdio(&args.payload) {
+        return Err(anyhow!("the carrier and the payload can not both be stdin"));
+    }
+
+    let carrier = if is_stdio(&args.carrier) {
+        read_stdin().await?
+    } else {
+        tokio::fs::read(&args.carrier).await?
+    };
+    let backend = select_backend(sniff::sniff(&carrier), args)?;
+
+    let (payload, name, size): (Box<dyn AsyncRead + Unpin + Send>, _, _) =
+        if is_stdio(&args.payload) {
+            (Box::new(tokio::io::stdin()), "stdin".to_string(), None)
+        } else {
+            let spec = file_spec(&args.payload).await?;
+            let file = tokio::fs::File::open(&args.payload).await?;
+            (Box::new(file), spec.name, Some(spec.size))
+        };
+
+    let (mut writer, output) = create_output(&args.output).await?;
+    let cancel_handle = CancelHandle::new();
+    cancel_on_ctrl_c(cancel_handle.clone());
+
+    let size_hint = Some(carrier.len() as u64);
+    let outcome = hidebox_core::encode_stream(
+        StreamSpec {
+            reader: Cursor::new(carrier),
+            name: args.carrier.to_string_lossy().to_string(),
+            size: size_hint,
+        },
+        StreamSpec {
+            reader: payload,
+            name: args.name.clone().unwrap_or(name),
+            size,
+        },
+        &mut writer,
+        &password,
+        backend,
+        progress_callback(quiet),
+        &cancel_handle,
+    )
+    .await;
+    end_progress(quiet);
+
+    let outcome = outcome?;
+    commit_output(writer, output).await?;
+    Ok((backend, outcome))
+}
+
+async fn encode(args: &EncodeArgs, password: String, quiet: bool) -> Result<Report> {
+    let is_stream = [&args.carrier, &args.payload, &args.output]
+        .iter()
+        .any(|path| is_stdio(path));
+
+    let (backend, outcome) = if is_stream {
+        encode_stream(args, password, quiet).await?
+    } else {
+        encode_file(args, password, quiet).await?
+    };
+
+    let output = &args.output;
+    let efficiency = outcome.report.as_ref().map(|report| report.efficiency());
+    let size_change = outcome.report.as_ref().map(|report| report.size_change);
+    let mut text = format!("encoded {} with {}", output.display(), backend.name());
+    if let Some(efficiency) = efficiency {
+        text.push_str(&format!(", embedding efficiency {efficiency:.2}"));
+    }
+    if let Some(size_change) = size_change.filter(|v| *v != 0) {
+        text.push_str(&format!(", carrier size changed by {size_change:+} bytes"));
+    }
+    if outcome.is_verified {
+        text.push_str(", verified");
+    }
+
+    let mut report = Report::ok(
+        json!({
+            "output": output,
+            "backend": backend.name(),
+            "verified": outcome.is_verified,
+            "efficiency": efficiency,
+            "size_change": size_change,
+        }),
+        text,
+    );
+    report.is_stdout_used = is_stdio(output);
+    Ok(report)
+}
+
+async fn decode_stream<R: AsyncRead + AsyncSeek + Unpin>(
+    src: &mut R,
+    output: &Path,
+    password: &str,
+    quiet: bool,
+) -> Result<hidebox_core::DecodeOutcome> {
+    let (mut writer, output) = create_output(output).await?;
+    let cancel_handle = CancelHandle::new();
+    cancel_on_ctrl_c(cancel_handle.clone());
+
+    let outcome = hidebox_core::decode_stream(
+        src,
+        &mut writer,
+        password,
+        progress_callback(quiet),
+        &cancel_handle,
+    )
+    .await;
+    end_progress(quiet);
+
+    let outcome = outcome?;
+    commit_output(writer, output).await?;
+    Ok(outcome)
+}
+
+async fn decode(args: &DecodeArgs, password: String, quiet: bool) -> Result<Report> {
+    let output = &args.output;
+    let outcome = if is_stdio(&args.carrier) {
+        let mut src = Cursor::new(read_stdin().await?);
+        decode_stream(&mut src, output, &password, quiet).await?
+    } else if is_stdio(output) {
+        let mut src = tokio::fs::File::open(&args.carrier).await?;
+        decode_stream(&mut src, output, &password, quiet).await?
+    } else {
+        let job = hidebox_core::decode::spawn(
+            file_spec(&args.carrier).await?,
+            output.clone(),
+            password,
+            progress_callback(quiet),
+        );
+        cancel_on_ctrl_c(job.cancel_handle());
+        let outcome = job.wait().await;
+        end_progress(quiet);
+        outcome?
+    };
+
+    let mut report = Report::ok(
+        json!({
+            "output": output,
+            "payload_name": outcome.append_name,
+        }),
+        format!("decoded {} to {}", outcome.append_name, output.display()),
+    );
+    report.is_stdout_used = is_stdio(output);
+    Ok(report)
+}
+
+async fn inspect(files: &[PathBuf]) -> Result<Report> {
+    let (mut items, mut lines, mut code) = (vec![], vec![], 0);
+
+    for path in files {
+        match hidebox_core::inspect(path).await? {
+            Some(item) => {
+                let payload_size = item
+                    .payload_size
+                    .map(|size| format!("{size} bytes"))
+                    .unwrap_or("unknown size".to_string());
+                lines.push(format!(
+                    "{}: {} payload, {}",
+                    path.display(),
+                    item.location.name(),
+                    payload_size
+                ));
+                items.push(serde_json::to_value(&item)?);
+            }
+            None => {
+                lines.push(format!("{}: not a carrier", path.display()));
+                items.push(json!({
+                    "path": path,
+                    "location": Value::Null,
+                }));
+                code = EXIT_NOT_A_CARRIER;
+            }
+        }
+    }
+
+    Ok(Report {
+        json: Value::Array(items),
+        text: lines.join("\n"),
+        code,
+        is_stdout_used: false,
+    })
+}
+
+async fn strip(file: &Path) -> Result<Report> {
+    let spec = file_spec(file).await?;
+    let is_stripped = hidebox_core::strip(&spec).await?;
+
+    let text = if is_stripped {
+        format!("stripped {}", file.display())
+    } else {
+        format!("{}: not a carrier", file.display())
+    };
+
+    Ok(Report {
+        json: json!({
+            "path": file,
+            "stripped": is_stripped,
+        }),
+        text,
+        code: if is_stripped { 0 } else { EXIT_NOT_A_CARRIER },
+        is_stdout_used: false,
+    })
+}
+
+fn is_stdout_output(command: &Command) -> bool {
+    match command {
+        Command::Encode(args) => is_stdio(&args.output),
+        Command::Decode(args) => is_stdio(&args.output),
+        _ => false,
+    }
+}
+
+async fn run(cli: &Cli) -> Result<Report> {
+    match &cli.command {
+        Command::Encode(args) => {
+            let password = password::read(&args.password, true)?;
+            encode(args, password, cli.quiet).await
+        }
+        Command::Decode(args) => {
+            let password = password::read(&args.password, false)?;
+            decode(args, password, cli.quiet).await
+        }
+        Command::Inspect { files } => inspect(files).await,
+        Command::Strip { file } => strip(file).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli).await {
+        Ok(report) => {
+            let text = if cli.json {
+                report.json.to_string()
+            } else {
+                report.text
+            };
+
+            if report.is_stdout_used {
+                eprintln!("{text}");
+            } else {
+                println!("{text}");
+            }
+            ExitCode::from(report.code)
+        }
+        Err(e) => {
+            let text = if cli.json {
+                json!({
+                    "error": error_kind(&e),
+                    "message": e.to_string(),
+                })
+                .to_string()
+            } else {
+                format!("error: {e}")
+            };
+
+            // stdout can carry a part of the payload, the error is not mixed into it
+            if cli.json && !is_stdout_output(&cli.command) {
+                println!("{text}");
+            } else {
+                eprintln!("{text}");
+            }
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let e = anyhow::Error::from(HideboxError::WrongPassword);
+        assert_eq!(exit_code(&e), EXIT_WRONG_PASSWORD);
+        assert_eq!(error_kind(&e), "wrong-password");
+
+        let e = anyhow::Error::from(HideboxError::NotACarrier);
+        assert_eq!(exit_code(&e), EXIT_NOT_A_CARRIER);
+
+        let e = anyhow::Error::from(HideboxError::corrupt("bad chunk"));
+        assert_eq!(exit_code(&e), EXIT_CORRUPT);
+
+        let e = anyhow!("other error");
+        assert_eq!(exit_code(&e), EXIT_ERROR);
+        assert_eq!(error_kind(&e), "error");
+    }
+
+    #[test]
+    fn test_cli_parse() {
+        assert!(Cli::try_parse_from(["hidebox-cli", "inspect", "a.png"]).is_ok());
+        assert!(Cli::try_parse_from(["hidebox-cli", "inspect"]).is_err());
+        assert!(Cli::try_parse_from([
+            "hidebox-cli",
+            "encode",
+            "a.png",
+            "b.txt",
+            "-o",
+            "c.png",
+            "--stego",
+            "--xattr"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "hidebox-cli",
+            "encode",
+            "a.jpg",
+            "b.txt",
+            "-o",
+            "c.jpg",
+            "--jpeg-segment",
+            "app0"
+        ])
+        .is_err());
+
+        let cli = Cli::try_parse_from([
+            "hidebox-cli",
+            "encode",
+            "a.jpg",
+            "b.txt",
+            "-o",
+            "c.jpg",
+            "--jpeg-segment",
+            "com",
+        ])
+        .unwrap();
+        let Command::Encode(args) = cli.command else {
+            panic!("not an encode command");
+        };
+        assert_eq!(
+            select_backend(sniff::FileType::Jpeg, &args).unwrap(),
+            Backend::JpegSegment(jpeg::Marker::Com)
+        );
+        assert!(select_backend(sniff::FileType::Png, &args).is_err());
+
+        assert!(Cli::try_parse_from([
+            "hidebox-cli",
+            "decode",
+            "a.png",
+            "-o",
+            "b.txt",
+            "--password-env",
+            "A",
+            "--password-file",
+            "b"
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_output() -> Result<()> {
+        let dir = std::env::temp_dir().join("hidebox-cli-output");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await?;
+
+        // the output file is not touched until the commit
+        let path = dir.join("output.dat");
+        tokio::fs::write(&path, b"old").await?;
+        let (mut writer, output) = create_output(&path).await?;
+        writer.write_all(b"new").await?;
+        drop((writer, output));
+        assert_eq!(tokio::fs::read(&path).await?, b"old");
+
+        let (mut writer, output) = create_output(&path).await?;
+        writer.write_all(b"new").await?;
+        commit_output(writer, output).await?;
+        assert_eq!(tokio::fs::read(&path).await?, b"new");
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        assert!(create_output(Path::new("-")).await?.1.is_none());
+        assert!(is_stdout_output(
+            &Cli::try_parse_from(["hidebox-cli", "decode", "a.png", "-o", "-"])?.command
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use std::io::Read;
+use std::path::PathBuf;
+
+// the password is prompted without echo if no source is given
+#[derive(Args, Debug, Default)]
+#[group(multiple = false)]
+pub struct PasswordArgs {
+    #[arg(
+        long,
+        value_name = "VAR",
+        help = "read the password from the environment variable"
+    )]
+    pub password_env: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "read the password from the first line of the file"
+    )]
+    pub password_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FD",
+        help = "read the password from the first line of the file descriptor"
+    )]
+    pub password_fd: Option<i32>,
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String> {
+    use std::os::unix::io::FromRawFd;
+
+    // the descriptor is owned and closed by the file
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let mut text = String::new();
+    file.read_to_string(&mut text)
+        .with_context(|| format!("read the password from the file descriptor {fd} failed"))?;
+    Ok(text)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<String> {
+    Err(anyhow!(
+        "reading the password from a file descriptor is not supported"
+    ))
+}
+
+fn prompt(is_confirmed: bool) -> Result<String> {
+    let password = rpassword::prompt_password("password: ")?;
+    if is_confirmed && rpassword::prompt_password("confirm password: ")? != password {
+        return Err(anyhow!("the passwords do not match"));
+    }
+    Ok(password)
+}
+
+// the errors never contain the password, a prompted password is asked twice if
+//  is_confirmed is true
+pub fn read(args: &PasswordArgs, is_confirmed: bool) -> Result<String> {
+    let password = if let Some(name) = &args.password_env {
+        std::env::var(name).map_err(|_| anyhow!("the environment variable {name} is not set"))?
+    } else if let Some(path) = &args.password_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read the password file {} failed", path.display()))?;
+        first_line(&text).to_string()
+    } else if let Some(fd) = args.password_fd {
+        first_line(&read_fd(fd)?).to_string()
+    } else {
+        prompt(is_confirmed)?
+    };
+
+    if password.is_empty() {
+        return Err(anyhow!("the password is empty"));
+    }
+
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_password_first_line() {
+        assert_eq!(first_line("123456\n"), "123456");
+        assert_eq!(first_line("123456\r\nnext"), "123456");
+        assert_eq!(first_line(""), "");
+    }
+
+    #[test]
+    fn test_password_read() -> Result<()> {
+        let path = env::temp_dir().join("hidebox-cli-password.txt");
+        std::fs::write(&path, "123456\n")?;
+
+        let args = PasswordArgs {
+            password_file: Some(path),
+            ..Default::default()
+        };
+        assert_eq!(read(&args, false)?, "123456");
+
+        let args = PasswordArgs {
+            password_env: Some("HIDEBOX_CLI_TEST_MISSING_PASSWORD".to_string()),
+            ..Default::default()
+        };
+        assert!(read(&args, false).is_err());
+
+        let empty_path = env::temp_dir().join("hidebox-cli-empty-password.txt");
+        std::fs::write(&empty_path, "\n")?;
+        let args = PasswordArgs {
+            password_file: Some(empty_path),
+            ..Default::default()
+        };
+        assert!(read(&args, false).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    rt.block_on(hidebox_core::fuzz::hide_spec_data(data));
+});
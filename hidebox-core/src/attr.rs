@@ -0,0 +1,135 @@
+use super::error::{HideboxError, Result};
+use super::MAGIC_NUM;
+use std::path::Path;
+
+// the payload is split into user.hidebox.0, user.hidebox.1, ...
+const ATTR_PREFIX: &str = "user.hidebox.";
+
+// ext4 keeps all the attributes of a file in one block which limits the payload to about 4K,
+//  the other file systems like XFS and btrfs allow larger payloads.
+const ATTR_CHUNK_SIZE: usize = 2048;
+
+fn attr_name(index: usize) -> String {
+    format!("{ATTR_PREFIX}{index}")
+}
+
+// store the payload in the extended attributes of the file, the file content is not changed.
+//  The hidebox attributes of the file would be replaced.
+pub fn write(path: &Path, payload: &[u8]) -> Result<()> {
+    strip(path)?;
+
+    for (index, chunk) in payload.chunks(ATTR_CHUNK_SIZE).enumerate() {
+        if let Err(e) = xattr::set(path, attr_name(index), chunk) {
+            let _ = strip(path);
+            return Err(HideboxError::unsupported(format!(
+                "write extended attribute failed, the file system may not support user attributes or limit their size: {e}"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Option<Vec<u8>> {
+    let mut payload = vec![];
+    for index in 0.. {
+        match xattr::get(path, attr_name(index)) {
+            Ok(Some(chunk)) => payload.extend(chunk),
+            _ => break,
+        }
+    }
+
+    if payload.len() > MAGIC_NUM.len() * 2
+        && payload.starts_with(MAGIC_NUM.as_bytes())
+        && payload.ends_with(MAGIC_NUM.as_bytes())
+    {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+// remove the hidebox attributes, return false if the file does not have them
+pub fn strip(path: &Path) -> Result<bool> {
+    let names = match xattr::list(path) {
+        Ok(v) => v
+            .filter(|name| name.to_string_lossy().starts_with(ATTR_PREFIX))
+            .collect::<Vec<_>>(),
+
+        // the file system does not support the extended attributes
+        Err(_) => return Ok(false),
+    };
+
+    for name in names.iter() {
+        xattr::remove(path, name)?;
+    }
+
+    Ok(!names.is_empty())
+}
+
+// copy the attributes of src which dst does not have, the hidebox attributes are not copied
+//  if dst has its own payload. The file system of dst may not support them, so the
+//  failures are ignored.
+pub fn copy(src: &Path, dst: &Path) {
+    let Ok(names) = xattr::list(src) else {
+        return;
+    };
+
+    let dst_names = xattr::list(dst)
+        .map(|v| v.collect::<Vec<_>>())
+        .unwrap_or_default();
+    let has_payload = dst_names
+        .iter()
+        .any(|name| name.to_string_lossy().starts_with(ATTR_PREFIX));
+
+    for name in names {
+        if dst_names.contains(&name)
+            || (has_payload && name.to_string_lossy().starts_with(ATTR_PREFIX))
+        {
+            continue;
+        }
+
+        if let Ok(Some(value)) = xattr::get(src, &name) {
+            let _ = xattr::set(dst, &name, &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use anyhow::Result;
+    use std::env;
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_attr_write_read_strip() -> Result<()> {
+        let path = env::temp_dir().join("hidebox-attr.dat");
+        std::fs::write(&path, b"carrier")?;
+        assert!(read(&path).is_none());
+        assert!(!strip(&path)?);
+
+        // the payload is split into 2 attributes
+        let payload = payload(3000);
+        write(&path, &payload)?;
+        assert!(xattr::get(&path, attr_name(1))?.is_some());
+        assert_eq!(read(&path).unwrap(), payload);
+        assert_eq!(std::fs::read(&path)?, b"carrier");
+
+        // the old attributes are replaced
+        let payload = super::tests::payload(10);
+        write(&path, &payload)?;
+        assert!(xattr::get(&path, attr_name(1))?.is_none());
+        assert_eq!(read(&path).unwrap(), payload);
+
+        assert!(strip(&path)?);
+        assert!(read(&path).is_none());
+        assert_eq!(std::fs::read(&path)?, b"carrier");
+        Ok(())
+    }
+}
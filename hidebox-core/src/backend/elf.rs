@@ -0,0 +1,506 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+const SHN_LORESERVE: usize = 0xff00;
+
+const SECTION_NAME: &[u8] = b".hidebox";
+const SHT_ALIGN: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+struct Elf {
+    is_64: bool,
+    is_le: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Section {
+    name: u32,
+    kind: u32,
+    offset: usize,
+    size: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Header {
+    elf: Elf,
+    header_size: usize,
+    ph_offset: usize,
+    ph_entry_size: usize,
+    ph_num: usize,
+    sh_offset: usize,
+    sh_entry_size: usize,
+    shstrndx: usize,
+    sections: Vec<Section>,
+}
+
+impl Elf {
+    fn read(&self, data: &[u8], pos: usize, size: usize) -> Result<u64> {
+        let bytes = match data.get(pos..pos.saturating_add(size)) {
+            Some(v) => v,
+            None => return Err(anyhow!("unexpected end of elf file")),
+        };
+
+        let mut value = 0_u64;
+        for i in 0..size {
+            let b = if self.is_le {
+                bytes[size - 1 - i]
+            } else {
+                bytes[i]
+            };
+            value = (value << 8) | b as u64;
+        }
+        Ok(value)
+    }
+
+    fn write(&self, data: &mut [u8], pos: usize, size: usize, value: u64) {
+        for i in 0..size {
+            let b = (value >> (i * 8)) as u8;
+            if self.is_le {
+                data[pos + i] = b;
+            } else {
+                data[pos + size - 1 - i] = b;
+            }
+        }
+    }
+
+    fn u16(&self, data: &[u8], pos: usize) -> Result<usize> {
+        Ok(self.read(data, pos, 2)? as usize)
+    }
+
+    fn u32(&self, data: &[u8], pos: usize) -> Result<u32> {
+        Ok(self.read(data, pos, 4)? as u32)
+    }
+
+    // the address and offset fields are 4 bytes in ELF32 and 8 bytes in ELF64
+    fn word(&self, data: &[u8], pos: usize) -> Result<usize> {
+        Ok(self.read(data, pos, self.word_size())? as usize)
+    }
+
+    fn word_size(&self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    // positions of (e_phoff, e_shoff, e_ehsize) in the ELF header
+    fn header_pos(&self) -> (usize, usize, usize) {
+        if self.is_64 {
+            (0x20, 0x28, 0x34)
+        } else {
+            (0x1C, 0x20, 0x28)
+        }
+    }
+
+    // positions of (p_offset, p_filesz) in a program header
+    fn program_pos(&self) -> (usize, usize) {
+        if self.is_64 {
+            (8, 32)
+        } else {
+            (4, 16)
+        }
+    }
+
+    // positions of (sh_offset, sh_size) in a section header
+    fn section_pos(&self) -> (usize, usize) {
+        if self.is_64 {
+            (24, 32)
+        } else {
+            (16, 20)
+        }
+    }
+}
+
+// LAYOUT: ELF header + [program headers] + sections + section header table;
+fn parse_header(data: &[u8]) -> Result<Header> {
+    if data.len() < 0x34 || !data.starts_with(ELF_MAGIC) {
+        return Err(anyhow!("not an elf file"));
+    }
+
+    let is_64 = match data[4] {
+        ELFCLASS32 => false,
+        ELFCLASS64 => true,
+        _ => return Err(anyhow!("unknown elf class")),
+    };
+
+    let is_le = match data[5] {
+        ELFDATA2LSB => true,
+        ELFDATA2MSB => false,
+        _ => return Err(anyhow!("unknown elf data encoding")),
+    };
+
+    let elf = Elf { is_64, is_le };
+    let (phoff_pos, shoff_pos, ehsize_pos) = elf.header_pos();
+    let header_size = elf.u16(data, ehsize_pos)?;
+    let ph_offset = elf.word(data, phoff_pos)?;
+    let ph_entry_size = elf.u16(data, ehsize_pos + 2)?;
+    let ph_num = elf.u16(data, ehsize_pos + 4)?;
+    let sh_offset = elf.word(data, shoff_pos)?;
+    let sh_entry_size = elf.u16(data, ehsize_pos + 6)?;
+    let sh_num = elf.u16(data, ehsize_pos + 8)?;
+    let shstrndx = elf.u16(data, ehsize_pos + 10)?;
+
+    // the extended section numbering keeps the real values in the first section header
+    if sh_num == 0
+        || shstrndx >= sh_num
+        || sh_num >= SHN_LORESERVE
+        || sh_entry_size < 16 + elf.word_size() * 6
+    {
+        return Err(anyhow!("unsupported elf section header table"));
+    }
+
+    if table_end(sh_offset, sh_num, sh_entry_size)? > data.len() {
+        return Err(anyhow!("invalid elf section header table"));
+    }
+
+    let (offset_pos, size_pos) = elf.section_pos();
+    let mut sections = Vec::with_capacity(sh_num);
+    for i in 0..sh_num {
+        let pos = sh_offset + i * sh_entry_size;
+        let section = Section {
+            name: elf.u32(data, pos)?,
+            kind: elf.u32(data, pos + 4)?,
+            offset: elf.word(data, pos + offset_pos)?,
+            size: elf.word(data, pos + size_pos)?,
+        };
+
+        if section.kind != SHT_NOBITS && section.offset.saturating_add(section.size) > data.len() {
+            return Err(anyhow!("invalid elf section {i}"));
+        }
+
+        sections.push(section);
+    }
+
+    // the names are read from the file, a NOBITS section has no bytes in the file
+    if sections[shstrndx].kind == SHT_NOBITS {
+        return Err(anyhow!("invalid elf section name table"));
+    }
+
+    Ok(Header {
+        elf,
+        header_size,
+        ph_offset,
+        ph_entry_size,
+        ph_num,
+        sh_offset,
+        sh_entry_size,
+        shstrndx,
+        sections,
+    })
+}
+
+// the end of a table of `num` entries, the sizes are read from the file and may overflow
+fn table_end(offset: usize, num: usize, entry_size: usize) -> Result<usize> {
+    num.checked_mul(entry_size)
+        .and_then(|v| v.checked_add(offset))
+        .ok_or_else(|| anyhow!("invalid elf table size"))
+}
+
+impl Header {
+    // a NOBITS section has no data in the file
+    fn section_data<'a>(&self, data: &'a [u8], index: usize) -> &'a [u8] {
+        let section = &self.sections[index];
+        if section.kind == SHT_NOBITS {
+            return &[];
+        }
+
+        data.get(section.offset..section.offset.saturating_add(section.size))
+            .unwrap_or_default()
+    }
+
+    fn section_name<'a>(&self, data: &'a [u8], index: usize) -> &'a [u8] {
+        let names = self.section_data(data, self.shstrndx);
+        let name = names
+            .get(self.sections[index].name as usize..)
+            .unwrap_or_default();
+        name.split(|b| *b == 0).next().unwrap_or_default()
+    }
+
+    // a NOBITS hidebox section is never written by the embedding
+    fn find_section(&self, data: &[u8]) -> Result<Option<usize>> {
+        match (0..self.sections.len()).find(|i| self.section_name(data, *i) == SECTION_NAME) {
+            Some(i) if self.sections[i].kind == SHT_NOBITS => {
+                Err(anyhow!("invalid elf hidebox section"))
+            }
+            index => Ok(index),
+        }
+    }
+
+    // the end of the bytes which are referenced by the ELF header, the program
+    // headers and the sections, except the ones which would be written again
+    fn content_end(&self, data: &[u8], skip: &[usize]) -> Result<usize> {
+        let elf = self.elf;
+        let ph_end = table_end(self.ph_offset, self.ph_num, self.ph_entry_size)?;
+        if self.ph_num > 0 && ph_end > data.len() {
+            return Err(anyhow!("invalid elf program header table"));
+        }
+
+        let mut end = if self.ph_num > 0 {
+            usize::max(self.header_size, ph_end)
+        } else {
+            self.header_size
+        };
+
+        let (offset_pos, filesz_pos) = elf.program_pos();
+        for i in 0..self.ph_num {
+            let pos = self.ph_offset + i * self.ph_entry_size;
+            let segment_end = elf
+                .word(data, pos + offset_pos)?
+                .checked_add(elf.word(data, pos + filesz_pos)?)
+                .ok_or_else(|| anyhow!("invalid elf program header {i}"))?;
+            end = usize::max(end, segment_end);
+        }
+
+        // the sections in the file are checked to be in bounds by the parsing
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.kind != SHT_NOBITS && !skip.contains(&i) {
+                end = usize::max(end, section.offset + section.size);
+            }
+        }
+
+        if end > data.len() {
+            return Err(anyhow!("invalid elf content size"));
+        }
+
+        Ok(end)
+    }
+}
+
+fn is_payload(data: &[u8]) -> bool {
+    data.len() > MAGIC_NUM.len() * 2
+        && data.starts_with(MAGIC_NUM.as_bytes())
+        && data.ends_with(MAGIC_NUM.as_bytes())
+}
+
+// rebuild the end of the file:
+//  content + payload section + section name table + section header table;
+// the payload section is not in any segment, so it is not loaded when the binary runs.
+// The hidebox section of the carrier would be replaced.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let header = parse_header(carrier)?;
+    let elf = header.elf;
+    let index = header.find_section(carrier)?;
+
+    let mut names = header.section_data(carrier, header.shstrndx).to_vec();
+    let name = match index {
+        Some(i) => header.sections[i].name,
+        None => {
+            let name = names.len() as u32;
+            names.extend_from_slice(SECTION_NAME);
+            names.push(0);
+            name
+        }
+    };
+
+    let skip = [Some(header.shstrndx), index]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    let content_end = header.content_end(carrier, &skip)?;
+
+    let mut output =
+        Vec::with_capacity(content_end + payload.len() + names.len() + carrier.len() / 8);
+    output.extend_from_slice(&carrier[..content_end]);
+
+    let payload_offset = output.len();
+    output.extend_from_slice(payload);
+
+    let names_offset = output.len();
+    output.extend_from_slice(&names);
+    output.resize(output.len().next_multiple_of(SHT_ALIGN), 0);
+
+    let sh_offset = output.len();
+    let sh_table_size = header.sections.len() * header.sh_entry_size;
+    output.extend_from_slice(&carrier[header.sh_offset..header.sh_offset + sh_table_size]);
+
+    let (offset_pos, size_pos) = elf.section_pos();
+    let word_size = elf.word_size();
+    let set_section = |output: &mut Vec<u8>, i: usize, offset: usize, size: usize| {
+        let pos = sh_offset + i * header.sh_entry_size;
+        elf.write(output, pos + offset_pos, word_size, offset as u64);
+        elf.write(output, pos + size_pos, word_size, size as u64);
+    };
+
+    set_section(&mut output, header.shstrndx, names_offset, names.len());
+
+    let sh_num = match index {
+        Some(i) => {
+            set_section(&mut output, i, payload_offset, payload.len());
+            header.sections.len()
+        }
+        None => {
+            // name + type + flags + addr + offset + size + link + info + addralign + entsize
+            let mut section = vec![0; header.sh_entry_size];
+            elf.write(&mut section, 0, 4, name as u64);
+            elf.write(&mut section, 4, 4, SHT_PROGBITS as u64);
+            elf.write(&mut section, 16 + word_size * 4, word_size, 1);
+            output.extend_from_slice(&section);
+
+            let i = header.sections.len();
+            set_section(&mut output, i, payload_offset, payload.len());
+            i + 1
+        }
+    };
+
+    let (_, shoff_pos, ehsize_pos) = elf.header_pos();
+    elf.write(&mut output, shoff_pos, word_size, sh_offset as u64);
+    elf.write(&mut output, ehsize_pos + 8, 2, sh_num as u64);
+
+    Ok(output)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    let header = parse_header(data).ok()?;
+    let index = header.find_section(data).ok()??;
+    let payload = header.section_data(data, index);
+
+    if is_payload(payload) {
+        Some(payload.to_vec())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.elf"))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_elf_parse_header() -> Result<()> {
+        let carrier = carrier()?;
+        let header = parse_header(&carrier)?;
+        assert!(header.elf.is_64 && header.elf.is_le);
+        assert_eq!(header.section_name(&carrier, header.shstrndx), b".shstrtab");
+        assert!(header.find_section(&carrier)?.is_none());
+        assert!(parse_header(b"not an elf file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_elf_embed_extract() -> Result<()> {
+        let carrier = carrier()?;
+        assert!(extract(&carrier).is_none());
+
+        let payload = payload(10000);
+        let output = embed(&carrier, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+
+        let src_header = parse_header(&carrier)?;
+        let header = parse_header(&output)?;
+        assert_eq!(header.sections.len(), src_header.sections.len() + 1);
+
+        for i in 0..src_header.sections.len() {
+            assert_eq!(
+                header.section_name(&output, i),
+                src_header.section_name(&carrier, i)
+            );
+            if src_header.sections[i].kind != SHT_NOBITS && i != src_header.shstrndx {
+                assert_eq!(
+                    header.section_data(&output, i),
+                    src_header.section_data(&carrier, i)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_elf_embed_replace() -> Result<()> {
+        let carrier = carrier()?;
+        let output = embed(&carrier, &payload(100))?;
+        let payload = payload(50);
+        let output = embed(&output, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+        assert_eq!(
+            parse_header(&output)?.sections.len(),
+            parse_header(&carrier)?.sections.len() + 1
+        );
+        Ok(())
+    }
+
+    // set the type and the offset of the section header
+    fn set_section(data: &mut [u8], index: usize, kind: u32, offset: u64) -> Result<()> {
+        let header = parse_header(data)?;
+        let pos = header.sh_offset + index * header.sh_entry_size;
+        header.elf.write(data, pos + 4, 4, kind as u64);
+        header
+            .elf
+            .write(data, pos + header.elf.section_pos().0, 8, offset);
+        Ok(())
+    }
+
+    // the section name table is a NOBITS section whose offset is out of the file
+    pub(crate) fn hostile_carrier() -> Result<Vec<u8>> {
+        let mut data = carrier()?;
+        let shstrndx = parse_header(&data)?.shstrndx;
+        set_section(&mut data, shstrndx, SHT_NOBITS, 0x100000)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_elf_malformed() -> Result<()> {
+        let carrier = carrier()?;
+
+        let data = hostile_carrier()?;
+        assert!(parse_header(&data).is_err());
+        assert!(extract(&data).is_none());
+        assert!(embed(&data, &payload(10)).is_err());
+
+        let mut data = embed(&carrier, &payload(100))?;
+        let index = parse_header(&data)?.find_section(&data)?.unwrap();
+        set_section(&mut data, index, SHT_NOBITS, 0x100000)?;
+        assert!(extract(&data).is_none());
+        assert!(embed(&data, &payload(10)).is_err());
+
+        let mut data = carrier.clone();
+        let (_, shoff_pos, _) = parse_header(&data)?.elf.header_pos();
+        data[shoff_pos..shoff_pos + 8].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(parse_header(&data).is_err());
+        assert!(extract(&data).is_none());
+
+        let mut data = carrier.clone();
+        let (phoff_pos, _, _) = parse_header(&data)?.elf.header_pos();
+        data[phoff_pos..phoff_pos + 8].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(embed(&data, &payload(10)).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_elf_embed_run() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let output = embed(&carrier()?, &payload(10000))?;
+        let path = env::temp_dir().join("hidebox-dst-run.elf");
+        std::fs::write(&path, output)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+
+        let result = std::process::Command::new(&path).output()?;
+        assert!(result.status.success());
+        assert_eq!(result.stdout, b"hello hidebox\n");
+        Ok(())
+    }
+}
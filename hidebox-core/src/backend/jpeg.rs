@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Result};
+
+const MARKER_PREFIX: u8 = 0xFF;
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const COM: u8 = 0xFE;
+const APP0: u8 = 0xE0;
+const APP11: u8 = 0xEB;
+const APP12: u8 = 0xEC;
+const APP13: u8 = 0xED;
+const APP14: u8 = 0xEE;
+const APP15: u8 = 0xEF;
+const TEM: u8 = 0x01;
+const SOF0: u8 = 0xC0;
+const DHT: u8 = 0xC4;
+const JPG: u8 = 0xC8;
+const DAC: u8 = 0xCC;
+const SOF15: u8 = 0xCF;
+const RST0: u8 = 0xD0;
+const RST7: u8 = 0xD7;
+
+const IDENTIFIER: &[u8] = b"HIDEBOX\0";
+const SEGMENT_LEN_SIZE: usize = 2;
+
+// the segment length field is 16 bits and includes itself
+const MAX_SEGMENT_DATA_SIZE: usize = u16::MAX as usize - SEGMENT_LEN_SIZE - IDENTIFIER.len();
+
+// the segment which carries the payload, APP15 is the default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Marker {
+    App11,
+    App12,
+    App13,
+    App14,
+    #[default]
+    App15,
+    Com,
+}
+
+impl Marker {
+    pub const ALL: [Marker; 6] = [
+        Marker::App11,
+        Marker::App12,
+        Marker::App13,
+        Marker::App14,
+        Marker::App15,
+        Marker::Com,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Marker::App11 => "APP11",
+            Marker::App12 => "APP12",
+            Marker::App13 => "APP13",
+            Marker::App14 => "APP14",
+            Marker::App15 => "APP15",
+            Marker::Com => "COM",
+        }
+    }
+
+    // the name is case insensitive, e.g. "app11" or "COM"
+    pub fn from_name(name: &str) -> Option<Marker> {
+        Self::ALL
+            .into_iter()
+            .find(|marker| marker.name().eq_ignore_ascii_case(name))
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Marker::App11 => APP11,
+            Marker::App12 => APP12,
+            Marker::App13 => APP13,
+            Marker::App14 => APP14,
+            Marker::App15 => APP15,
+            Marker::Com => COM,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    marker: u8,
+    start: usize,
+    end: usize,
+}
+
+impl Segment {
+    fn is_hidebox(&self, data: &[u8]) -> bool {
+        is_payload_marker(self.marker) && self.data(data).starts_with(IDENTIFIER)
+    }
+
+    fn data<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let start = usize::min(self.start + 2 + SEGMENT_LEN_SIZE, self.end);
+        &data[start..self.end]
+    }
+}
+
+// the application segments APP11 to APP15 and the comment segment can carry the payload,
+//  the readers skip them unless they know the identifier
+fn is_payload_marker(marker: u8) -> bool {
+    (APP11..=APP15).contains(&marker) || marker == COM
+}
+
+// LAYOUT: SOI + segments + SOS + entropy coded data + EOI;
+//  return the segments before the first scan and the position of the SOS marker
+fn parse_header(data: &[u8]) -> Result<(Vec<Segment>, usize)> {
+    if data.len() < 4 || data[0] != MARKER_PREFIX || data[1] != SOI {
+        return Err(anyhow!("not a jpeg file"));
+    }
+
+    let mut segments = vec![];
+    let mut pos = 2;
+
+    loop {
+        if pos + 1 >= data.len() {
+            return Err(anyhow!("unexpected end of jpeg header"));
+        }
+
+        if data[pos] != MARKER_PREFIX {
+            return Err(anyhow!("invalid jpeg marker at {pos}"));
+        }
+
+        // skip fill bytes
+        if data[pos + 1] == MARKER_PREFIX {
+            pos += 1;
+            continue;
+        }
+
+        let marker = data[pos + 1];
+        if marker == SOS || marker == EOI {
+            return Ok((segments, pos));
+        }
+
+        if marker == TEM || (RST0..=RST7).contains(&marker) {
+            segments.push(Segment {
+                marker,
+                start: pos,
+                end: pos + 2,
+            });
+            pos += 2;
+            continue;
+        }
+
+        if pos + 2 + SEGMENT_LEN_SIZE > data.len() {
+            return Err(anyhow!("unexpected end of jpeg segment"));
+        }
+
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if len < SEGMENT_LEN_SIZE || pos + 2 + len > data.len() {
+            return Err(anyhow!("invalid jpeg segment length at {pos}"));
+        }
+
+        segments.push(Segment {
+            marker,
+            start: pos,
+            end: pos + 2 + len,
+        });
+        pos += 2 + len;
+    }
+}
+
+// LAYOUT: 0xFF + marker + len(2 bytes) + IDENTIFIER + data;
+fn make_segments(payload: &[u8], marker: u8) -> Vec<u8> {
+    let mut segments = vec![];
+    for chunk in payload.chunks(MAX_SEGMENT_DATA_SIZE) {
+        let len = (SEGMENT_LEN_SIZE + IDENTIFIER.len() + chunk.len()) as u16;
+        segments.extend_from_slice(&[MARKER_PREFIX, marker]);
+        segments.extend_from_slice(&len.to_be_bytes());
+        segments.extend_from_slice(IDENTIFIER);
+        segments.extend_from_slice(chunk);
+    }
+    segments
+}
+
+// insert the payload segments of the marker after the leading APPn and COM segments,
+// so that JFIF and Exif segments are still the first segments of the file.
+// The hidebox segments of the carrier would be replaced, whatever their marker is.
+pub fn embed(carrier: &[u8], payload: &[u8], marker: Marker) -> Result<Vec<u8>> {
+    let (segments, header_end) = parse_header(carrier)?;
+
+    let mut output = Vec::with_capacity(carrier.len() + payload.len() * 2);
+    output.extend_from_slice(&carrier[..2]);
+
+    let mut is_inserted = false;
+    for segment in segments.iter() {
+        if segment.is_hidebox(carrier) {
+            continue;
+        }
+
+        let is_leading = (APP0..=APP15).contains(&segment.marker) || segment.marker == COM;
+        if !is_inserted && !is_leading {
+            output.append(&mut make_segments(payload, marker.code()));
+            is_inserted = true;
+        }
+
+        output.extend_from_slice(&carrier[segment.start..segment.end]);
+    }
+
+    if !is_inserted {
+        output.append(&mut make_segments(payload, marker.code()));
+    }
+
+    output.extend_from_slice(&carrier[header_end..]);
+    Ok(output)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    let (segments, _) = parse_header(data).ok()?;
+
+    let mut payload = vec![];
+    for segment in segments.iter().filter(|s| s.is_hidebox(data)) {
+        payload.extend_from_slice(&segment.data(data)[IDENTIFIER.len()..]);
+    }
+
+    if payload.is_empty() {
+        None
+    } else {
+        Some(payload)
+    }
+}
+
+// the marker of the segment which has no length field
+fn is_standalone(marker: u8) -> bool {
+    marker == SOI || marker == EOI || marker == TEM || (RST0..=RST7).contains(&marker)
+}
+
+// the frame header of the baseline, progressive and lossless JPEG files
+fn is_sof(marker: u8) -> bool {
+    (SOF0..=SOF15).contains(&marker) && !matches!(marker, DHT | JPG | DAC)
+}
+
+// walk the segments and the entropy coded data of every scan until EOI,
+//  the data after EOI is ignored by the readers.
+pub fn verify(data: &[u8]) -> Result<()> {
+    let (segments, mut pos) = parse_header(data)?;
+    if !segments.iter().any(|s| is_sof(s.marker)) {
+        return Err(anyhow!("jpeg frame header is not found"));
+    }
+
+    let mut has_scan = false;
+    loop {
+        let marker = match data.get(pos + 1) {
+            Some(v) if data[pos] == MARKER_PREFIX => *v,
+            _ => return Err(anyhow!("invalid jpeg marker at {pos}")),
+        };
+
+        if marker == MARKER_PREFIX {
+            pos += 1;
+            continue;
+        }
+
+        if marker == EOI {
+            return if has_scan {
+                Ok(())
+            } else {
+                Err(anyhow!("jpeg scan is not found"))
+            };
+        }
+
+        if is_standalone(marker) {
+            pos += 2;
+            continue;
+        }
+
+        let len = match data.get(pos + 2..pos + 2 + SEGMENT_LEN_SIZE) {
+            Some(v) => u16::from_be_bytes([v[0], v[1]]) as usize,
+            None => return Err(anyhow!("unexpected end of jpeg segment")),
+        };
+        if len < SEGMENT_LEN_SIZE || pos + 2 + len > data.len() {
+            return Err(anyhow!("invalid jpeg segment length at {pos}"));
+        }
+        pos += 2 + len;
+
+        if marker != SOS {
+            continue;
+        }
+
+        // the entropy coded data ends at the first marker which is not a stuffed byte
+        //  or a restart marker
+        has_scan = true;
+        loop {
+            match data.get(pos..pos + 2) {
+                Some([MARKER_PREFIX, next]) if *next == 0 || (RST0..=RST7).contains(next) => {
+                    pos += 2
+                }
+                Some([MARKER_PREFIX, _]) => break,
+                Some(_) => pos += 1,
+                None => return Err(anyhow!("unexpected end of jpeg entropy coded data")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.jpg"))?)
+    }
+
+    #[test]
+    fn test_jpeg_parse_header() -> Result<()> {
+        let carrier = carrier()?;
+        let (segments, header_end) = parse_header(&carrier)?;
+        assert_eq!(segments[0].marker, APP0);
+        assert_eq!(carrier[header_end + 1], SOS);
+        assert!(parse_header(b"not a jpeg file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jpeg_embed_extract() -> Result<()> {
+        let carrier = carrier()?;
+        assert!(extract(&carrier).is_none());
+
+        let payload = util::str::random_string(MAX_SEGMENT_DATA_SIZE * 2 + 100);
+        let output = embed(&carrier, payload.as_bytes(), Marker::App15)?;
+        assert_eq!(extract(&output).unwrap(), payload.as_bytes());
+
+        let (segments, _) = parse_header(&output)?;
+        assert_eq!(segments[0].marker, APP0);
+        assert_eq!(segments.iter().filter(|s| s.is_hidebox(&output)).count(), 3);
+        assert_eq!(&output[output.len() - 2..], &[MARKER_PREFIX, EOI]);
+
+        let image = jpeg_decoder::Decoder::new(output.as_slice()).decode()?;
+        let src_image = jpeg_decoder::Decoder::new(carrier.as_slice()).decode()?;
+        assert_eq!(image, src_image);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jpeg_embed_replace() -> Result<()> {
+        let carrier = carrier()?;
+        let output = embed(&carrier, b"first payload", Marker::App15)?;
+        let output = embed(&output, b"second payload", Marker::App15)?;
+        assert_eq!(extract(&output).unwrap(), b"second payload");
+        Ok(())
+    }
+
+    #[test]
+    fn test_jpeg_embed_marker() -> Result<()> {
+        let carrier = carrier()?;
+        let src_image = jpeg_decoder::Decoder::new(carrier.as_slice()).decode()?;
+
+        for marker in Marker::ALL {
+            let output = embed(&carrier, b"payload", marker)?;
+            assert_eq!(extract(&output).unwrap(), b"payload");
+
+            let (segments, _) = parse_header(&output)?;
+            let hidebox = segments.iter().find(|s| s.is_hidebox(&output)).unwrap();
+            assert_eq!(hidebox.marker, marker.code());
+
+            let image = jpeg_decoder::Decoder::new(output.as_slice()).decode()?;
+            assert_eq!(image, src_image);
+        }
+
+        // the payload of another marker is replaced
+        let output = embed(&carrier, b"first payload", Marker::Com)?;
+        let output = embed(&output, b"second payload", Marker::App11)?;
+        assert_eq!(extract(&output).unwrap(), b"second payload");
+
+        assert_eq!(Marker::from_name("app12"), Some(Marker::App12));
+        assert_eq!(Marker::from_name("COM"), Some(Marker::Com));
+        assert_eq!(Marker::from_name("APP0"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const COMMENT_START: &[u8] = b"<!--hidebox:";
+const COMMENT_END: &[u8] = b"-->";
+const END_TAG_START: &[u8] = b"</";
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn find(data: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(pattern.len())
+        .position(|w| w == pattern)
+        .map(|pos| pos + from)
+}
+
+fn rfind(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len()).rposition(|w| w == pattern)
+}
+
+// the SVG, HTML and XML documents start with a tag after the optional BOM and white spaces
+fn is_markup(data: &[u8]) -> bool {
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+// the start and the end of the hidebox comment
+fn find_comment(data: &[u8]) -> Option<(usize, usize)> {
+    let start = find(data, COMMENT_START, 0)?;
+    let end = find(data, COMMENT_END, start + COMMENT_START.len())?;
+    Some((start, end + COMMENT_END.len()))
+}
+
+// LAYOUT: <!--hidebox:base64(payload)-->;
+//  the base64 text never contains "--", so the comment is always well-formed.
+fn make_comment(payload: &[u8]) -> Vec<u8> {
+    [
+        COMMENT_START,
+        STANDARD.encode(payload).as_bytes(),
+        COMMENT_END,
+    ]
+    .concat()
+}
+
+// put the payload comment before the end tag of the root element,
+//  the comments are not rendered and the document stays well-formed.
+//  The hidebox comment of the carrier would be replaced.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    if !is_markup(carrier) {
+        return Err(anyhow!("not a svg, html or xml document"));
+    }
+
+    let carrier = match find_comment(carrier) {
+        Some((start, end)) => [&carrier[..start], &carrier[end..]].concat(),
+        None => carrier.to_vec(),
+    };
+
+    let Some(pos) = rfind(&carrier, END_TAG_START) else {
+        return Err(anyhow!("the end tag of the root element is missing"));
+    };
+
+    let comment = make_comment(payload);
+    let mut output = Vec::with_capacity(carrier.len() + comment.len());
+    output.extend_from_slice(&carrier[..pos]);
+    output.extend_from_slice(&comment);
+    output.extend_from_slice(&carrier[pos..]);
+    Ok(output)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    if !is_markup(data) {
+        return None;
+    }
+
+    let (start, end) = find_comment(data)?;
+    let text = &data[start + COMMENT_START.len()..end - COMMENT_END.len()];
+    let payload = STANDARD.decode(text).ok()?;
+
+    if payload.len() > MAGIC_NUM.len() * 2
+        && payload.starts_with(MAGIC_NUM.as_bytes())
+        && payload.ends_with(MAGIC_NUM.as_bytes())
+    {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier(name: &str) -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata").join(name))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    // the elements, attributes and texts of the document without the comments
+    fn nodes(data: &[u8]) -> Result<Vec<String>> {
+        let text = std::str::from_utf8(data)?;
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = roxmltree::Document::parse_with_options(text, options)?;
+        Ok(doc
+            .descendants()
+            .filter(|n| !n.is_comment())
+            .map(|n| {
+                let attrs = n
+                    .attributes()
+                    .map(|a| format!("{}={}", a.name(), a.value()))
+                    .collect::<Vec<_>>();
+                format!("{:?} {:?} {:?}", n.tag_name(), attrs, n.text())
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_markup_embed_extract() -> Result<()> {
+        for name in ["src.svg", "src.html"] {
+            let carrier = carrier(name)?;
+            assert!(extract(&carrier).is_none());
+
+            let large_payload = payload(10000);
+            let output = embed(&carrier, &large_payload)?;
+            assert_eq!(extract(&output).unwrap(), large_payload);
+            assert!(!output.ends_with(MAGIC_NUM.as_bytes()));
+
+            // the document is well-formed and has the same content
+            assert_eq!(nodes(&output)?, nodes(&carrier)?);
+
+            // the payload comment is replaced
+            let payload = payload(10);
+            let output = embed(&output, &payload)?;
+            assert_eq!(extract(&output).unwrap(), payload);
+            assert_eq!(output.len(), carrier.len() + make_comment(&payload).len());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_markup_invalid() {
+        assert!(embed(b"not a markup document", b"data").is_err());
+        assert!(embed(b"<svg/>", b"data").is_err());
+        assert!(extract(b"<svg><!--hidebox:not base64--></svg>").is_none());
+    }
+}
@@ -0,0 +1,37 @@
+use super::Backend;
+use anyhow::{anyhow, Result};
+
+pub mod elf;
+pub mod jpeg;
+pub mod markup;
+pub mod mp4;
+pub mod pdf;
+pub mod riff;
+pub mod zip;
+
+// store the payload inside the carrier and keep the carrier format valid
+pub fn embed(backend: Backend, carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    match backend {
+        Backend::JpegSegment(marker) => jpeg::embed(carrier, payload, marker),
+        Backend::Zip => zip::embed(carrier, payload),
+        Backend::Pdf => pdf::embed(carrier, payload),
+        Backend::Mp4 => mp4::embed(carrier, payload),
+        Backend::Elf => elf::embed(carrier, payload),
+        Backend::Riff => riff::embed(carrier, payload),
+        Backend::Markup => markup::embed(carrier, payload),
+        Backend::Trailer | Backend::Xattr | Backend::Lsb | Backend::Dct | Backend::Pcm => {
+            Err(anyhow!("{backend:?} is not an embedded backend"))
+        }
+    }
+}
+
+// find the payload written by any embedded backend
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    jpeg::extract(data)
+        .or_else(|| zip::extract(data))
+        .or_else(|| pdf::extract(data))
+        .or_else(|| mp4::extract(data))
+        .or_else(|| elf::extract(data))
+        .or_else(|| riff::extract(data))
+        .or_else(|| markup::extract(data))
+}
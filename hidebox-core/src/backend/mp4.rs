@@ -0,0 +1,344 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+
+const BOX_HEADER_SIZE: usize = 8;
+const LARGE_BOX_HEADER_SIZE: usize = 16;
+const FULL_BOX_HEADER_SIZE: usize = 4;
+
+// the boxes which contain the boxes with absolute file offsets
+const CONTAINER_BOXES: [&[u8; 4]; 8] = [
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"moof", b"traf", b"edts",
+];
+
+// tfhd flag: base-data-offset-present
+const TFHD_BASE_DATA_OFFSET: u32 = 0x01;
+
+#[derive(Clone, Debug)]
+struct BoxHeader {
+    kind: [u8; 4],
+    start: usize,
+    content: usize,
+    end: usize,
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32> {
+    match data.get(pos..pos.saturating_add(4)) {
+        Some(v) => Ok(u32::from_be_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of mp4 box")),
+    }
+}
+
+fn u64_at(data: &[u8], pos: usize) -> Result<u64> {
+    match data.get(pos..pos.saturating_add(8)) {
+        Some(v) => Ok(u64::from_be_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of mp4 box")),
+    }
+}
+
+// LAYOUT: size(4 bytes) + type(4 bytes) + [largesize(8 bytes)] + content;
+//  size == 1 means the largesize is used, size == 0 means the box extends to the end.
+fn parse_boxes(data: &[u8], start: usize, end: usize) -> Result<Vec<BoxHeader>> {
+    let mut boxes = vec![];
+    let mut pos = start;
+
+    while pos < end {
+        let size = u32_at(data, pos)? as u64;
+        let kind: [u8; 4] = match data.get(pos + 4..pos + BOX_HEADER_SIZE) {
+            Some(v) => v.try_into()?,
+            None => return Err(anyhow!("unexpected end of mp4 box")),
+        };
+
+        let (content, size) = match size {
+            0 => (pos + BOX_HEADER_SIZE, (end - pos) as u64),
+            1 => (pos + LARGE_BOX_HEADER_SIZE, u64_at(data, pos + 8)?),
+            _ => (pos + BOX_HEADER_SIZE, size),
+        };
+
+        // a crafted largesize may overflow the end of the box
+        let box_end = match (pos as u64).checked_add(size) {
+            Some(v) if size >= (content - pos) as u64 && v <= end as u64 => v,
+            _ => return Err(anyhow!("invalid mp4 box size at {pos}")),
+        };
+
+        boxes.push(BoxHeader {
+            kind,
+            start: pos,
+            content,
+            end: box_end as usize,
+        });
+        pos = box_end as usize;
+    }
+
+    Ok(boxes)
+}
+
+fn top_level_boxes(data: &[u8]) -> Result<Vec<BoxHeader>> {
+    let boxes = parse_boxes(data, 0, data.len())?;
+    match boxes.first() {
+        Some(b) if &b.kind == b"ftyp" => Ok(boxes),
+        _ => Err(anyhow!("not a mp4 file")),
+    }
+}
+
+fn is_payload_box(data: &[u8], b: &BoxHeader) -> bool {
+    let content = &data[b.content..b.end];
+    (&b.kind == b"free" || &b.kind == b"skip")
+        && content.len() > MAGIC_NUM.len() * 2
+        && content.starts_with(MAGIC_NUM.as_bytes())
+        && content.ends_with(MAGIC_NUM.as_bytes())
+}
+
+fn shift(offset: u64, from: u64, delta: i64) -> Result<u64> {
+    if offset < from {
+        return Ok(offset);
+    }
+
+    offset
+        .checked_add_signed(delta)
+        .ok_or_else(|| anyhow!("invalid mp4 chunk offset {offset}"))
+}
+
+// move the absolute file offsets (stco, co64, tfhd) which point at or after `from`
+fn shift_offsets(data: &mut [u8], start: usize, end: usize, from: u64, delta: i64) -> Result<()> {
+    for b in parse_boxes(data, start, end)? {
+        if CONTAINER_BOXES.contains(&&b.kind) {
+            shift_offsets(data, b.content, b.end, from, delta)?;
+            continue;
+        }
+
+        let pos = b.content + FULL_BOX_HEADER_SIZE;
+        match &b.kind {
+            b"stco" | b"co64" => {
+                let entry_size = if &b.kind == b"stco" { 4 } else { 8 };
+                let count = u32_at(data, pos)? as usize;
+                let entries_end = count
+                    .checked_mul(entry_size)
+                    .and_then(|v| v.checked_add(pos + 4));
+                if !matches!(entries_end, Some(v) if v <= b.end) {
+                    return Err(anyhow!("invalid mp4 chunk offset box"));
+                }
+
+                for i in 0..count {
+                    let entry_pos = pos + 4 + i * entry_size;
+                    if entry_size == 4 {
+                        let offset = shift(u32_at(data, entry_pos)? as u64, from, delta)?;
+                        let offset = u32::try_from(offset)
+                            .map_err(|_| anyhow!("mp4 chunk offset is too large for stco"))?;
+                        data[entry_pos..entry_pos + 4].copy_from_slice(&offset.to_be_bytes());
+                    } else {
+                        let offset = shift(u64_at(data, entry_pos)?, from, delta)?;
+                        data[entry_pos..entry_pos + 8].copy_from_slice(&offset.to_be_bytes());
+                    }
+                }
+            }
+            b"tfhd" => {
+                let flags = u32_at(data, b.content)? & 0x00FF_FFFF;
+                if flags & TFHD_BASE_DATA_OFFSET != 0 {
+                    let entry_pos = pos + 4;
+                    let offset = shift(u64_at(data, entry_pos)?, from, delta)?;
+                    data[entry_pos..entry_pos + 8].copy_from_slice(&offset.to_be_bytes());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+// remove the payload boxes which are written before
+fn strip(carrier: &[u8]) -> Result<Vec<u8>> {
+    let mut data = carrier.to_vec();
+
+    while let Some(b) = top_level_boxes(&data)?
+        .into_iter()
+        .find(|b| is_payload_box(&data, b))
+    {
+        let len = data.len();
+        shift_offsets(&mut data, 0, len, b.end as u64, -((b.end - b.start) as i64))?;
+        data.drain(b.start..b.end);
+    }
+
+    Ok(data)
+}
+
+fn make_box(payload: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(payload.len() + LARGE_BOX_HEADER_SIZE);
+    let size = (payload.len() + BOX_HEADER_SIZE) as u64;
+
+    if size > u32::MAX as u64 {
+        output.extend_from_slice(&1_u32.to_be_bytes());
+        output.extend_from_slice(b"free");
+        output.extend_from_slice(&(size + 8).to_be_bytes());
+    } else {
+        output.extend_from_slice(&(size as u32).to_be_bytes());
+        output.extend_from_slice(b"free");
+    }
+
+    output.extend_from_slice(payload);
+    output
+}
+
+// LAYOUT: ftyp + free(payload) + other top-level boxes;
+//  the payload box is inserted before moov and mdat, so the chunk offsets are moved
+//  and tools that rewrite the end of the file would keep it.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let mut data = strip(carrier)?;
+    let pos = top_level_boxes(&data)?[0].end;
+    let payload_box = make_box(payload);
+
+    let len = data.len();
+    shift_offsets(&mut data, 0, len, pos as u64, payload_box.len() as i64)?;
+    data.splice(pos..pos, payload_box);
+
+    Ok(data)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    top_level_boxes(data)
+        .ok()?
+        .into_iter()
+        .find(|b| is_payload_box(data, b))
+        .map(|b| data[b.content..b.end].to_vec())
+}
+
+// the top-level boxes cover the whole file and the movie box is present
+pub fn verify(data: &[u8]) -> Result<()> {
+    let boxes = top_level_boxes(data)?;
+    if !boxes.iter().any(|b| &b.kind == b"moov") {
+        return Err(anyhow!("mp4 moov box is not found"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::util;
+
+    fn mp4_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let size = (content.len() + BOX_HEADER_SIZE) as u32;
+        [&size.to_be_bytes()[..], kind, content].concat()
+    }
+
+    // ftyp + moov(trak(mdia(minf(stbl(stco + co64))))) + mdat
+    fn carrier() -> Vec<u8> {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        let samples = [b"first sample".to_vec(), b"second sample".to_vec()].concat();
+
+        let build = |mdat_pos: usize| {
+            let mut stco = vec![0, 0, 0, 0];
+            stco.extend_from_slice(&1_u32.to_be_bytes());
+            stco.extend_from_slice(&(mdat_pos as u32 + 8).to_be_bytes());
+
+            let mut co64 = vec![0, 0, 0, 0];
+            co64.extend_from_slice(&1_u32.to_be_bytes());
+            co64.extend_from_slice(&(mdat_pos as u64 + 8 + 12).to_be_bytes());
+
+            let stbl = mp4_box(
+                b"stbl",
+                &[mp4_box(b"stco", &stco), mp4_box(b"co64", &co64)].concat(),
+            );
+            let minf = mp4_box(b"minf", &stbl);
+            let mdia = mp4_box(b"mdia", &minf);
+            let trak = mp4_box(b"trak", &mdia);
+            mp4_box(b"moov", &trak)
+        };
+
+        let moov_len = build(0).len();
+        let moov = build(ftyp.len() + moov_len);
+        [ftyp, moov, mp4_box(b"mdat", &samples)].concat()
+    }
+
+    fn chunk_offsets(data: &[u8]) -> Result<Vec<u64>> {
+        let mut offsets = vec![];
+        let mut boxes = parse_boxes(data, 0, data.len())?;
+        while let Some(b) = boxes.pop() {
+            match &b.kind {
+                b"stco" => offsets.push(u32_at(data, b.content + 8)? as u64),
+                b"co64" => offsets.push(u64_at(data, b.content + 8)?),
+                kind if CONTAINER_BOXES.contains(&kind) => {
+                    boxes.append(&mut parse_boxes(data, b.content, b.end)?)
+                }
+                _ => (),
+            }
+        }
+        offsets.sort();
+        Ok(offsets)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_mp4_parse_boxes() -> Result<()> {
+        let carrier = carrier();
+        let boxes = top_level_boxes(&carrier)?;
+        let kinds = boxes.iter().map(|b| &b.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"ftyp", b"moov", b"mdat"]);
+
+        let offsets = chunk_offsets(&carrier)?;
+        assert_eq!(&carrier[offsets[0] as usize..][..12], b"first sample");
+        assert_eq!(&carrier[offsets[1] as usize..][..13], b"second sample");
+
+        assert!(top_level_boxes(b"not a mp4 file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mp4_embed_extract() -> Result<()> {
+        let carrier = carrier();
+        assert!(extract(&carrier).is_none());
+
+        let payload = payload(10000);
+        let output = embed(&carrier, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+
+        let boxes = top_level_boxes(&output)?;
+        let kinds = boxes.iter().map(|b| &b.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"ftyp", b"free", b"moov", b"mdat"]);
+        assert_eq!(
+            boxes[1].end - boxes[1].start,
+            payload.len() + BOX_HEADER_SIZE
+        );
+
+        let offsets = chunk_offsets(&output)?;
+        assert_eq!(&output[offsets[0] as usize..][..12], b"first sample");
+        assert_eq!(&output[offsets[1] as usize..][..13], b"second sample");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mp4_embed_replace() -> Result<()> {
+        let carrier = carrier();
+        let output = embed(&carrier, &payload(100))?;
+        let payload = payload(50);
+        let output = embed(&output, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+        assert_eq!(
+            output.len(),
+            carrier.len() + payload.len() + BOX_HEADER_SIZE
+        );
+        assert_eq!(strip(&output)?, carrier);
+        Ok(())
+    }
+
+    // the largesize of the free box overflows the end of the file
+    pub(crate) fn hostile_carrier() -> Vec<u8> {
+        let mut free = 1_u32.to_be_bytes().to_vec();
+        free.extend_from_slice(b"free");
+        free.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        [mp4_box(b"ftyp", b"isom"), free].concat()
+    }
+
+    #[test]
+    fn test_mp4_largesize_overflow() {
+        let data = hostile_carrier();
+        assert!(top_level_boxes(&data).is_err());
+        assert!(extract(&data).is_none());
+        assert!(verify(&data).is_err());
+    }
+}
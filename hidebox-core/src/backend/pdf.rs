@@ -0,0 +1,514 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+
+const STARTXREF: &[u8] = b"startxref";
+const XREF: &[u8] = b"xref";
+const TRAILER: &[u8] = b"trailer";
+const STREAM: &[u8] = b"stream";
+const EOF_MARKER: &[u8] = b"%%EOF";
+
+// the trailer entries which have to be repeated in the update trailer
+const TRAILER_KEYS: [&str; 4] = ["/Root", "/Info", "/ID", "/Encrypt"];
+
+// where to look for startxref from the end of the file
+const STARTXREF_SEARCH_SIZE: usize = 1024;
+const MAX_UPDATE_SECTIONS: usize = 256;
+
+// the arrays and dictionaries are parsed recursively, a hostile file must not overflow the stack
+const MAX_NESTING_DEPTH: usize = 64;
+
+type DictEntries = Vec<(String, Vec<u8>)>;
+
+#[derive(Clone, Debug)]
+struct Trailer {
+    size: u64,
+    prev: Option<usize>,
+    is_xref_stream: bool,
+    entries: DictEntries,
+}
+
+fn is_whitespace(c: u8) -> bool {
+    matches!(c, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_delimiter(c: u8) -> bool {
+    matches!(
+        c,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn skip_whitespace(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() {
+        if is_whitespace(data[pos]) {
+            pos += 1;
+        } else if data[pos] == b'%' {
+            while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+fn token_end(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() && !is_whitespace(data[pos]) && !is_delimiter(data[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+// return the token at pos and the position after it
+fn next_token(data: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let start = skip_whitespace(data, pos);
+    let end = token_end(data, start);
+    if start == end {
+        None
+    } else {
+        Some((&data[start..end], end))
+    }
+}
+
+fn parse_uint(token: &[u8]) -> Option<u64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+fn find(data: &[u8], pat: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(pat.len())
+        .position(|w| w == pat)
+        .map(|pos| pos + from)
+}
+
+// return the end position of the literal string which starts at pos
+fn string_end(data: &[u8], mut pos: usize) -> Result<usize> {
+    let mut depth = 0;
+    while pos < data.len() {
+        match data[pos] {
+            b'\\' => pos += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(pos + 1);
+                }
+            }
+            _ => (),
+        }
+        pos += 1;
+    }
+    Err(anyhow!("unterminated pdf string"))
+}
+
+// return the end position of the object which starts at pos,
+// an indirect reference `1 0 R` is treated as one object
+fn object_end(data: &[u8], pos: usize) -> Result<usize> {
+    nested_object_end(data, pos, 0)
+}
+
+fn nested_object_end(data: &[u8], pos: usize, depth: usize) -> Result<usize> {
+    if depth >= MAX_NESTING_DEPTH {
+        return Err(anyhow!("pdf objects are nested too deeply"));
+    }
+
+    let pos = skip_whitespace(data, pos);
+    let Some(&c) = data.get(pos) else {
+        return Err(anyhow!("unexpected end of pdf object"));
+    };
+
+    match c {
+        b'<' if data.get(pos + 1) == Some(&b'<') => {
+            let mut cur = pos + 2;
+            loop {
+                cur = skip_whitespace(data, cur);
+                if data.get(cur..cur + 2) == Some(b">>") {
+                    return Ok(cur + 2);
+                }
+                cur = nested_object_end(data, cur, depth + 1)?;
+            }
+        }
+        b'<' => match find(data, b">", pos) {
+            Some(end) => Ok(end + 1),
+            None => Err(anyhow!("unterminated pdf hex string")),
+        },
+        b'[' => {
+            let mut cur = pos + 1;
+            loop {
+                cur = skip_whitespace(data, cur);
+                if data.get(cur) == Some(&b']') {
+                    return Ok(cur + 1);
+                }
+                cur = nested_object_end(data, cur, depth + 1)?;
+            }
+        }
+        b'(' => string_end(data, pos),
+        b'/' => Ok(token_end(data, pos + 1)),
+        _ => {
+            let end = token_end(data, pos);
+            if end == pos {
+                return Err(anyhow!("invalid pdf object at {pos}"));
+            }
+
+            // lookahead for the generation number and `R`
+            if parse_uint(&data[pos..end]).is_some() {
+                if let Some((gen, gen_end)) = next_token(data, end) {
+                    if let Some((r, r_end)) = next_token(data, gen_end) {
+                        if parse_uint(gen).is_some() && r == b"R" {
+                            return Ok(r_end);
+                        }
+                    }
+                }
+            }
+            Ok(end)
+        }
+    }
+}
+
+// return the entries of the dictionary at pos and the position after it
+fn parse_dict(data: &[u8], pos: usize) -> Result<(DictEntries, usize)> {
+    let mut pos = skip_whitespace(data, pos);
+    if data.get(pos..pos + 2) != Some(b"<<") {
+        return Err(anyhow!("invalid pdf dictionary at {pos}"));
+    }
+    pos += 2;
+
+    let mut entries = vec![];
+    loop {
+        pos = skip_whitespace(data, pos);
+        if data.get(pos..pos + 2) == Some(b">>") {
+            return Ok((entries, pos + 2));
+        }
+
+        if data.get(pos) != Some(&b'/') {
+            return Err(anyhow!("invalid pdf dictionary key at {pos}"));
+        }
+
+        let key_end = token_end(data, pos + 1);
+        let key = String::from_utf8_lossy(&data[pos..key_end]).to_string();
+        let value_start = skip_whitespace(data, key_end);
+        let value_end = object_end(data, value_start)?;
+        entries.push((key, data[value_start..value_end].to_vec()));
+        pos = value_end;
+    }
+}
+
+fn dict_value<'a>(entries: &'a [(String, Vec<u8>)], key: &str) -> Option<&'a [u8]> {
+    entries
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_slice())
+}
+
+fn startxref(data: &[u8]) -> Result<usize> {
+    let from = data.len().saturating_sub(STARTXREF_SEARCH_SIZE);
+    let pos = data[from..]
+        .windows(STARTXREF.len())
+        .rposition(|w| w == STARTXREF)
+        .map(|pos| pos + from);
+
+    let Some(pos) = pos else {
+        return Err(anyhow!("not a pdf file, startxref is not found"));
+    };
+
+    match next_token(data, pos + STARTXREF.len()).and_then(|(t, _)| parse_uint(t)) {
+        Some(offset) if (offset as usize) < data.len() => Ok(offset as usize),
+        _ => Err(anyhow!("invalid pdf startxref")),
+    }
+}
+
+// skip `num gen obj` and return the position after it
+fn skip_object_header(data: &[u8], pos: usize) -> Option<usize> {
+    let (num, pos) = next_token(data, pos)?;
+    let (gen, pos) = next_token(data, pos)?;
+    let (keyword, pos) = next_token(data, pos)?;
+
+    if parse_uint(num).is_some() && parse_uint(gen).is_some() && keyword == b"obj" {
+        Some(pos)
+    } else {
+        None
+    }
+}
+
+// the trailer of a cross-reference table or a cross-reference stream
+fn read_trailer(data: &[u8], xref_offset: usize) -> Result<Trailer> {
+    let pos = skip_whitespace(data, xref_offset);
+
+    let (entries, is_xref_stream) = if data[pos..].starts_with(XREF) {
+        match find(data, TRAILER, pos) {
+            Some(trailer_pos) => (parse_dict(data, trailer_pos + TRAILER.len())?.0, false),
+            None => return Err(anyhow!("pdf trailer is not found")),
+        }
+    } else {
+        match skip_object_header(data, pos) {
+            Some(dict_pos) => (parse_dict(data, dict_pos)?.0, true),
+            None => return Err(anyhow!("invalid pdf cross-reference section")),
+        }
+    };
+
+    let size = match dict_value(&entries, "/Size").and_then(parse_uint) {
+        Some(v) => v,
+        None => return Err(anyhow!("invalid pdf trailer /Size")),
+    };
+
+    let prev = dict_value(&entries, "/Prev")
+        .and_then(parse_uint)
+        .map(|v| v as usize);
+
+    Ok(Trailer {
+        size,
+        prev,
+        is_xref_stream,
+        entries,
+    })
+}
+
+// the offsets of the in-use objects of a cross-reference table
+fn xref_objects(data: &[u8], xref_offset: usize) -> Option<Vec<usize>> {
+    let (keyword, mut pos) = next_token(data, xref_offset)?;
+    if keyword != XREF {
+        return None;
+    }
+
+    let mut offsets = vec![];
+    loop {
+        let (token, next) = next_token(data, pos)?;
+        if token == TRAILER {
+            return Some(offsets);
+        }
+
+        let (count, next) = next_token(data, next)?;
+        pos = next;
+
+        parse_uint(token)?;
+        for _ in 0..parse_uint(count)? {
+            let (offset, next) = next_token(data, pos)?;
+            let (_gen, next) = next_token(data, next)?;
+            let (kind, next) = next_token(data, next)?;
+            pos = next;
+
+            if kind == b"n" {
+                offsets.push(parse_uint(offset)? as usize);
+            }
+        }
+    }
+}
+
+// the data range of the stream object at offset
+fn stream_data(data: &[u8], offset: usize) -> Option<Range<usize>> {
+    let dict_pos = skip_object_header(data, offset)?;
+    let (entries, pos) = parse_dict(data, dict_pos).ok()?;
+    let len = parse_uint(dict_value(&entries, "/Length")?)? as usize;
+
+    let pos = skip_whitespace(data, pos);
+    if !data[pos..].starts_with(STREAM) {
+        return None;
+    }
+
+    let mut start = pos + STREAM.len();
+    if data.get(start) == Some(&b'\r') {
+        start += 1;
+    }
+    if data.get(start) == Some(&b'\n') {
+        start += 1;
+    }
+
+    let end = start.checked_add(len)?;
+    if end > data.len() {
+        None
+    } else {
+        Some(start..end)
+    }
+}
+
+fn is_payload(data: &[u8]) -> bool {
+    data.len() > MAGIC_NUM.len() * 2
+        && data.starts_with(MAGIC_NUM.as_bytes())
+        && data.ends_with(MAGIC_NUM.as_bytes())
+}
+
+// the payload object of the update section at xref_offset
+fn find_payload(data: &[u8], xref_offset: usize) -> Option<(usize, Range<usize>)> {
+    for offset in xref_objects(data, xref_offset)? {
+        if let Some(range) = stream_data(data, offset) {
+            if is_payload(&data[range.clone()]) {
+                return Some((offset, range));
+            }
+        }
+    }
+    None
+}
+
+// LAYOUT: original pdf + payload stream object + xref section + trailer;
+//  the update trailer points to the original cross-reference section with /Prev.
+//  The payload update is replaced if it is the last update of the carrier.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let mut xref_offset = startxref(carrier)?;
+    let mut base = carrier;
+    if let Some((offset, _)) = find_payload(carrier, xref_offset) {
+        base = &carrier[..offset];
+        xref_offset = startxref(base)?;
+    }
+
+    let trailer = read_trailer(base, xref_offset)?;
+
+    let mut output = Vec::with_capacity(base.len() + payload.len() + 512);
+    output.extend_from_slice(base);
+    if !output.ends_with(b"\n") && !output.ends_with(b"\r") {
+        output.push(b'\n');
+    }
+
+    let obj_num = trailer.size;
+    let obj_offset = output.len();
+    output.extend_from_slice(
+        format!("{obj_num} 0 obj\n<< /Length {} >>\nstream\n", payload.len()).as_bytes(),
+    );
+    output.extend_from_slice(payload);
+    output.extend_from_slice(b"\nendstream\nendobj\n");
+
+    let update_xref_offset = output.len();
+    output.extend_from_slice(format!("xref\n{obj_num} 1\n{obj_offset:010} 00000 n\r\n").as_bytes());
+
+    output.extend_from_slice(format!("trailer\n<< /Size {}", obj_num + 1).as_bytes());
+    for key in TRAILER_KEYS {
+        if let Some(value) = dict_value(&trailer.entries, key) {
+            output.extend_from_slice(format!(" {key} ").as_bytes());
+            output.extend_from_slice(value);
+        }
+    }
+    output.extend_from_slice(format!(" /Prev {xref_offset} >>\n").as_bytes());
+    output.extend_from_slice(format!("startxref\n{update_xref_offset}\n%%EOF\n").as_bytes());
+
+    Ok(output)
+}
+
+// walk the update sections from the last one
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    let mut xref_offset = startxref(data).ok()?;
+
+    for _ in 0..MAX_UPDATE_SECTIONS {
+        if let Some((_, range)) = find_payload(data, xref_offset) {
+            return Some(data[range].to_vec());
+        }
+
+        let trailer = read_trailer(data, xref_offset).ok()?;
+        match trailer.prev {
+            Some(prev) if prev < data.len() && !trailer.is_xref_stream => xref_offset = prev,
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+// the readers find the last cross-reference section from the end of the file,
+//  its trailer must lead to the document catalog
+pub fn verify(data: &[u8]) -> Result<()> {
+    let from = data.len().saturating_sub(STARTXREF_SEARCH_SIZE);
+    if find(data, EOF_MARKER, from).is_none() {
+        return Err(anyhow!(
+            "pdf %%EOF marker is not found at the end of the file"
+        ));
+    }
+
+    let trailer = read_trailer(data, startxref(data)?)?;
+    if dict_value(&trailer.entries, "/Root").is_none() {
+        return Err(anyhow!("pdf trailer /Root is not found"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier(name: &str) -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata").join(name))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_pdf_parse_dict() -> Result<()> {
+        let text = b"<< /Size 7 /Root 1 0 R /ID [<0a1b> (a (b) \\) c)] /D << /N /Name >> >>";
+        let (entries, end) = parse_dict(text, 0)?;
+        assert_eq!(end, text.len());
+        assert_eq!(dict_value(&entries, "/Size"), Some(&b"7"[..]));
+        assert_eq!(dict_value(&entries, "/Root"), Some(&b"1 0 R"[..]));
+        assert_eq!(
+            dict_value(&entries, "/ID"),
+            Some(&b"[<0a1b> (a (b) \\) c)]"[..])
+        );
+        assert_eq!(dict_value(&entries, "/D"), Some(&b"<< /N /Name >>"[..]));
+        assert!(parse_dict(b"<< /Size 7", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pdf_embed_extract() -> Result<()> {
+        for name in ["src.pdf", "src-xref-stream.pdf"] {
+            let carrier = carrier(name)?;
+            assert!(extract(&carrier).is_none());
+
+            let payload = payload(10000);
+            let output = embed(&carrier, &payload)?;
+            assert_eq!(extract(&output).unwrap(), payload);
+            assert!(output.starts_with(&carrier));
+            assert!(output.ends_with(b"%%EOF\n"));
+
+            let trailer = read_trailer(&output, startxref(&output)?)?;
+            assert_eq!(trailer.prev, Some(startxref(&carrier)?));
+            assert_eq!(dict_value(&trailer.entries, "/Root"), Some(&b"1 0 R"[..]));
+
+            let doc = lopdf::Document::load_mem(&output)?;
+            assert_eq!(doc.get_pages().len(), 1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pdf_embed_replace() -> Result<()> {
+        let carrier = carrier("src.pdf")?;
+        let output = embed(&carrier, &payload(100))?;
+        let payload = payload(50);
+        let output = embed(&output, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+        assert_eq!(read_trailer(&output, startxref(&output)?)?.size, 8);
+        Ok(())
+    }
+
+    fn nested(open: &str, close: &str, depth: usize) -> String {
+        format!("<< /A {}1{} >>", open.repeat(depth), close.repeat(depth))
+    }
+
+    // the trailer is nested too deeply to be parsed recursively
+    pub(crate) fn hostile_carrier(open: &str, close: &str) -> Vec<u8> {
+        format!(
+            "%PDF-1.4\nxref\n0 1\ntrailer\n{}\nstartxref\n9\n%%EOF\n",
+            nested(open, close, 100_000)
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_pdf_deep_nesting() {
+        assert!(parse_dict(nested("[", "]", MAX_NESTING_DEPTH - 1).as_bytes(), 0).is_ok());
+        assert!(parse_dict(nested("[", "]", MAX_NESTING_DEPTH).as_bytes(), 0).is_err());
+
+        // the trailer is parsed by the extracting of every pdf file
+        for (open, close) in [("[", "]"), ("<< /B ", " >>")] {
+            let pdf = hostile_carrier(open, close);
+            assert!(extract(&pdf).is_none());
+            assert!(verify(&pdf).is_err());
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+
+const RIFF_ID: &[u8] = b"RIFF";
+const FORM_TYPES: [&[u8]; 3] = [b"WAVE", b"AVI ", b"WEBP"];
+const CHUNK_ID: &[u8] = b"hbox";
+
+// the chunk is the last one of the file, the terminator keeps the file from
+// ending with the magic number, so it is not mistaken for a trailer carrier
+const TERMINATOR: u8 = 0;
+
+const CHUNK_HEADER_SIZE: usize = 8;
+const FORM_TYPE_SIZE: usize = 4;
+
+#[derive(Clone, Debug)]
+struct Chunk {
+    id: [u8; 4],
+    start: usize,
+    end: usize,
+}
+
+impl Chunk {
+    fn data<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let size = u32_at(data, self.start + 4).unwrap_or_default() as usize;
+        &data[self.start + CHUNK_HEADER_SIZE..self.start + CHUNK_HEADER_SIZE + size]
+    }
+
+    fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let content = self.data(data);
+        content.strip_suffix(&[TERMINATOR]).unwrap_or(content)
+    }
+
+    fn is_hidebox(&self, data: &[u8]) -> bool {
+        let content = self.payload(data);
+        self.id == CHUNK_ID
+            && content.len() > MAGIC_NUM.len() * 2
+            && content.starts_with(MAGIC_NUM.as_bytes())
+            && content.ends_with(MAGIC_NUM.as_bytes())
+    }
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32> {
+    match data.get(pos..pos.saturating_add(4)) {
+        Some(v) => Ok(u32::from_le_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of riff file")),
+    }
+}
+
+// LAYOUT: RIFF + size(4 bytes) + form type(4 bytes) + chunks;
+//  chunk: id(4 bytes) + size(4 bytes) + data + [pad byte if size is odd].
+// Return the chunks and the end of the RIFF chunk.
+fn parse_chunks(data: &[u8]) -> Result<(Vec<Chunk>, usize)> {
+    if data.len() < CHUNK_HEADER_SIZE + FORM_TYPE_SIZE
+        || !data.starts_with(RIFF_ID)
+        || !FORM_TYPES.contains(&&data[8..12])
+    {
+        return Err(anyhow!("not a riff file"));
+    }
+
+    let riff_end = CHUNK_HEADER_SIZE + u32_at(data, 4)? as usize;
+    if riff_end > data.len() {
+        return Err(anyhow!("invalid riff size"));
+    }
+
+    // the OpenDML AVI files have more RIFF chunks which are referenced by absolute offsets
+    if data[riff_end..].starts_with(RIFF_ID)
+        || data.get(riff_end + 1..riff_end + 5) == Some(RIFF_ID)
+    {
+        return Err(anyhow!("unsupported riff file with multiple RIFF chunks"));
+    }
+
+    let mut chunks = vec![];
+    let mut pos = CHUNK_HEADER_SIZE + FORM_TYPE_SIZE;
+    while pos < riff_end {
+        let id: [u8; 4] = match data.get(pos..pos + 4) {
+            Some(v) => v.try_into()?,
+            None => return Err(anyhow!("unexpected end of riff chunk")),
+        };
+
+        let size = u32_at(data, pos + 4)? as usize;
+        let end = pos + CHUNK_HEADER_SIZE + size;
+        if end > riff_end {
+            return Err(anyhow!("invalid riff chunk size at {pos}"));
+        }
+
+        // the pad byte may be missing in the last chunk
+        let end = usize::min(end + size % 2, riff_end);
+        chunks.push(Chunk {
+            id,
+            start: pos,
+            end,
+        });
+        pos = end;
+    }
+
+    Ok((chunks, riff_end))
+}
+
+// LAYOUT: hbox + size(4 bytes) + payload + terminator(1 byte) + [pad byte];
+fn make_chunk(payload: &[u8]) -> Vec<u8> {
+    let size = payload.len() + 1;
+    let mut chunk = Vec::with_capacity(size + CHUNK_HEADER_SIZE + 1);
+    chunk.extend_from_slice(CHUNK_ID);
+    chunk.extend_from_slice(&(size as u32).to_le_bytes());
+    chunk.extend_from_slice(payload);
+    chunk.push(TERMINATOR);
+    if size % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+// append the payload chunk at the end of the RIFF chunk and fix up the RIFF size,
+// the other chunks are not moved. The hidebox chunk of the carrier would be replaced.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let (chunks, riff_end) = parse_chunks(carrier)?;
+
+    let mut output = Vec::with_capacity(carrier.len() + payload.len() + CHUNK_HEADER_SIZE + 1);
+    output.extend_from_slice(&carrier[..CHUNK_HEADER_SIZE + FORM_TYPE_SIZE]);
+
+    for chunk in chunks.iter().filter(|c| !c.is_hidebox(carrier)) {
+        output.extend_from_slice(&carrier[chunk.start..chunk.end]);
+        if (chunk.end - chunk.start) % 2 == 1 {
+            output.push(0);
+        }
+    }
+
+    output.append(&mut make_chunk(payload));
+
+    let riff_size = u32::try_from(output.len() - CHUNK_HEADER_SIZE)
+        .map_err(|_| anyhow!("riff file is too large"))?;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    output.extend_from_slice(&carrier[riff_end..]);
+    Ok(output)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    let (chunks, _) = parse_chunks(data).ok()?;
+    chunks
+        .iter()
+        .find(|c| c.is_hidebox(data))
+        .map(|c| c.payload(data).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.wav"))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    fn samples(data: &[u8]) -> Result<Vec<i16>> {
+        let reader = hound::WavReader::new(data)?;
+        Ok(reader
+            .into_samples::<i16>()
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    #[test]
+    fn test_riff_parse_chunks() -> Result<()> {
+        let carrier = carrier()?;
+        let (chunks, riff_end) = parse_chunks(&carrier)?;
+        let ids = chunks.iter().map(|c| &c.id).collect::<Vec<_>>();
+        assert_eq!(ids, [b"fmt ", b"data"]);
+        assert_eq!(riff_end, carrier.len());
+        assert!(parse_chunks(b"not a riff file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_riff_embed_extract() -> Result<()> {
+        let carrier = carrier()?;
+        assert!(extract(&carrier).is_none());
+
+        // an even payload size needs a pad byte after the terminator
+        let payload = payload(10000);
+        let output = embed(&carrier, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+
+        let (chunks, riff_end) = parse_chunks(&output)?;
+        assert_eq!(riff_end, output.len());
+        assert_eq!(riff_end % 2, 0);
+        assert_eq!(output[output.len() - 2..], [TERMINATOR, 0]);
+        assert_eq!(&chunks.last().unwrap().id, b"hbox");
+
+        assert_eq!(samples(&output)?, samples(&carrier)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_riff_embed_replace() -> Result<()> {
+        let carrier = carrier()?;
+        let output = embed(&carrier, &payload(100))?;
+        let payload = payload(50);
+        let output = embed(&output, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+        assert_eq!(
+            output.len(),
+            carrier.len() + payload.len() + CHUNK_HEADER_SIZE + 2
+        );
+        Ok(())
+    }
+}
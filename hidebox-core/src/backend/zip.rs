@@ -0,0 +1,361 @@
+use super::super::MAGIC_NUM;
+use anyhow::{anyhow, Result};
+
+const LOCAL_HEADER_SIG: &[u8] = b"PK\x03\x04";
+const CENTRAL_HEADER_SIG: &[u8] = b"PK\x01\x02";
+const DATA_DESCRIPTOR_SIG: &[u8] = b"PK\x07\x08";
+const ZIP64_EOCD_SIG: &[u8] = b"PK\x06\x06";
+const ZIP64_LOCATOR_SIG: &[u8] = b"PK\x06\x07";
+const EOCD_SIG: &[u8] = b"PK\x05\x06";
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const EOCD_SIZE: usize = 22;
+const MAX_COMMENT_SIZE: usize = u16::MAX as usize;
+
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+const U32_MAX: u64 = u32::MAX as u64;
+
+#[derive(Clone, Debug)]
+struct Directory {
+    eocd_pos: usize,
+    zip64_locator_pos: Option<usize>,
+    zip64_eocd_pos: Option<usize>,
+    cd_offset: usize,
+    entries_end: usize,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16> {
+    match data.get(pos..pos.saturating_add(2)) {
+        Some(v) => Ok(u16::from_le_bytes([v[0], v[1]])),
+        None => Err(anyhow!("unexpected end of zip file")),
+    }
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32> {
+    match data.get(pos..pos.saturating_add(4)) {
+        Some(v) => Ok(u32::from_le_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of zip file")),
+    }
+}
+
+fn u64_at(data: &[u8], pos: usize) -> Result<u64> {
+    match data.get(pos..pos.saturating_add(8)) {
+        Some(v) => Ok(u64::from_le_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of zip file")),
+    }
+}
+
+fn has_sig(data: &[u8], pos: usize, sig: &[u8]) -> bool {
+    data.get(pos..pos.saturating_add(sig.len())) == Some(sig)
+}
+
+// the comment is the last field of the file, so the EOCD record is
+// the one whose comment reaches the end of the file
+fn find_eocd(data: &[u8]) -> Result<usize> {
+    if data.len() < EOCD_SIZE {
+        return Err(anyhow!("not a zip file"));
+    }
+
+    let last = data.len() - EOCD_SIZE;
+    let first = last.saturating_sub(MAX_COMMENT_SIZE);
+    for pos in (first..=last).rev() {
+        if has_sig(data, pos, EOCD_SIG)
+            && pos + EOCD_SIZE + u16_at(data, pos + 20)? as usize == data.len()
+        {
+            return Ok(pos);
+        }
+    }
+
+    Err(anyhow!("not a zip file"))
+}
+
+// return the end position of the local entry which is described by
+// the central directory header at pos, and the size of the central header
+fn parse_entry(data: &[u8], pos: usize) -> Result<(usize, usize)> {
+    if !has_sig(data, pos, CENTRAL_HEADER_SIG) {
+        return Err(anyhow!("invalid central directory header at {pos}"));
+    }
+
+    let flags = u16_at(data, pos + 8)?;
+    let mut compressed_size = u32_at(data, pos + 20)? as u64;
+    let uncompressed_size = u32_at(data, pos + 24)? as u64;
+    let name_len = u16_at(data, pos + 28)? as usize;
+    let extra_len = u16_at(data, pos + 30)? as usize;
+    let comment_len = u16_at(data, pos + 32)? as usize;
+    let mut local_offset = u32_at(data, pos + 42)? as u64;
+
+    // the zip64 extra field only contains the saturated fields
+    let mut is_zip64 = false;
+    let mut extra_pos = pos + CENTRAL_HEADER_SIZE + name_len;
+    let extra_end = extra_pos + extra_len;
+    while extra_pos + 4 <= extra_end {
+        let id = u16_at(data, extra_pos)?;
+        let size = u16_at(data, extra_pos + 2)? as usize;
+
+        if id == ZIP64_EXTRA_ID {
+            is_zip64 = true;
+            let mut field_pos = extra_pos + 4;
+            if uncompressed_size == U32_MAX {
+                field_pos += 8;
+            }
+            if compressed_size == U32_MAX {
+                compressed_size = u64_at(data, field_pos)?;
+                field_pos += 8;
+            }
+            if local_offset == U32_MAX {
+                local_offset = u64_at(data, field_pos)?;
+            }
+        }
+
+        extra_pos += 4 + size;
+    }
+
+    // the zip64 fields are read from the file, so the sizes may overflow
+    let local_offset = usize::try_from(local_offset)?;
+    let compressed_size = usize::try_from(compressed_size)?;
+    if !has_sig(data, local_offset, LOCAL_HEADER_SIG) {
+        return Err(anyhow!("invalid local file header at {local_offset}"));
+    }
+
+    let local_name_len = u16_at(data, local_offset + 26)? as usize;
+    let local_extra_len = u16_at(data, local_offset + 28)? as usize;
+    let mut end = (local_offset + LOCAL_HEADER_SIZE + local_name_len + local_extra_len)
+        .checked_add(compressed_size)
+        .ok_or_else(|| anyhow!("invalid local file entry at {local_offset}"))?;
+
+    if flags & FLAG_DATA_DESCRIPTOR != 0 {
+        if has_sig(data, end, DATA_DESCRIPTOR_SIG) {
+            end += DATA_DESCRIPTOR_SIG.len();
+        }
+        end += if is_zip64 { 20 } else { 12 };
+    }
+
+    if end > data.len() {
+        return Err(anyhow!("invalid local file entry at {local_offset}"));
+    }
+
+    Ok((
+        end,
+        CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len,
+    ))
+}
+
+// LAYOUT: local entries + gap + central directory + [zip64 eocd + zip64 locator] + eocd;
+//  the gap is empty for normal zip files.
+fn parse_directory(data: &[u8]) -> Result<Directory> {
+    let eocd_pos = find_eocd(data)?;
+    let mut entries = u16_at(data, eocd_pos + 10)? as u64;
+    let mut cd_size = u32_at(data, eocd_pos + 12)? as u64;
+    let mut cd_offset = u32_at(data, eocd_pos + 16)? as u64;
+
+    let (mut zip64_locator_pos, mut zip64_eocd_pos) = (None, None);
+    if eocd_pos >= ZIP64_LOCATOR_SIZE
+        && has_sig(data, eocd_pos - ZIP64_LOCATOR_SIZE, ZIP64_LOCATOR_SIG)
+    {
+        let locator_pos = eocd_pos - ZIP64_LOCATOR_SIZE;
+        let record_pos = usize::try_from(u64_at(data, locator_pos + 8)?)?;
+        if !has_sig(data, record_pos, ZIP64_EOCD_SIG) {
+            return Err(anyhow!("invalid zip64 end of central directory record"));
+        }
+
+        entries = u64_at(data, record_pos + 32)?;
+        cd_size = u64_at(data, record_pos + 40)?;
+        cd_offset = u64_at(data, record_pos + 48)?;
+        zip64_locator_pos = Some(locator_pos);
+        zip64_eocd_pos = Some(record_pos);
+    }
+
+    let cd_offset = usize::try_from(cd_offset)?;
+    let cd_end = zip64_eocd_pos.unwrap_or(eocd_pos);
+    if cd_offset.checked_add(usize::try_from(cd_size)?) != Some(cd_end) {
+        return Err(anyhow!(
+            "unsupported zip layout, central directory is moved"
+        ));
+    }
+
+    let (mut pos, mut entries_end) = (cd_offset, 0);
+    for _ in 0..entries {
+        let (end, header_size) = parse_entry(data, pos)?;
+        entries_end = usize::max(entries_end, end);
+        pos += header_size;
+    }
+
+    if pos != cd_end || entries_end > cd_offset {
+        return Err(anyhow!("invalid zip central directory"));
+    }
+
+    Ok(Directory {
+        eocd_pos,
+        zip64_locator_pos,
+        zip64_eocd_pos,
+        cd_offset,
+        entries_end,
+    })
+}
+
+fn is_payload(gap: &[u8]) -> bool {
+    gap.len() > MAGIC_NUM.len() * 2
+        && gap.starts_with(MAGIC_NUM.as_bytes())
+        && gap.ends_with(MAGIC_NUM.as_bytes())
+}
+
+// insert the payload between the last local entry and the central directory.
+// Only the central directory offset in the (zip64) end of central directory
+// record has to be fixed up, the local entries and their offsets are not moved.
+pub fn embed(carrier: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let dir = parse_directory(carrier)?;
+
+    let mut gap = &carrier[dir.entries_end..dir.cd_offset];
+    if is_payload(gap) {
+        gap = &[];
+    }
+
+    let cd_offset = dir.entries_end + gap.len() + payload.len();
+    let shift = cd_offset as i64 - dir.cd_offset as i64;
+
+    let mut output = Vec::with_capacity(carrier.len() + payload.len());
+    output.extend_from_slice(&carrier[..dir.entries_end]);
+    output.extend_from_slice(gap);
+    output.extend_from_slice(payload);
+    output.extend_from_slice(&carrier[dir.cd_offset..]);
+
+    let new_pos = |pos: usize| (pos as i64 + shift) as usize;
+
+    if let (Some(locator_pos), Some(record_pos)) = (dir.zip64_locator_pos, dir.zip64_eocd_pos) {
+        let (locator_pos, record_pos) = (new_pos(locator_pos), new_pos(record_pos));
+        output[locator_pos + 8..locator_pos + 16]
+            .copy_from_slice(&(record_pos as u64).to_le_bytes());
+        output[record_pos + 48..record_pos + 56].copy_from_slice(&(cd_offset as u64).to_le_bytes());
+    }
+
+    let eocd_pos = new_pos(dir.eocd_pos);
+    if u32_at(carrier, dir.eocd_pos + 16)? as u64 != U32_MAX {
+        if cd_offset as u64 >= U32_MAX {
+            return Err(anyhow!("zip file is too large, zip64 is required"));
+        }
+        output[eocd_pos + 16..eocd_pos + 20].copy_from_slice(&(cd_offset as u32).to_le_bytes());
+    }
+
+    Ok(output)
+}
+
+pub fn extract(data: &[u8]) -> Option<Vec<u8>> {
+    let dir = parse_directory(data).ok()?;
+    let gap = &data[dir.entries_end..dir.cd_offset];
+
+    if is_payload(gap) {
+        Some(gap.to_vec())
+    } else {
+        None
+    }
+}
+
+// the end of central directory record, the central directory and the local
+//  file headers of all the entries are valid
+pub fn verify(data: &[u8]) -> Result<()> {
+    parse_directory(data).map(|_| ())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.zip"))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_zip_parse_directory() -> Result<()> {
+        let carrier = carrier()?;
+        let dir = parse_directory(&carrier)?;
+        assert_eq!(dir.entries_end, dir.cd_offset);
+        assert!(dir.zip64_eocd_pos.is_some());
+        assert!(parse_directory(b"not a zip file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_embed_extract() -> Result<()> {
+        let carrier = carrier()?;
+        assert!(extract(&carrier).is_none());
+
+        let payload = payload(10000);
+        let output = embed(&carrier, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+
+        let src_dir = parse_directory(&carrier)?;
+        let dir = parse_directory(&output)?;
+        assert_eq!(dir.entries_end, src_dir.entries_end);
+        assert_eq!(dir.cd_offset, src_dir.cd_offset + payload.len());
+        assert_eq!(&output[..dir.entries_end], &carrier[..src_dir.entries_end]);
+        assert_eq!(
+            &output[dir.cd_offset..dir.zip64_eocd_pos.unwrap()],
+            &carrier[src_dir.cd_offset..src_dir.zip64_eocd_pos.unwrap()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_embed_replace() -> Result<()> {
+        let carrier = carrier()?;
+        let output = embed(&carrier, &payload(100))?;
+        let payload = payload(50);
+        let output = embed(&output, &payload)?;
+        assert_eq!(extract(&output).unwrap(), payload);
+        assert_eq!(output.len(), carrier.len() + payload.len());
+        Ok(())
+    }
+
+    // the compressed size of the zip64 extra field overflows the end of the entry
+    pub(crate) fn hostile_carrier() -> Vec<u8> {
+        let mut local = LOCAL_HEADER_SIG.to_vec();
+        local.extend_from_slice(&[0; 22]);
+        local.extend_from_slice(&1_u16.to_le_bytes());
+        local.extend_from_slice(&0_u16.to_le_bytes());
+        local.push(b'a');
+
+        // the zip64 extra field only has the compressed size
+        let mut extra = ZIP64_EXTRA_ID.to_le_bytes().to_vec();
+        extra.extend_from_slice(&8_u16.to_le_bytes());
+        extra.extend_from_slice(&(u64::MAX - 10).to_le_bytes());
+
+        let mut central = CENTRAL_HEADER_SIG.to_vec();
+        central.extend_from_slice(&[0; 16]);
+        central.extend_from_slice(&u32::MAX.to_le_bytes());
+        central.extend_from_slice(&0_u32.to_le_bytes());
+        central.extend_from_slice(&1_u16.to_le_bytes());
+        central.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        central.extend_from_slice(&[0; 12]);
+        central.push(b'a');
+        central.extend_from_slice(&extra);
+
+        let mut eocd = EOCD_SIG.to_vec();
+        eocd.extend_from_slice(&[0; 6]);
+        eocd.extend_from_slice(&1_u16.to_le_bytes());
+        eocd.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        eocd.extend_from_slice(&(local.len() as u32).to_le_bytes());
+        eocd.extend_from_slice(&0_u16.to_le_bytes());
+
+        [local, central, eocd].concat()
+    }
+
+    #[test]
+    fn test_zip_compressed_size_overflow() {
+        let data = hostile_carrier();
+        assert!(parse_directory(&data).is_err());
+        assert!(extract(&data).is_none());
+        assert!(verify(&data).is_err());
+    }
+}
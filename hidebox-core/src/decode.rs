@@ -0,0 +1,1144 @@
+use super::error::{HideboxError, Result};
+use super::scan::Location;
+use super::*;
+use crate::util;
+use std::io::{Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+// the limits of the fields which are read from the carrier, the encoder never writes larger values
+const MAX_NAME_SIZE: usize = 255;
+const MAX_CHUNKS: u64 = MAX_FILE_SIZE / CHUNK_SIZE as u64 + 1;
+
+// a chunk contains at least one AES block and at most 4096 + 16 bytes which are hex encoded
+const MIN_CHUNK_TEXT_LEN: usize = 16 * 2 + HASH_TEXT_SIZE;
+const MAX_CHUNK_LEN: usize = (CHUNK_SIZE + 16) * 2 + HASH_TEXT_SIZE;
+
+// the embedded and steganography payloads are parsed in memory, larger carriers are not read
+pub(crate) const MAX_CARRIER_SIZE: u64 = MAX_FILE_SIZE;
+
+#[allow(dead_code)]
+fn get_chunk_from_buffer(buffer: &[u8]) -> Result<Vec<u8>> {
+    if buffer.len() <= MIN_CHUNK_LEN {
+        return Err(HideboxError::corrupt(format!(
+            "buffer is too small, less than {MIN_CHUNK_LEN}"
+        )));
+    }
+
+    let text_len = String::from_utf8_lossy(&buffer[..CHUNK_LEN_SIZE])
+        .trim()
+        .to_string();
+
+    if text_len.is_empty() {
+        return Err(HideboxError::corrupt("chunk length is empty"));
+    }
+
+    let text_len = usize::from_str_radix(&text_len, 16).map_err(HideboxError::corrupt)?;
+
+    if text_len > buffer.len() - CHUNK_LEN_SIZE {
+        return Err(HideboxError::corrupt(
+            "chunk length is larger than buffer length",
+        ));
+    }
+
+    let chunk = &buffer[..CHUNK_LEN_SIZE + text_len].to_vec();
+    Ok(chunk.clone())
+}
+
+fn parse_chunk(password: &str, buffer: &[u8]) -> Result<ChunkSpec> {
+    let encrypt_text = &buffer[CHUNK_LEN_SIZE..buffer.len() - HASH_TEXT_SIZE];
+    let encrypt_text = String::from_utf8_lossy(encrypt_text);
+
+    let hash_text = &buffer[buffer.len() - HASH_TEXT_SIZE..];
+    let hash_text = String::from_utf8_lossy(hash_text);
+
+    if hash_text != util::crypto::hash(&encrypt_text) {
+        return Err(HideboxError::corrupt("invalid chunk checksum"));
+    }
+
+    let data = util::crypto::decrypt(password, &encrypt_text).map_err(HideboxError::corrupt)?;
+    if data.len() > CHUNK_SIZE {
+        return Err(HideboxError::corrupt(
+            "invalid chunk, chunk size is too larger",
+        ));
+    }
+
+    Ok(ChunkSpec { data })
+}
+
+// the payload is appended to the end of the file
+pub async fn has_trailer(file_spec: &FileSpec) -> Result<bool> {
+    if file_spec.size <= MAGIC_NUM.len() as u64 {
+        return Ok(false);
+    }
+
+    let mut magic_buf = vec![0_u8; MAGIC_NUM.len()];
+    let mut file = File::open(&file_spec.path).await?;
+    file.seek(SeekFrom::Start(file_spec.size - MAGIC_NUM.len() as u64))
+        .await?;
+
+    file.read_exact(&mut magic_buf).await.map_err(truncated)?;
+
+    Ok(magic_buf == MAGIC_NUM.as_bytes())
+}
+
+pub async fn has_append_file(file_spec: &FileSpec) -> Result<bool> {
+    Ok(locate_payload(file_spec, MAX_CARRIER_SIZE).await?.is_some())
+}
+
+// where the payload of the file is, the trailer is checked before the extended attributes.
+//  The payload is returned unless it is a trailer, which is not read. The embedded carriers
+//  larger than the limit are not read. The scanner finds the carriers with it too.
+pub(crate) async fn locate_payload(
+    file_spec: &FileSpec,
+    limit: u64,
+) -> Result<Option<(Location, Option<Vec<u8>>)>> {
+    if has_trailer(file_spec).await? {
+        return Ok(Some((Location::Trailer, None)));
+    }
+
+    if let Some(payload) = attr::read(Path::new(&file_spec.path)) {
+        return Ok(Some((Location::Xattr, Some(payload))));
+    }
+
+    let mut file = File::open(&file_spec.path).await?;
+    let payload = match read_carrier(&mut file, limit).await? {
+        Some(data) => backend::extract(&data),
+        None => None,
+    };
+    Ok(payload.map(|payload| (Location::Embedded, Some(payload))))
+}
+
+// read the whole carrier if its type has an embedded or a steganography backend,
+//  the other files can not contain such a payload and are not read
+pub(crate) async fn read_carrier<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    limit: u64,
+) -> Result<Option<Vec<u8>>> {
+    let size = reader.seek(SeekFrom::End(0)).await?;
+
+    let mut head = Vec::with_capacity(sniff::SNIFF_SIZE);
+    reader.seek(SeekFrom::Start(0)).await?;
+    (&mut *reader)
+        .take(sniff::SNIFF_SIZE as u64)
+        .read_to_end(&mut head)
+        .await?;
+
+    let file_type = sniff::sniff(&head);
+    if file_type.backend() == Backend::Trailer && file_type.stego_backend().is_none() {
+        return Ok(None);
+    }
+
+    if size > limit {
+        return Err(HideboxError::too_large(format!(
+            "carrier is larger than {limit} bytes"
+        )));
+    }
+
+    let mut data = Vec::with_capacity(size as usize);
+    reader.seek(SeekFrom::Start(0)).await?;
+    (&mut *reader).take(size).read_to_end(&mut data).await?;
+    Ok(Some(data))
+}
+
+async fn get_hide_spec_data(file_spec: &FileSpec, password: &str) -> Result<HideSpec> {
+    let mut file = File::open(&file_spec.path).await?;
+    read_hide_spec_data(&mut file, file_spec.size, password).await
+}
+
+// the length fields are hex numbers which are padded with spaces
+// a payload which ends before its lengths say is corrupt
+fn truncated(e: std::io::Error) -> HideboxError {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        HideboxError::corrupt("unexpected end of payload")
+    } else {
+        HideboxError::Io(e)
+    }
+}
+
+fn parse_len(buf: &[u8]) -> Result<usize> {
+    let text = String::from_utf8_lossy(buf);
+    let text = text.trim();
+
+    if text.is_empty() || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(HideboxError::corrupt("invalid length field"));
+    }
+
+    usize::from_str_radix(text, 16).map_err(HideboxError::corrupt)
+}
+
+// the hide spec comes from the carrier, so every field is checked against the payload size.
+//  LAYOUT: src_size + MAGIC_NUM + chunks(append_size) + hide_spec_data = spec_start
+fn check_hide_spec(hide_spec: &HideSpec, spec_start: u64) -> Result<()> {
+    if hide_spec.append_name.len() > MAX_NAME_SIZE
+        || hide_spec.append_name.contains(['/', '\\', '\0'])
+    {
+        return Err(HideboxError::corrupt("invalid append file name"));
+    }
+
+    let chunks_end = hide_spec
+        .src_size
+        .checked_add(MAGIC_NUM.len() as u64)
+        .and_then(|v| v.checked_add(hide_spec.append_size));
+
+    if chunks_end != Some(spec_start) {
+        return Err(HideboxError::corrupt(
+            "invalid hide specify data, the sizes do not match",
+        ));
+    }
+
+    if hide_spec.append_size > MAX_CHUNKS * MAX_CHUNK_LEN as u64 {
+        return Err(HideboxError::corrupt(
+            "invalid hide specify data, too many chunks",
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn read_hide_spec_data<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    size: u64,
+    password: &str,
+) -> Result<HideSpec> {
+    let pos_of_end = (CHUNK_LEN_SIZE + MAGIC_NUM.len()) as u64;
+
+    if size <= pos_of_end {
+        return Err(HideboxError::NotACarrier);
+    }
+
+    let mut chunk_len_buf = vec![0_u8; CHUNK_LEN_SIZE];
+    reader.seek(SeekFrom::Start(size - pos_of_end)).await?;
+    reader
+        .read_exact(&mut chunk_len_buf)
+        .await
+        .map_err(truncated)?;
+
+    let chunk_len = parse_len(&chunk_len_buf)?;
+    if chunk_len > CHUNK_SIZE || chunk_len as u64 > size - pos_of_end {
+        return Err(HideboxError::corrupt("invalid hide specify lenght"));
+    }
+
+    let spec_start = size - pos_of_end - chunk_len as u64;
+    let mut hide_spec_data = vec![0; chunk_len];
+    reader.seek(SeekFrom::Start(spec_start)).await?;
+    reader
+        .read_exact(&mut hide_spec_data)
+        .await
+        .map_err(truncated)?;
+
+    let hide_spec_data = String::from_utf8_lossy(&hide_spec_data);
+    let hide_spec_data = match util::crypto::decrypt(password, &hide_spec_data) {
+        Ok(v) => v,
+        Err(_) => return Err(HideboxError::WrongPassword),
+    };
+    let hide_spec_data = String::from_utf8_lossy(&hide_spec_data);
+
+    let hide_spec = match serde_json::from_str(&hide_spec_data) {
+        Ok(v) => v,
+        Err(_) => return Err(HideboxError::WrongPassword),
+    };
+
+    check_hide_spec(&hide_spec, spec_start)?;
+    Ok(hide_spec)
+}
+
+// decode the payload of the file into output_file, the output file is replaced
+//  only if the decoding succeeds.
+pub async fn decode(
+    src_file_spec: FileSpec,
+    output_file: &Path,
+    password: &str,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<DecodeOutcome> {
+    // the trailer is checked before the extended attributes like the scan, the
+    //  password of a trailer is checked before the output is created
+    let is_trailer = has_trailer(&src_file_spec).await?;
+    if is_trailer {
+        get_hide_spec_data(&src_file_spec, password).await?;
+    }
+
+    let output = Output::new(output_file);
+    let mut output_file = output.create().await?;
+
+    let payload = if is_trailer {
+        None
+    } else {
+        attr::read(Path::new(&src_file_spec.path))
+    };
+
+    let outcome = match payload {
+        Some(payload) => {
+            let size = payload.len() as u64;
+            decode_payload(
+                &mut Cursor::new(payload),
+                size,
+                &mut output_file,
+                password,
+                progress_callback,
+                cancel_handle,
+            )
+            .await?
+        }
+        None => {
+            let mut src_file = File::open(&src_file_spec.path).await?;
+            decode_stream(
+                &mut src_file,
+                &mut output_file,
+                password,
+                progress_callback,
+                cancel_handle,
+            )
+            .await?
+        }
+    };
+
+    output_file.flush().await?;
+    output.commit().await?;
+    Ok(outcome)
+}
+
+// decode the payload of the reader into the output writer, the reader is seeked
+//  because the hide spec is at the end of the payload. The extended attributes
+//  of the files are not read.
+pub async fn decode_stream<R, W>(
+    src: &mut R,
+    output: &mut W,
+    password: &str,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<DecodeOutcome>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // the offsets are checked against the real size instead of the given one
+    let size = src.seek(SeekFrom::End(0)).await?;
+
+    if size > MAGIC_NUM.len() as u64 {
+        let mut magic_buf = vec![0_u8; MAGIC_NUM.len()];
+        src.seek(SeekFrom::Start(size - MAGIC_NUM.len() as u64))
+            .await?;
+        src.read_exact(&mut magic_buf).await.map_err(truncated)?;
+
+        if magic_buf == MAGIC_NUM.as_bytes() {
+            return decode_payload(
+                src,
+                size,
+                output,
+                password,
+                progress_callback,
+                cancel_handle,
+            )
+            .await;
+        }
+    }
+
+    let payload = match read_carrier(src, MAX_CARRIER_SIZE).await? {
+        Some(data) => backend::extract(&data).or_else(|| stego::extract(&data, password)),
+        None => None,
+    };
+
+    let Some(payload) = payload else {
+        return Err(HideboxError::NotACarrier);
+    };
+
+    let size = payload.len() as u64;
+    decode_payload(
+        &mut Cursor::new(payload),
+        size,
+        output,
+        password,
+        progress_callback,
+        cancel_handle,
+    )
+    .await
+}
+
+// run the decoding in the background, the job is canceled by its handle
+pub fn spawn(
+    src_file_spec: FileSpec,
+    output_file: PathBuf,
+    password: String,
+    progress_callback: ProgressCb,
+) -> Job<DecodeOutcome> {
+    Job::spawn(move |cancel_handle| async move {
+        decode(
+            src_file_spec,
+            &output_file,
+            &password,
+            progress_callback,
+            &cancel_handle,
+        )
+        .await
+    })
+}
+
+// the reader contains the payload that starts at the hide_spec.src_size position
+//  and ends at the end of the reader
+async fn decode_payload<R: AsyncRead + AsyncSeek + Unpin, W: AsyncWrite + Unpin>(
+    src_file: &mut R,
+    size: u64,
+    output: &mut W,
+    password: &str,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<DecodeOutcome> {
+    let hide_spec = read_hide_spec_data(src_file, size, password).await?;
+
+    src_file.seek(SeekFrom::Start(hide_spec.src_size)).await?;
+
+    let mut magic_buf = vec![0_u8; MAGIC_NUM.len()];
+    src_file
+        .read_exact(&mut magic_buf)
+        .await
+        .map_err(truncated)?;
+    if magic_buf != MAGIC_NUM.as_bytes() {
+        return Err(HideboxError::corrupt(
+            "do not find magic number before append file",
+        ));
+    }
+
+    let mut progress = Progress::new(progress_callback, hide_spec.append_size);
+    progress.phase(Phase::Decrypt);
+
+    let is_done = write_chunks(
+        src_file,
+        output,
+        hide_spec.append_size,
+        password,
+        &mut progress,
+        cancel_handle,
+    )
+    .await?;
+
+    if !is_done {
+        return Err(HideboxError::Cancelled);
+    }
+
+    Ok(DecodeOutcome {
+        append_name: hide_spec.append_name,
+    })
+}
+
+// decrypt the chunks which take exactly append_size bytes from the reader,
+//  return false if the decoding is canceled.
+pub(crate) async fn write_chunks<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    src_file: &mut R,
+    output: &mut W,
+    append_size: u64,
+    password: &str,
+    progress: &mut Progress,
+    cancel_handle: &CancelHandle,
+) -> Result<bool> {
+    let mut current = 0;
+    let mut total_chunks = 0;
+
+    while current < append_size {
+        let mut chunk_len_buf = vec![0; CHUNK_LEN_SIZE];
+        src_file
+            .read_exact(&mut chunk_len_buf)
+            .await
+            .map_err(truncated)?;
+        let chunk_len = parse_len(&chunk_len_buf)?;
+
+        if !(MIN_CHUNK_TEXT_LEN..=MAX_CHUNK_LEN).contains(&chunk_len) {
+            return Err(HideboxError::corrupt(format!(
+                "invalid chunk length, it is not in [{MIN_CHUNK_TEXT_LEN}, {MAX_CHUNK_LEN}]"
+            )));
+        }
+
+        current += (CHUNK_LEN_SIZE + chunk_len) as u64;
+        total_chunks += 1;
+        if current > append_size || total_chunks > MAX_CHUNKS {
+            return Err(HideboxError::corrupt(
+                "invalid chunk, it is out of the append file",
+            ));
+        }
+
+        let mut encrypt_buf = vec![0; chunk_len];
+        src_file
+            .read_exact(&mut encrypt_buf)
+            .await
+            .map_err(truncated)?;
+
+        let mut chunk_buf = Vec::with_capacity(CHUNK_LEN_SIZE + chunk_len);
+        chunk_buf.append(&mut chunk_len_buf);
+        chunk_buf.append(&mut encrypt_buf);
+
+        let chunk_spec = parse_chunk(password, &chunk_buf)?;
+        output.write_all(&chunk_spec.data).await?;
+        progress.advance(CHUNK_LEN_SIZE + chunk_len);
+
+        if cancel_handle.is_cancelled() {
+            return Ok(false);
+        }
+    }
+
+    progress.finish();
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::encode::make_chunk;
+    use super::*;
+    use anyhow::Result;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use std::env;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const PASSWORD: &str = "123456";
+
+    #[test]
+    fn test_file_get_chunk_from_buffer() -> Result<()> {
+        let buffer = util::str::random_string(CHUNK_SIZE);
+        let chunk = make_chunk(PASSWORD, buffer.as_bytes())?;
+        let chunk_2 = get_chunk_from_buffer(chunk.as_slice())?;
+        assert_eq!(chunk, chunk_2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_parse_chunk() -> Result<()> {
+        let buffer = util::str::random_string(CHUNK_SIZE);
+        let chunk = make_chunk(PASSWORD, buffer.as_bytes())?;
+        let chunk_2 = get_chunk_from_buffer(chunk.as_slice())?;
+        assert_eq!(chunk, chunk_2);
+
+        let cs = parse_chunk(PASSWORD, chunk_2.as_slice())?;
+        assert_eq!(cs.data, buffer.as_bytes());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_has_append_file() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/src.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "src.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        assert_eq!(has_append_file(&src_spec).await?, false);
+
+        let dst_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-less-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let dst_file = File::open(&dst_file_path).await?;
+        let dst_meta = dst_file.metadata().await?;
+        let dst_spec = FileSpec {
+            path: dst_file_path.clone(),
+            name: "dst-less-than-4k.dat".to_string(),
+            size: dst_meta.len(),
+        };
+
+        assert_eq!(has_append_file(&dst_spec).await?, true);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_get_hide_spec_data() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/src.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "src.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        assert!(get_hide_spec_data(&src_spec, PASSWORD).await.is_err());
+
+        let dst_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-less-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let dst_file = File::open(&dst_file_path).await?;
+        let dst_meta = dst_file.metadata().await?;
+        let dst_spec = FileSpec {
+            path: dst_file_path.clone(),
+            name: "dst-less-than-4k.dat".to_string(),
+            size: dst_meta.len(),
+        };
+
+        let file_spec = get_hide_spec_data(&dst_spec, PASSWORD).await?;
+        println!("{file_spec:?}");
+        assert!(file_spec.src_size > 0);
+        assert!(file_spec.append_size > 0);
+        assert_eq!(file_spec.append_name, "append-less-than-4k.dat");
+
+        Ok(())
+    }
+
+    fn pcb(info: ProgressInfo) {
+        println!("progress: {}", info.percent());
+    }
+
+    // LAYOUT: MAGIC_NUM + chunks + hide_spec_data + hide_spec_data_len(8 bytes) + MAGIC_NUM
+    fn make_payload(data: &[u8], hide_spec: &HideSpec) -> Result<Vec<u8>> {
+        let mut payload = MAGIC_NUM.as_bytes().to_vec();
+        for chunk in data.chunks(CHUNK_SIZE) {
+            payload.extend(make_chunk(PASSWORD, chunk)?);
+        }
+
+        let hide_spec_data = serde_json::to_string(hide_spec)?;
+        let hide_spec_data = util::crypto::encrypt(PASSWORD, hide_spec_data.as_bytes())?;
+        payload.extend(hide_spec_data.as_bytes());
+        payload.extend(format!("{:8x}", hide_spec_data.len()).as_bytes());
+        payload.extend(MAGIC_NUM.as_bytes());
+        Ok(payload)
+    }
+
+    fn valid_payload(data: &[u8]) -> Result<(Vec<u8>, HideSpec)> {
+        let mut chunks_len = 0;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            chunks_len += make_chunk(PASSWORD, chunk)?.len();
+        }
+
+        let hide_spec = HideSpec {
+            append_name: "append.dat".to_string(),
+            append_size: chunks_len as u64,
+            src_size: 0,
+        };
+        Ok((make_payload(data, &hide_spec)?, hide_spec))
+    }
+
+    // flip, truncate, insert and overwrite the bytes with the hex digits and spaces,
+    //  so that the length fields get hostile values
+    fn mutate(rng: &mut ChaCha20Rng, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        let pos = rng.gen_range(0..data.len());
+
+        match rng.gen_range(0..4) {
+            0 => {
+                for _ in 0..rng.gen_range(1..8) {
+                    let pos = rng.gen_range(0..data.len());
+                    data[pos] ^= rng.gen_range(1..=255);
+                }
+            }
+            1 => data.truncate(pos),
+            2 => {
+                let bytes = (0..rng.gen_range(1..64))
+                    .map(|_| rng.gen())
+                    .collect::<Vec<u8>>();
+                data.splice(pos..pos, bytes);
+            }
+            _ => {
+                let digits = b"0123456789abcdefABCDEF +-";
+                for i in pos..usize::min(pos + CHUNK_LEN_SIZE, data.len()) {
+                    data[i] = digits[rng.gen_range(0..digits.len())];
+                }
+            }
+        }
+
+        data
+    }
+
+    #[tokio::test]
+    async fn test_file_hostile_hide_spec() -> Result<()> {
+        let data = util::str::random_string(100);
+        let (payload, hide_spec) = valid_payload(data.as_bytes())?;
+        let size = payload.len() as u64;
+        assert!(
+            read_hide_spec_data(&mut Cursor::new(&payload), size, PASSWORD)
+                .await
+                .is_ok()
+        );
+
+        let hostile_specs = [
+            HideSpec {
+                src_size: u64::MAX,
+                ..hide_spec.clone()
+            },
+            HideSpec {
+                append_size: u64::MAX,
+                ..hide_spec.clone()
+            },
+            HideSpec {
+                src_size: size,
+                ..hide_spec.clone()
+            },
+            HideSpec {
+                append_name: "a".repeat(MAX_NAME_SIZE + 1),
+                ..hide_spec.clone()
+            },
+            HideSpec {
+                append_name: "../append.dat".to_string(),
+                ..hide_spec.clone()
+            },
+        ];
+
+        for hostile_spec in hostile_specs {
+            let payload = make_payload(data.as_bytes(), &hostile_spec)?;
+            let size = payload.len() as u64;
+            assert!(
+                read_hide_spec_data(&mut Cursor::new(payload), size, PASSWORD)
+                    .await
+                    .is_err()
+            );
+        }
+
+        // the size is larger than the payload
+        assert!(
+            read_hide_spec_data(&mut Cursor::new(&payload), size * 2, PASSWORD)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fuzz_hide_spec_data() -> Result<()> {
+        let mut rng = ChaCha20Rng::seed_from_u64(0x4849_4445);
+        let (payload, _) = valid_payload(util::str::random_string(100).as_bytes())?;
+
+        // only the tail of the payload is read
+        let tail = payload.len() - 200;
+        for _ in 0..2000 {
+            let data = [&payload[..tail], &mutate(&mut rng, &payload[tail..])].concat();
+            crate::fuzz::hide_spec_data(&data).await;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fuzz_chunk_loop() -> Result<()> {
+        let mut rng = ChaCha20Rng::seed_from_u64(0x4348_554E);
+        let data = util::str::random_string(CHUNK_SIZE * 2 + 100);
+        let (payload, hide_spec) = valid_payload(data.as_bytes())?;
+        let chunks = &payload[MAGIC_NUM.len()..MAGIC_NUM.len() + hide_spec.append_size as usize];
+
+        let mut output = vec![];
+        let is_done = write_chunks(
+            &mut Cursor::new(chunks),
+            &mut output,
+            hide_spec.append_size,
+            PASSWORD,
+            &mut Progress::new(Arc::new(pcb), hide_spec.append_size),
+            &CancelHandle::new(),
+        )
+        .await?;
+        assert!(is_done);
+        assert_eq!(output, data.as_bytes());
+
+        for _ in 0..300 {
+            crate::fuzz::chunk_loop(&mutate(&mut rng, chunks)).await;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fuzz_stego_extract() -> Result<()> {
+        let mut rng = ChaCha20Rng::seed_from_u64(0x4C53_4200);
+        let cur_dir = env::current_dir()?;
+
+        // the headers decide the image size, the pixels are not mutated
+        for name in ["src.bmp", "src.png"] {
+            let carrier = std::fs::read(cur_dir.join("../testdata").join(name))?;
+            for _ in 0..300 {
+                let data = [&mutate(&mut rng, &carrier[..64]), &carrier[64..]].concat();
+                crate::fuzz::stego_extract(&data);
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_decode_less_than_4k() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-less-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "dst-less-than-4k.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        let output_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-less-than-4k-decode.dat");
+
+        decode(
+            src_spec,
+            output_file_path.as_path(),
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let src_string =
+            tokio::fs::read(cur_dir.clone().join("../testdata/append-less-than-4k.dat")).await?;
+        let dst_string = tokio::fs::read(output_file_path).await?;
+        assert_eq!(src_string, dst_string);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_decode_more_than_4k() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-more-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "dst-more-than-4k.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        let output_file_path = cur_dir
+            .clone()
+            .join("../testdata/dst-more-than-4k-decode.dat");
+
+        decode(
+            src_spec,
+            output_file_path.as_path(),
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let src_string =
+            tokio::fs::read(cur_dir.clone().join("../testdata/append-more-than-4k.dat")).await?;
+        let dst_string = tokio::fs::read(output_file_path).await?;
+        assert_eq!(src_string, dst_string);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_decode_wrong_password() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+        let src_file_path = cur_dir.join("../testdata/dst-less-than-4k.dat");
+        let src_spec = FileSpec {
+            path: src_file_path.to_str().unwrap().to_string(),
+            name: "dst-less-than-4k.dat".to_string(),
+            size: tokio::fs::metadata(&src_file_path).await?.len(),
+        };
+
+        let password = "not-the-password";
+        let output_file_path = env::temp_dir().join("hidebox-wrong-password.dat");
+        let e = decode(
+            src_spec,
+            &output_file_path,
+            password,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(e, HideboxError::WrongPassword));
+        assert!(!e.to_string().contains(password));
+        Ok(())
+    }
+
+    // encode the carrier with the backend and decode it again, return the encoded file
+    async fn encode_decode(src_name: &str, backend: Backend) -> Result<PathBuf> {
+        encode_decode_with(src_name, "append-more-than-4k.dat", backend).await
+    }
+
+    async fn encode_decode_with(
+        src_name: &str,
+        append_name: &str,
+        backend: Backend,
+    ) -> Result<PathBuf> {
+        let cur_dir = env::current_dir()?;
+        let src_file_path = cur_dir.join("../testdata").join(src_name);
+        let append_file_path = cur_dir.join("../testdata").join(append_name);
+        let dst_file_path = env::temp_dir().join(format!("hidebox-dst-{src_name}"));
+        let output_file_path = env::temp_dir().join(format!("hidebox-dst-{src_name}-decode.dat"));
+
+        let src_spec = FileSpec {
+            path: src_file_path.to_str().unwrap().to_string(),
+            name: src_name.to_string(),
+            size: tokio::fs::metadata(&src_file_path).await?.len(),
+        };
+
+        let append_spec = FileSpec {
+            path: append_file_path.to_str().unwrap().to_string(),
+            name: append_name.to_string(),
+            size: tokio::fs::metadata(&append_file_path).await?.len(),
+        };
+
+        super::super::encode::encode(
+            src_spec,
+            append_spec,
+            dst_file_path.as_path(),
+            PASSWORD,
+            backend,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let dst_spec = FileSpec {
+            path: dst_file_path.to_str().unwrap().to_string(),
+            name: format!("hidebox-dst-{src_name}"),
+            size: tokio::fs::metadata(&dst_file_path).await?.len(),
+        };
+
+        assert!(!has_trailer(&dst_spec).await?);
+
+        // the steganography payload can not be found without the password
+        assert_eq!(has_append_file(&dst_spec).await?, !backend.is_stego());
+
+        decode(
+            dst_spec,
+            output_file_path.as_path(),
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let src_string = tokio::fs::read(append_file_path).await?;
+        let dst_string = tokio::fs::read(output_file_path).await?;
+        assert_eq!(src_string, dst_string);
+
+        Ok(dst_file_path)
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_jpeg_segment() -> Result<()> {
+        encode_decode("src.jpg", Backend::JpegSegment(backend::jpeg::Marker::Com)).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_zip() -> Result<()> {
+        encode_decode("src.zip", Backend::Zip).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_pdf() -> Result<()> {
+        encode_decode("src.pdf", Backend::Pdf).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_mp4() -> Result<()> {
+        encode_decode("src.mp4", Backend::Mp4).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_elf() -> Result<()> {
+        encode_decode("src.elf", Backend::Elf).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_riff() -> Result<()> {
+        encode_decode("src.wav", Backend::Riff).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_markup() -> Result<()> {
+        encode_decode("src.svg", Backend::Markup).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_xattr() -> Result<()> {
+        let dst_file_path =
+            encode_decode_with("src.dat", "append-less-than-4k.dat", Backend::Xattr).await?;
+
+        // the file content is not changed
+        let src = tokio::fs::read(env::current_dir()?.join("../testdata/src.dat")).await?;
+        assert_eq!(tokio::fs::read(&dst_file_path).await?, src);
+        assert!(attr::strip(&dst_file_path)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_lsb() -> Result<()> {
+        let dst_file_path = encode_decode("src.png", Backend::Lsb).await?;
+        let data = tokio::fs::read(dst_file_path).await?;
+        assert!(backend::extract(&data).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_dct() -> Result<()> {
+        let dst_file_path =
+            encode_decode_with("src-dct.jpg", "append-less-than-4k.dat", Backend::Dct).await?;
+        let data = tokio::fs::read(dst_file_path).await?;
+        assert!(backend::extract(&data).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_pcm() -> Result<()> {
+        let dst_file_path =
+            encode_decode_with("src-pcm.wav", "append-less-than-4k.dat", Backend::Pcm).await?;
+        let data = tokio::fs::read(dst_file_path).await?;
+        assert!(backend::extract(&data).is_none());
+
+        // the append file is larger than the capacity of the carrier
+        assert!(encode_decode("src-pcm.wav", Backend::Pcm).await.is_err());
+        Ok(())
+    }
+
+    async fn encode_decode_stream(src_name: &str, backend: Backend) -> Result<()> {
+        let testdata = env::current_dir()?.join("../testdata");
+        let carrier = tokio::fs::read(testdata.join(src_name)).await?;
+        let append = tokio::fs::read(testdata.join("append-more-than-4k.dat")).await?;
+
+        // the pipe returns less bytes than a chunk and its size is unknown
+        let (mut writer, reader) = tokio::io::duplex(100);
+        let data = append.clone();
+        tokio::spawn(async move { writer.write_all(&data).await });
+
+        let mut output = vec![];
+        super::super::encode::encode_stream(
+            StreamSpec {
+                reader: Cursor::new(carrier),
+                name: src_name.to_string(),
+                size: None,
+            },
+            StreamSpec {
+                reader,
+                name: "stdin".to_string(),
+                size: None,
+            },
+            &mut output,
+            PASSWORD,
+            backend,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let mut decode_output = vec![];
+        let outcome = decode_stream(
+            &mut Cursor::new(output),
+            &mut decode_output,
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        assert_eq!(outcome.append_name, "stdin");
+        assert_eq!(decode_output, append);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_decode_stream() -> Result<()> {
+        encode_decode_stream("src.dat", Backend::Trailer).await?;
+        encode_decode_stream("src.zip", Backend::Zip).await?;
+
+        let e = encode_decode_stream("src.dat", Backend::Xattr)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<HideboxError>(),
+            Some(HideboxError::Unsupported(_))
+        ));
+
+        let e = decode_stream(
+            &mut Cursor::new(b"not a carrier".to_vec()),
+            &mut vec![],
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, HideboxError::NotACarrier));
+        Ok(())
+    }
+
+    // the carriers which crashed the backend parsers are rejected by every path that reads them
+    #[tokio::test]
+    async fn test_file_hostile_carriers() -> Result<()> {
+        let carriers = [
+            backend::mp4::tests::hostile_carrier(),
+            backend::zip::tests::hostile_carrier(),
+            backend::pdf::tests::hostile_carrier("[", "]"),
+            backend::pdf::tests::hostile_carrier("<< /B ", " >>"),
+            backend::elf::tests::hostile_carrier()?,
+        ];
+
+        for (i, carrier) in carriers.iter().enumerate() {
+            crate::fuzz::backend_extract(carrier);
+
+            let path = env::temp_dir().join(format!("hidebox-hostile-{i}.dat"));
+            tokio::fs::write(&path, carrier).await?;
+            let spec = FileSpec {
+                path: path.to_str().unwrap().to_string(),
+                size: carrier.len() as u64,
+                ..Default::default()
+            };
+            assert!(!has_append_file(&spec).await?);
+
+            let e = decode_stream(
+                &mut Cursor::new(carrier),
+                &mut vec![],
+                PASSWORD,
+                Arc::new(pcb),
+                &CancelHandle::new(),
+            )
+            .await
+            .unwrap_err();
+            assert!(matches!(e, HideboxError::NotACarrier));
+        }
+
+        Ok(())
+    }
+}
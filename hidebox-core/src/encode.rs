@@ -0,0 +1,820 @@
+use super::error::{HideboxError, Result};
+use super::*;
+use crate::util;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+fn hex_str(num: u64) -> Option<String> {
+    if num > MAX_FILE_SIZE {
+        None
+    } else {
+        Some(format!("{:8x}", num))
+    }
+}
+
+// LAYOUT: data_len(8 bytes) + encrypt_text + hash_text(32 bytes);
+//  data_len = encrypt_text.len + hash_text.len;
+//  Note: buffer.len >= 4096
+pub fn make_chunk(password: &str, buffer: &[u8]) -> Result<Vec<u8>> {
+    let encrypt_text = util::crypto::encrypt(password, buffer).map_err(HideboxError::too_large)?;
+    let hash_text = util::crypto::hash(&encrypt_text);
+    let text_len = encrypt_text.len() + hash_text.len();
+    let hex_text_len = match hex_str(text_len as u64) {
+        Some(v) => v,
+        None => return Err(HideboxError::too_large("buffer is too large")),
+    };
+
+    let mut chunk = Vec::with_capacity(hex_text_len.len() + text_len);
+    chunk.extend_from_slice(hex_text_len.as_bytes());
+    chunk.extend_from_slice(encrypt_text.as_bytes());
+    chunk.extend_from_slice(hash_text.as_bytes());
+
+    Ok(chunk)
+}
+
+// the progress total is unknown if any of the sizes is unknown
+fn total_size(sizes: &[Option<u64>]) -> u64 {
+    sizes
+        .iter()
+        .copied()
+        .sum::<Option<u64>>()
+        .unwrap_or_default()
+}
+
+// write the carrier file and the payload into output_file.
+//  The trailer backend appends the payload to the end of the carrier file,
+//  the xattr backend stores the payload in the extended attributes of output_file,
+//  the other backends embed the payload into the carrier file structure.
+//  The output file is checked by the validator of its format after it is written,
+//  it is replaced only if the encoding and the check succeed, so it can be the carrier file.
+//  A carrier which is already broken is written but not verified.
+//  The output file takes the permissions and the extended attributes of the carrier file.
+pub async fn encode(
+    src_file_spec: FileSpec,
+    append_file_spec: FileSpec,
+    output_file: &Path,
+    password: &str,
+    backend: Backend,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<EncodeOutcome> {
+    let total = src_file_spec.size + append_file_spec.size;
+    log::debug!(
+        "src-size:{} append-size:{} total:{} backend:{:?}",
+        src_file_spec.size,
+        append_file_spec.size,
+        total,
+        backend
+    );
+
+    let mut progress = Progress::new(progress_callback, total);
+    let mut src_file = File::open(&src_file_spec.path).await?;
+    let mut append_file = File::open(&append_file_spec.path).await?;
+    let carrier = StreamSpec {
+        reader: &mut src_file,
+        name: src_file_spec.name,
+        size: Some(src_file_spec.size),
+    };
+    let append = StreamSpec {
+        reader: &mut append_file,
+        name: append_file_spec.name,
+        size: Some(append_file_spec.size),
+    };
+
+    let src_path = PathBuf::from(&src_file_spec.path);
+    let output = Output::new(output_file).with_attrs_of(&src_path);
+    let mut output_file = output.create().await?;
+    let mut outcome = if backend == Backend::Xattr {
+        progress.phase(Phase::CopyCarrier);
+        copy_carrier(
+            carrier.reader,
+            &mut output_file,
+            &mut progress,
+            cancel_handle,
+        )
+        .await?;
+        let payload = read_payload(append, password, &mut progress, cancel_handle).await?;
+
+        // the rename keeps the extended attributes of the temporary file
+        progress.phase(Phase::Embed);
+        attr::write(output.tmp_path(), &payload)?;
+        EncodeOutcome::default()
+    } else {
+        write_carrier(
+            carrier,
+            append,
+            &mut output_file,
+            password,
+            backend,
+            &mut progress,
+            cancel_handle,
+        )
+        .await?
+    };
+    output_file.flush().await?;
+
+    // a broken carrier is reported before the commit, so it never replaces the output file.
+    //  The embedded carriers are verified in memory and the xattr backend does not change
+    //  the content, so only the trailer is read back.
+    progress.phase(Phase::Verify);
+    if backend == Backend::Trailer {
+        outcome.is_verified = verify_output(output.tmp_path(), &src_path).await?;
+    }
+    output.commit().await?;
+
+    progress.finish();
+    Ok(outcome)
+}
+
+// only the header is read if the type of the output file has no validator or the file
+//  is too large to be read. The broken output is an error only if the carrier is valid,
+//  so the carrier is read only then.
+async fn verify_output(path: &Path, carrier_path: &Path) -> Result<bool> {
+    let mut head = Vec::with_capacity(sniff::SNIFF_SIZE);
+    let file = File::open(path).await?;
+    let size = file.metadata().await?.len();
+    file.take(sniff::SNIFF_SIZE as u64)
+        .read_to_end(&mut head)
+        .await?;
+
+    if !verify::has_validator(sniff::sniff(&head)) || size > MAX_FILE_SIZE {
+        return Ok(false);
+    }
+
+    let e = match verify::verify(&tokio::fs::read(path).await?) {
+        Ok(v) => return Ok(v),
+        Err(e) => e,
+    };
+
+    match verify::verify(&tokio::fs::read(carrier_path).await?) {
+        Ok(_) => Err(HideboxError::BrokenCarrier(e.to_string())),
+        Err(_) => Ok(false),
+    }
+}
+
+// write the carrier and the payload into the output writer, the sizes of the readers
+//  can be unknown. The xattr backend is not supported because the writer is not a file.
+//  The output of the embedded backends is verified in memory, the output of the trailer
+//  backend is not verified, it is left to the consumer of the writer.
+pub async fn encode_stream<C, A, W>(
+    carrier: StreamSpec<C>,
+    append: StreamSpec<A>,
+    output: &mut W,
+    password: &str,
+    backend: Backend,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<EncodeOutcome>
+where
+    C: AsyncRead + Unpin,
+    A: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if backend == Backend::Xattr {
+        return Err(HideboxError::unsupported(
+            "the xattr backend needs an output file",
+        ));
+    }
+
+    let total = total_size(&[carrier.size, append.size]);
+    let mut progress = Progress::new(progress_callback, total);
+    let outcome = write_carrier(
+        carrier,
+        append,
+        output,
+        password,
+        backend,
+        &mut progress,
+        cancel_handle,
+    )
+    .await?;
+    output.flush().await?;
+
+    progress.finish();
+    Ok(outcome)
+}
+
+// run the encoding in the background, the job is canceled by its handle
+pub fn spawn(
+    src_file_spec: FileSpec,
+    append_file_spec: FileSpec,
+    output_file: PathBuf,
+    password: String,
+    backend: Backend,
+    progress_callback: ProgressCb,
+) -> Job<EncodeOutcome> {
+    Job::spawn(move |cancel_handle| async move {
+        encode(
+            src_file_spec,
+            append_file_spec,
+            &output_file,
+            &password,
+            backend,
+            progress_callback,
+            &cancel_handle,
+        )
+        .await
+    })
+}
+
+// write the carrier and the payload with the backends except the xattr backend
+async fn write_carrier<C, A, W>(
+    mut carrier: StreamSpec<C>,
+    mut append: StreamSpec<A>,
+    output: &mut W,
+    password: &str,
+    backend: Backend,
+    progress: &mut Progress,
+    cancel_handle: &CancelHandle,
+) -> Result<EncodeOutcome>
+where
+    C: AsyncRead + Unpin,
+    A: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut outcome = EncodeOutcome::default();
+    progress.phase(Phase::CopyCarrier);
+
+    if backend == Backend::Trailer {
+        let src_size = copy_carrier(&mut carrier.reader, output, progress, cancel_handle).await?;
+        let append_size = write_payload(
+            output,
+            &mut append.reader,
+            append.name,
+            src_size,
+            password,
+            progress,
+            cancel_handle,
+        )
+        .await?;
+
+        if append_size.is_none() {
+            return Err(HideboxError::Cancelled);
+        }
+
+        progress.phase(Phase::WriteTrailer);
+        return Ok(outcome);
+    }
+
+    // the embedded carriers are parsed in memory, the larger carriers are not read
+    let too_large =
+        || HideboxError::too_large(format!("carrier is larger than {MAX_FILE_SIZE} bytes"));
+    if carrier.size.is_some_and(|size| size > MAX_FILE_SIZE) {
+        return Err(too_large());
+    }
+
+    let mut data = Vec::with_capacity(carrier.size.unwrap_or_default() as usize);
+    let mut reader = (&mut carrier.reader).take(MAX_FILE_SIZE + 1);
+    if copy_carrier(&mut reader, &mut data, progress, cancel_handle).await? > MAX_FILE_SIZE {
+        return Err(too_large());
+    }
+
+    // the carrier is validated before the embedding, so a broken output is an error
+    //  only if the embedding broke it
+    let is_valid_carrier = verify::verify(&data).unwrap_or(false);
+
+    // check the capacity before the append file is encrypted if its size is known
+    let max_append_size = if backend.is_stego() {
+        let capacity = stego::capacity(backend, &data).map_err(HideboxError::unsupported)?;
+        Some(stego::max_append_size(capacity) as u64)
+    } else {
+        None
+    };
+    let check_size = |size: u64| match max_append_size {
+        Some(max_size) if size > max_size => Err(HideboxError::too_large(format!(
+            "append file is too large, the max size for the carrier is {max_size} bytes"
+        ))),
+        _ => Ok(()),
+    };
+    check_size(append.size.unwrap_or_default())?;
+
+    let mut payload = vec![];
+    let append_size = write_payload(
+        &mut payload,
+        &mut append.reader,
+        append.name,
+        0,
+        password,
+        progress,
+        cancel_handle,
+    )
+    .await?;
+
+    let Some(append_size) = append_size else {
+        return Err(HideboxError::Cancelled);
+    };
+    check_size(append_size)?;
+
+    progress.phase(Phase::Embed);
+    let data = if backend.is_stego() {
+        let (data, report) =
+            stego::embed(backend, &data, &payload, password).map_err(HideboxError::unsupported)?;
+        outcome.report = Some(report);
+        data
+    } else {
+        backend::embed(backend, &data, &payload).map_err(HideboxError::unsupported)?
+    };
+
+    if is_valid_carrier {
+        verify::verify(&data).map_err(|e| HideboxError::BrokenCarrier(e.to_string()))?;
+        outcome.is_verified = true;
+    }
+    output.write_all(&data).await?;
+
+    Ok(outcome)
+}
+
+// copy the carrier into the output and return its size
+async fn copy_carrier<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    reader: &mut R,
+    output: &mut W,
+    progress: &mut Progress,
+    cancel_handle: &CancelHandle,
+) -> Result<u64> {
+    let mut size = 0;
+    let mut buf = [0; CHUNK_SIZE];
+
+    loop {
+        let len = reader.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+
+        output.write_all(&buf[0..len]).await?;
+        progress.advance(len);
+        size += len as u64;
+
+        if cancel_handle.is_cancelled() {
+            return Err(HideboxError::Cancelled);
+        }
+    }
+
+    Ok(size)
+}
+
+// the payload of the backends which do not write it after the carrier
+async fn read_payload<A: AsyncRead + Unpin>(
+    mut append: StreamSpec<A>,
+    password: &str,
+    progress: &mut Progress,
+    cancel_handle: &CancelHandle,
+) -> Result<Vec<u8>> {
+    let mut payload = vec![];
+    let append_size = write_payload(
+        &mut payload,
+        &mut append.reader,
+        append.name,
+        0,
+        password,
+        progress,
+        cancel_handle,
+    )
+    .await?;
+
+    match append_size {
+        Some(_) => Ok(payload),
+        None => Err(HideboxError::Cancelled),
+    }
+}
+
+// fill the buffer unless the reader ends, the pipes return less bytes than they have
+async fn read_full<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        let n = reader.read(&mut buf[len..]).await?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+
+    Ok(len)
+}
+
+// LAYOUT: MAGIC_NUM + chunks + hide_spec_data + hide_spec_data_len(8 bytes) + MAGIC_NUM;
+//  src_size is the position of the first MAGIC_NUM in the output file.
+//  return the size of the append file, or none if the encoding is canceled.
+async fn write_payload<W: AsyncWrite + Unpin, A: AsyncRead + Unpin>(
+    output: &mut W,
+    append_file: &mut A,
+    append_name: String,
+    src_size: u64,
+    password: &str,
+    progress: &mut Progress,
+    cancel_handle: &CancelHandle,
+) -> Result<Option<u64>> {
+    progress.phase(Phase::Encrypt);
+    output.write_all(MAGIC_NUM.as_bytes()).await?;
+
+    let mut append_total_size = 0;
+    let mut append_encrypt_total_size = 0;
+    let mut buf = [0; CHUNK_SIZE];
+
+    // write append file
+    loop {
+        let len = read_full(append_file, &mut buf).await?;
+        if len == 0 {
+            break;
+        }
+
+        let encrypt_buf = make_chunk(password, &buf[0..len])?;
+        output.write_all(&encrypt_buf).await?;
+
+        progress.advance(len);
+        append_total_size += len as u64;
+        append_encrypt_total_size += encrypt_buf.len();
+
+        if append_encrypt_total_size as u64 > MAX_FILE_SIZE {
+            return Err(HideboxError::too_large("append file is too big"));
+        }
+
+        if cancel_handle.is_cancelled() {
+            return Ok(None);
+        }
+
+        // the last chunk of the file is written
+        if len < buf.len() {
+            break;
+        }
+    }
+
+    let hide_spec = HideSpec {
+        append_name,
+        append_size: append_encrypt_total_size as u64,
+        src_size,
+    };
+    let hide_spec_data = serde_json::to_string(&hide_spec).map_err(HideboxError::corrupt)?;
+    let hide_spec_data = util::crypto::encrypt(password, hide_spec_data.as_bytes())
+        .map_err(HideboxError::too_large)?;
+    let hide_spec_data_len = hex_str(hide_spec_data.len() as u64).unwrap();
+
+    output.write_all(hide_spec_data.as_bytes()).await?;
+    output.write_all(hide_spec_data_len.as_bytes()).await?;
+    output.write_all(MAGIC_NUM.as_bytes()).await?;
+
+    Ok(Some(append_total_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::env;
+    use std::sync::Arc;
+
+    const PASSWORD: &str = "123456";
+
+    #[test]
+    fn test_file_hex_str() {
+        let s = super::hex_str(MAX_FILE_SIZE - 1);
+        assert!(s.is_some());
+    }
+
+    #[test]
+    fn test_file_make_chunk() -> Result<()> {
+        let buffer = util::str::random_string(CHUNK_SIZE);
+        let chunk = make_chunk(PASSWORD, buffer.as_bytes())?;
+        let chunk_str = String::from_utf8_lossy(chunk.as_slice());
+        assert!(chunk_str.len() > CHUNK_SIZE);
+        Ok(())
+    }
+
+    fn pcb(info: ProgressInfo) {
+        println!("{:?} {}", info.phase, info.percent());
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_less_than_4k() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/src.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let append_file_path = cur_dir
+            .clone()
+            .join("../testdata/append-less-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let dst_file_path = cur_dir.clone().join("../testdata/dst-less-than-4k.dat");
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "src.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        let append_file = File::open(&append_file_path).await?;
+        let append_meta = append_file.metadata().await?;
+        let append_spec = FileSpec {
+            path: append_file_path.clone(),
+            name: "append-less-than-4k.dat".to_string(),
+            size: append_meta.len(),
+        };
+
+        encode(
+            src_spec,
+            append_spec,
+            dst_file_path.as_path(),
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_more_than_4k() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+
+        let src_file_path = cur_dir
+            .clone()
+            .join("../testdata/src.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let append_file_path = cur_dir
+            .clone()
+            .join("../testdata/append-more-than-4k.dat")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let dst_file_path = cur_dir.clone().join("../testdata/dst-more-than-4k.dat");
+
+        println!("{src_file_path}");
+
+        let src_file = File::open(&src_file_path).await?;
+        let src_meta = src_file.metadata().await?;
+        let src_spec = FileSpec {
+            path: src_file_path.clone(),
+            name: "src.dat".to_string(),
+            size: src_meta.len(),
+        };
+
+        let append_file = File::open(&append_file_path).await?;
+        let append_meta = append_file.metadata().await?;
+        let append_spec = FileSpec {
+            path: append_file_path.clone(),
+            name: "append-more-than-4k.dat".to_string(),
+            size: append_meta.len(),
+        };
+
+        encode(
+            src_spec,
+            append_spec,
+            dst_file_path.as_path(),
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_concurrent_jobs() -> Result<()> {
+        let file_spec = |path: PathBuf| -> Result<FileSpec> {
+            Ok(FileSpec {
+                size: std::fs::metadata(&path)?.len(),
+                name: path.file_name().unwrap().to_str().unwrap().to_string(),
+                path: path.to_str().unwrap().to_string(),
+            })
+        };
+
+        let testdata = env::current_dir()?.join("../testdata");
+        let (src_path, append_path) = (
+            testdata.join("src.dat"),
+            testdata.join("append-more-than-4k.dat"),
+        );
+        let canceled_path = env::temp_dir().join("hidebox-job-canceled.dat");
+        let done_path = env::temp_dir().join("hidebox-job-done.dat");
+
+        let canceled_job = spawn(
+            file_spec(src_path.clone())?,
+            file_spec(append_path.clone())?,
+            canceled_path,
+            PASSWORD.to_string(),
+            Backend::Trailer,
+            Arc::new(pcb),
+        );
+        let done_job = spawn(
+            file_spec(src_path)?,
+            file_spec(append_path.clone())?,
+            done_path.clone(),
+            PASSWORD.to_string(),
+            Backend::Trailer,
+            Arc::new(pcb),
+        );
+
+        // the jobs do not start before the test yields on the current thread runtime
+        canceled_job.cancel_handle().cancel();
+        assert!(matches!(
+            canceled_job.wait().await,
+            Err(HideboxError::Cancelled)
+        ));
+        done_job.wait().await?;
+
+        let decode_path = env::temp_dir().join("hidebox-job-decode.dat");
+        decode::spawn(
+            file_spec(done_path)?,
+            decode_path.clone(),
+            PASSWORD.to_string(),
+            Arc::new(pcb),
+        )
+        .wait()
+        .await?;
+
+        assert_eq!(std::fs::read(decode_path)?, std::fs::read(append_path)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_in_place_and_cancel() -> Result<()> {
+        let dir = env::temp_dir().join("hidebox-in-place");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let testdata = env::current_dir()?.join("../testdata");
+        let carrier_path = dir.join("carrier.dat");
+        let append_path = testdata.join("append-more-than-4k.dat");
+        tokio::fs::copy(testdata.join("src.dat"), &carrier_path).await?;
+
+        let file_spec = |path: &Path| -> Result<FileSpec> {
+            Ok(FileSpec {
+                path: path.to_str().unwrap().to_string(),
+                name: path.file_name().unwrap().to_str().unwrap().to_string(),
+                size: std::fs::metadata(path)?.len(),
+            })
+        };
+
+        let canceled = CancelHandle::new();
+        canceled.cancel();
+
+        // the canceled jobs do not leave the output files
+        let e = encode(
+            file_spec(&carrier_path)?,
+            file_spec(&append_path)?,
+            &dir.join("canceled.dat"),
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &canceled,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, HideboxError::Cancelled));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&carrier_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        // the output file replaces the carrier file after it is read
+        encode(
+            file_spec(&carrier_path)?,
+            file_spec(&append_path)?,
+            &carrier_path,
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&carrier_path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        let e = decode::decode(
+            file_spec(&carrier_path)?,
+            &dir.join("canceled.dat"),
+            PASSWORD,
+            Arc::new(pcb),
+            &canceled,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, HideboxError::Cancelled));
+
+        let mut names = vec![];
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+        assert_eq!(names, ["carrier.dat"]);
+
+        let decode_path = env::temp_dir().join("hidebox-in-place-decode.dat");
+        decode::decode(
+            file_spec(&carrier_path)?,
+            &decode_path,
+            PASSWORD,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+        assert_eq!(std::fs::read(decode_path)?, std::fs::read(append_path)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_encode_broken_carrier() -> Result<()> {
+        let dir = env::temp_dir().join("hidebox-broken-carrier");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let testdata = env::current_dir()?.join("../testdata");
+        let append_path = testdata.join("append-less-than-4k.dat");
+        let file_spec = |path: &Path| -> Result<FileSpec> {
+            Ok(FileSpec {
+                path: path.to_str().unwrap().to_string(),
+                name: path.file_name().unwrap().to_str().unwrap().to_string(),
+                size: std::fs::metadata(path)?.len(),
+            })
+        };
+
+        // the zip readers look for the end of central directory at the end of the file,
+        //  so the trailer breaks the valid carrier
+        let e = encode(
+            file_spec(&testdata.join("src.zip"))?,
+            file_spec(&append_path)?,
+            &dir.join("output.zip"),
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, HideboxError::BrokenCarrier(_)));
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+        // the crc of the IHDR chunk does not match its data, the carrier which is already
+        //  broken is written but not verified
+        let carrier_path = dir.join("carrier.png");
+        let mut carrier = std::fs::read(testdata.join("src.png"))?;
+        carrier[29] ^= 0xff;
+        std::fs::write(&carrier_path, carrier)?;
+
+        for (backend, name) in [(Backend::Trailer, "output.png"), (Backend::Lsb, "lsb.png")] {
+            let outcome = encode(
+                file_spec(&carrier_path)?,
+                file_spec(&append_path)?,
+                &dir.join(name),
+                PASSWORD,
+                backend,
+                Arc::new(pcb),
+                &CancelHandle::new(),
+            )
+            .await?;
+            assert!(!outcome.is_verified);
+            assert!(dir.join(name).exists());
+        }
+
+        // the valid carrier is verified
+        let outcome = encode(
+            file_spec(&testdata.join("src.png"))?,
+            file_spec(&append_path)?,
+            &dir.join("valid.png"),
+            PASSWORD,
+            Backend::Lsb,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+        assert!(outcome.is_verified);
+
+        // the types without a validator are written but not verified
+        let outcome = encode(
+            file_spec(&testdata.join("src.dat"))?,
+            file_spec(&append_path)?,
+            &dir.join("output.dat"),
+            PASSWORD,
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+        assert!(!outcome.is_verified);
+        assert!(dir.join("output.dat").exists());
+        Ok(())
+    }
+}
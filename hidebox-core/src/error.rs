@@ -0,0 +1,91 @@
+use std::fmt;
+
+pub type Result<T, E = HideboxError> = std::result::Result<T, E>;
+
+// the errors of the file APIs, the messages never contain the password
+#[derive(Debug)]
+pub enum HideboxError {
+    // the hide spec data can not be decrypted
+    WrongPassword,
+
+    // the file does not contain a payload
+    NotACarrier,
+
+    // the payload is damaged or crafted, the reason describes the broken field
+    Corrupt(String),
+
+    // the carrier format is not supported by the backend
+    Unsupported(String),
+
+    // the append file does not fit in the carrier or the format limits
+    TooLarge(String),
+
+    // the output is not a valid carrier any more, so the output file is not written
+    BrokenCarrier(String),
+
+    Cancelled,
+
+    Io(std::io::Error),
+}
+
+impl fmt::Display for HideboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HideboxError::WrongPassword => write!(f, "wrong password"),
+            HideboxError::NotACarrier => write!(f, "do not contain hide specify data"),
+            HideboxError::Corrupt(reason) => write!(f, "corrupt payload: {reason}"),
+            HideboxError::Unsupported(reason) => write!(f, "unsupported carrier: {reason}"),
+            HideboxError::TooLarge(reason) => write!(f, "too large: {reason}"),
+            HideboxError::BrokenCarrier(reason) => write!(f, "broken carrier: {reason}"),
+            HideboxError::Cancelled => write!(f, "cancelled"),
+            HideboxError::Io(e) => write!(f, "io error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HideboxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HideboxError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HideboxError {
+    fn from(e: std::io::Error) -> Self {
+        HideboxError::Io(e)
+    }
+}
+
+impl HideboxError {
+    pub fn corrupt(reason: impl fmt::Display) -> Self {
+        HideboxError::Corrupt(reason.to_string())
+    }
+
+    pub fn unsupported(reason: impl fmt::Display) -> Self {
+        HideboxError::Unsupported(reason.to_string())
+    }
+
+    pub fn too_large(reason: impl fmt::Display) -> Self {
+        HideboxError::TooLarge(reason.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(HideboxError::WrongPassword.to_string(), "wrong password");
+        assert_eq!(
+            HideboxError::corrupt("invalid chunk checksum").to_string(),
+            "corrupt payload: invalid chunk checksum"
+        );
+
+        let e = HideboxError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(e, HideboxError::Io(_)));
+        assert!(std::error::Error::source(&e).is_some());
+    }
+}
@@ -0,0 +1,45 @@
+// the entry points of the fuzz targets in the fuzz directory, the input is a hostile carrier.
+//  Any input must return an error instead of panicking or allocating unbounded memory.
+use super::decode::{read_hide_spec_data, write_chunks};
+use super::{backend, stego, CancelHandle, Progress};
+use std::io::Cursor;
+use std::sync::Arc;
+
+const PASSWORD: &str = "123456";
+
+// the length field and the encrypted hide spec at the end of the payload
+pub async fn hide_spec_data(data: &[u8]) {
+    let size = data.len() as u64;
+    let _ = read_hide_spec_data(&mut Cursor::new(data), size, PASSWORD).await;
+}
+
+// the chunks take the whole input, the output is never larger than the chunks
+pub async fn chunk_loop(data: &[u8]) {
+    let size = data.len() as u64;
+    let mut output = vec![];
+
+    let result = write_chunks(
+        &mut Cursor::new(data),
+        &mut output,
+        size,
+        PASSWORD,
+        &mut Progress::new(Arc::new(|_| {}), size),
+        &CancelHandle::new(),
+    )
+    .await;
+
+    if result.is_ok() {
+        assert!(output.len() as u64 <= size);
+    }
+}
+
+// every embedded backend parses the input
+pub fn backend_extract(data: &[u8]) {
+    let _ = backend::extract(data);
+}
+
+// the BMP and PNG headers of the input decide the image size which is read
+pub fn stego_extract(data: &[u8]) {
+    let _ = stego::lsb::capacity(data);
+    let _ = stego::lsb::extract(data, PASSWORD);
+}
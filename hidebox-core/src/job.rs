@@ -0,0 +1,83 @@
+use super::error::{HideboxError, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+// cancel one job, the clones of the handle cancel the same job
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// the handles are equal if they cancel the same job
+impl PartialEq for CancelHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelHandle {}
+
+// a job running in the background, it is not canceled when the job is dropped
+pub struct Job<T> {
+    cancel_handle: CancelHandle,
+    handle: JoinHandle<Result<T>>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub(crate) fn spawn<F>(run: impl FnOnce(CancelHandle) -> F) -> Self
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let cancel_handle = CancelHandle::new();
+        let handle = tokio::spawn(run(cancel_handle.clone()));
+
+        Self {
+            cancel_handle,
+            handle,
+        }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    // wait for the job, the panic of the job is resumed
+    pub async fn wait(self) -> Result<T> {
+        match self.handle.await {
+            Ok(v) => v,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(HideboxError::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_handle() {
+        let (a, b) = (CancelHandle::new(), CancelHandle::new());
+        let c = a.clone();
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+
+        c.cancel();
+        assert!(a.is_cancelled());
+        assert!(!b.is_cancelled());
+    }
+}
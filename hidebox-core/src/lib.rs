@@ -0,0 +1,150 @@
+// the hidebox payload format and its carriers, the library does not depend on any UI.
+//  The stable API is re-exported here: encode and decode with their background jobs,
+//  the streaming encode_stream and decode_stream which work on readers and writers,
+//  inspect and scan to find the carriers, strip to remove the payloads, and Output
+//  which replaces a file atomically.
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod attr;
+pub mod backend;
+pub mod decode;
+pub mod encode;
+pub mod error;
+#[cfg(any(test, fuzzing))]
+pub mod fuzz;
+pub mod job;
+pub mod output;
+pub mod progress;
+pub mod scan;
+pub mod sniff;
+pub mod stego;
+pub mod strip;
+pub mod util;
+pub mod verify;
+
+pub use decode::{decode, decode_stream};
+pub use encode::{encode, encode_stream};
+pub use error::{HideboxError, Result};
+pub use job::{CancelHandle, Job};
+pub use output::Output;
+pub use progress::{Phase, ProgressCb, ProgressInfo};
+pub use scan::{inspect, scan, ScanError, ScanItem};
+pub use strip::strip;
+
+use progress::Progress;
+
+const CHUNK_SIZE: usize = 4096;
+const CHUNK_LEN_SIZE: usize = 8;
+const HASH_TEXT_SIZE: usize = 32;
+const MIN_CHUNK_LEN: usize = CHUNK_LEN_SIZE + HASH_TEXT_SIZE;
+const MAGIC_NUM: &str = "HIDEBOX";
+const MAX_FILE_SIZE: u64 = 2 * 1024 * 1024 * 1024; // 2G
+
+// where the encrypted payload is stored in the carrier file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    // append the payload after the end of the carrier file
+    #[default]
+    Trailer,
+
+    // store the payload in JPEG APP11 to APP15 or COM segments
+    JpegSegment(backend::jpeg::Marker),
+
+    // insert the payload before the ZIP central directory
+    Zip,
+
+    // add the payload as a stream object with a PDF incremental update
+    Pdf,
+
+    // store the payload in a top-level MP4 free box
+    Mp4,
+
+    // add the payload as a non-loaded ELF section
+    Elf,
+
+    // add the payload as a custom chunk of WAV, AVI and WebP files
+    Riff,
+
+    // store the payload as a base64 comment of SVG, HTML and XML documents
+    Markup,
+
+    // store the payload in the user.* extended attributes, the file content is not changed
+    Xattr,
+
+    // hide the payload in the least significant bits of PNG and BMP pixels
+    Lsb,
+
+    // hide the payload in the quantized DCT coefficients of JPEG files
+    Dct,
+
+    // hide the payload in the least significant bits of 16 bits PCM WAV samples
+    Pcm,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Trailer => "trailer",
+            Backend::JpegSegment(_) => "jpeg-segment",
+            Backend::Zip => "zip",
+            Backend::Pdf => "pdf",
+            Backend::Mp4 => "mp4",
+            Backend::Elf => "elf",
+            Backend::Riff => "riff",
+            Backend::Markup => "markup",
+            Backend::Xattr => "xattr",
+            Backend::Lsb => "lsb",
+            Backend::Dct => "dct",
+            Backend::Pcm => "pcm",
+        }
+    }
+
+    pub fn is_stego(&self) -> bool {
+        matches!(self, Backend::Lsb | Backend::Dct | Backend::Pcm)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileSpec {
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+}
+
+// a reader with the name of its data, the size is none if it is unknown, e.g. stdin
+#[derive(Debug)]
+pub struct StreamSpec<R> {
+    pub reader: R,
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HideSpec {
+    pub append_name: String,
+    pub append_size: u64,
+    pub src_size: u64,
+}
+
+// the result of a finished encoding, the cancellation is an error
+#[derive(Clone, Debug, Default)]
+pub struct EncodeOutcome {
+    // the embedding report of the steganography backends
+    pub report: Option<stego::Report>,
+
+    // the output file is checked by the validator of its format, the streams are not checked
+    pub is_verified: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOutcome {
+    // the original name of the append file
+    pub append_name: String,
+}
+
+#[derive(Clone, Default, Debug)]
+struct ChunkSpec {
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,143 @@
+use super::attr;
+use super::error::Result;
+use crate::util;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+
+// the output is written into a temporary file in the same directory and renamed to the
+//  output path only on success. The temporary file is removed if the output is dropped
+//  before it is committed, so a canceled or failed job never leaves a half-written file,
+//  and the output path can be the input path because the input is not touched until the rename.
+//  The permissions and the extended attributes of the source file are copied before the rename,
+//  the source is the output path by default, so a replaced file keeps them.
+pub struct Output {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    src_path: PathBuf,
+    is_committed: bool,
+}
+
+impl Output {
+    pub fn new(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let tmp_name = format!(".{name}.{}.hidebox-tmp", util::str::random_string(8));
+        Self {
+            path: path.to_path_buf(),
+            tmp_path: dir.join(tmp_name),
+            src_path: path.to_path_buf(),
+            is_committed: false,
+        }
+    }
+
+    pub fn with_attrs_of(mut self, src_path: &Path) -> Self {
+        self.src_path = src_path.to_path_buf();
+        self
+    }
+
+    pub fn tmp_path(&self) -> &Path {
+        &self.tmp_path
+    }
+
+    pub async fn create(&self) -> Result<File> {
+        Ok(File::create(&self.tmp_path).await?)
+    }
+
+    // flush the temporary file to the disk and move it to the output path
+    pub async fn commit(mut self) -> Result<()> {
+        if let Ok(meta) = tokio::fs::metadata(&self.src_path).await {
+            tokio::fs::set_permissions(&self.tmp_path, meta.permissions()).await?;
+            attr::copy(&self.src_path, &self.tmp_path);
+        }
+
+        File::open(&self.tmp_path).await?.sync_all().await?;
+        tokio::fs::rename(&self.tmp_path, &self.path).await?;
+        self.is_committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        if !self.is_committed {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_output_commit_and_drop() -> Result<()> {
+        let path = env::temp_dir().join("hidebox-output.dat");
+        tokio::fs::write(&path, b"old").await?;
+
+        let output = Output::new(&path);
+        let tmp_path = output.tmp_path().to_path_buf();
+        assert_eq!(tmp_path.parent(), path.parent());
+
+        tokio::fs::write(&tmp_path, b"new").await?;
+        drop(output);
+        assert!(!tmp_path.exists());
+        assert_eq!(tokio::fs::read(&path).await?, b"old");
+
+        let output = Output::new(&path);
+        tokio::fs::write(output.tmp_path(), b"new").await?;
+        output.commit().await?;
+        assert_eq!(tokio::fs::read(&path).await?, b"new");
+
+        let output = Output::new(Path::new("out.dat"));
+        assert_eq!(output.tmp_path().parent(), Some(Path::new(".")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_output_keep_attrs() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join("hidebox-output-attrs.dat");
+        tokio::fs::write(&path, b"old").await?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750))?;
+        xattr::set(&path, "user.hidebox-test", b"value")?;
+
+        // the replaced file keeps its mode and its attributes
+        let output = Output::new(&path);
+        tokio::fs::write(output.tmp_path(), b"new").await?;
+        output.commit().await?;
+        assert_eq!(tokio::fs::read(&path).await?, b"new");
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o750
+        );
+        assert_eq!(xattr::get(&path, "user.hidebox-test")?.unwrap(), b"value");
+
+        // the new file takes them from the source file
+        let dst_path = env::temp_dir().join("hidebox-output-attrs-dst.dat");
+        let _ = std::fs::remove_file(&dst_path);
+        let output = Output::new(&dst_path).with_attrs_of(&path);
+        tokio::fs::write(output.tmp_path(), b"new").await?;
+        output.commit().await?;
+        assert_eq!(
+            std::fs::metadata(&dst_path)?.permissions().mode() & 0o777,
+            0o750
+        );
+        assert_eq!(
+            xattr::get(&dst_path, "user.hidebox-test")?.unwrap(),
+            b"value"
+        );
+        Ok(())
+    }
+}
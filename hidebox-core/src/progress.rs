@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// the interval between two reports of the same phase
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+pub type ProgressCb = Arc<dyn Fn(ProgressInfo) + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Phase {
+    #[default]
+    CopyCarrier,
+    Encrypt,
+    WriteTrailer,
+    Embed,
+    Verify,
+    Decrypt,
+    Scan,
+}
+
+impl Phase {
+    // the identifier of the phase, the UIs translate it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::CopyCarrier => "copy carrier",
+            Phase::Encrypt => "encrypt",
+            Phase::WriteTrailer => "write trailer",
+            Phase::Embed => "embed",
+            Phase::Verify => "verify",
+            Phase::Decrypt => "decrypt",
+            Phase::Scan => "scan",
+        }
+    }
+}
+
+// done and total are bytes, except the scan which counts the files.
+//  The total is 0 if the size of a stream is unknown until the job finishes.
+//  The throughput is the average of the job in units per second.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressInfo {
+    pub phase: Phase,
+    pub done: u64,
+    pub total: u64,
+    pub throughput: f64,
+    pub eta: Option<Duration>,
+}
+
+impl ProgressInfo {
+    pub fn new(phase: Phase, done: u64, total: u64, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let throughput = if secs > 0. { done as f64 / secs } else { 0. };
+        let eta = if throughput > 0. && done < total {
+            Some(Duration::from_secs_f64((total - done) as f64 / throughput))
+        } else {
+            None
+        };
+
+        Self {
+            phase,
+            done,
+            total,
+            throughput,
+            eta,
+        }
+    }
+
+    pub fn percent(&self) -> u32 {
+        if self.total == 0 {
+            return 100;
+        }
+
+        (self.done.min(self.total) * 100 / self.total) as u32
+    }
+}
+
+// report the progress of a job to the callback, the reports are limited to
+//  one per REPORT_INTERVAL except the phase changes and the end of the job.
+pub(crate) struct Progress {
+    callback: ProgressCb,
+    phase: Phase,
+    done: u64,
+    total: u64,
+    start: Instant,
+    last_report: Instant,
+}
+
+impl Progress {
+    pub fn new(callback: ProgressCb, total: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            callback,
+            phase: Phase::default(),
+            done: 0,
+            total,
+            start: now,
+            last_report: now,
+        }
+    }
+
+    pub fn phase(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.report();
+    }
+
+    pub fn advance(&mut self, len: usize) {
+        self.done += len as u64;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.total == 0 {
+            self.total = self.done;
+        } else {
+            self.done = self.total;
+        }
+        self.report();
+    }
+
+    fn report(&mut self) {
+        self.last_report = Instant::now();
+        (self.callback)(ProgressInfo::new(
+            self.phase,
+            self.done,
+            self.total,
+            self.start.elapsed(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_info() {
+        let info = ProgressInfo::new(Phase::Encrypt, 250, 1000, Duration::from_secs(1));
+        assert_eq!(info.percent(), 25);
+        assert_eq!(info.throughput, 250.);
+        assert_eq!(info.eta, Some(Duration::from_secs(3)));
+
+        let info = ProgressInfo::new(Phase::Verify, 1000, 1000, Duration::ZERO);
+        assert_eq!(info.percent(), 100);
+        assert_eq!(info.eta, None);
+        assert_eq!(ProgressInfo::default().percent(), 100);
+    }
+
+    #[test]
+    fn test_progress_report() {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        let mut progress = Progress::new(
+            Arc::new(move |info: ProgressInfo| sink.lock().unwrap().push(info)),
+            100,
+        );
+
+        progress.phase(Phase::CopyCarrier);
+        progress.advance(10);
+        progress.phase(Phase::Encrypt);
+        progress.advance(10);
+        progress.finish();
+
+        let reports = reports.lock().unwrap();
+        let phases = reports.iter().map(|info| info.phase).collect::<Vec<_>>();
+        assert_eq!(phases, [Phase::CopyCarrier, Phase::Encrypt, Phase::Encrypt]);
+        assert_eq!(reports[1].done, 10);
+        assert_eq!(reports[2].percent(), 100);
+    }
+}
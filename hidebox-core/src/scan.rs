@@ -0,0 +1,394 @@
+use super::*;
+use anyhow::Result;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+const CSV_HEADER: &str = "path,size,location,payload_size";
+
+// the files are inspected in parallel by a few workers, so each of them is read
+//  up to this size and the memory of a scan is bounded
+const MAX_INSPECT_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_WORKERS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    // appended after the end of the carrier file
+    Trailer,
+
+    // stored in the extended attributes of the carrier file
+    Xattr,
+
+    // embedded in the carrier file structure
+    Embedded,
+}
+
+impl Location {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Location::Trailer => "trailer",
+            Location::Xattr => "xattr",
+            Location::Embedded => "embedded",
+        }
+    }
+}
+
+// the payload size is none if the payload structure is unknown
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanItem {
+    pub path: String,
+    pub size: u64,
+    pub location: Location,
+    pub payload_size: Option<u64>,
+}
+
+// a file or a directory which is not inspected, e.g. it is unreadable
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ScanError {
+    fn new(path: &Path, message: impl ToString) -> Self {
+        log::warn!("skip {}: {}", path.display(), message.to_string());
+        ScanError {
+            path: path.to_string_lossy().to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanResult {
+    pub items: Vec<ScanItem>,
+    pub errors: Vec<ScanError>,
+    pub files: usize,
+    pub is_canceled: bool,
+}
+
+// the regular files under the directory, the symbolic links are not followed.
+//  The unreadable directories and entries are the errors, only an unreadable root fails.
+async fn list_files(
+    dir: &Path,
+    cancel_handle: &CancelHandle,
+) -> Result<(Vec<PathBuf>, Vec<ScanError>)> {
+    let (mut files, mut errors) = (vec![], vec![]);
+    let mut dirs = vec![dir.to_path_buf()];
+    let mut is_root = true;
+
+    while let Some(dir) = dirs.pop() {
+        if cancel_handle.is_cancelled() {
+            break;
+        }
+
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(v) => v,
+            Err(e) if !is_root => {
+                errors.push(ScanError::new(&dir, e));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        is_root = false;
+
+        loop {
+            let entry = match entries.next_entry().await {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(e) => {
+                    errors.push(ScanError::new(&dir, e));
+                    break;
+                }
+            };
+
+            match entry.file_type().await {
+                Ok(v) if v.is_dir() => dirs.push(entry.path()),
+                Ok(v) if v.is_file() => files.push(entry.path()),
+                Ok(_) => (),
+                Err(e) => errors.push(ScanError::new(&entry.path(), e)),
+            }
+        }
+    }
+
+    Ok((files, errors))
+}
+
+// the carrier information of the file, none if the file does not contain a payload.
+//  The payload is found by its structure like has_append_file, so the password is not needed.
+pub async fn inspect(path: &Path) -> error::Result<Option<ScanItem>> {
+    inspect_with_limit(path, MAX_INSPECT_SIZE).await
+}
+
+// at most `limit` bytes of the file are read, the embedded carriers which are larger
+//  are too large and the payload size of a larger trailer is unknown
+async fn inspect_with_limit(path: &Path, limit: u64) -> error::Result<Option<ScanItem>> {
+    let file_spec = FileSpec {
+        path: path.to_string_lossy().to_string(),
+        size: tokio::fs::metadata(path).await?.len(),
+        ..Default::default()
+    };
+
+    let (location, payload) = match decode::locate_payload(&file_spec, limit).await? {
+        Some((Location::Trailer, _)) => {
+            let data = read_tail(path, file_spec.size.min(limit)).await?;
+            let payload = strip::trailer_start(&data).map(|start| data[start..].to_vec());
+            (Location::Trailer, payload)
+        }
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let payload = payload.filter(|p| strip::trailer_start(p) == Some(0));
+    Ok(Some(ScanItem {
+        path: file_spec.path,
+        size: file_spec.size,
+        location,
+        payload_size: payload.map(|p| p.len() as u64),
+    }))
+}
+
+async fn read_tail(path: &Path, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::End(-(len as i64))).await?;
+
+    let mut data = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+// find the hidebox carriers under the directory, the files are inspected in parallel.
+//  The found carriers are returned if the scan is canceled.
+pub async fn scan(
+    dir: &Path,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<ScanResult> {
+    scan_with_limit(dir, MAX_INSPECT_SIZE, progress_callback, cancel_handle).await
+}
+
+// the files which fail to be inspected, or whose inspection panics, are the errors
+//  of the result, the other files are still inspected
+async fn scan_with_limit(
+    dir: &Path,
+    limit: u64,
+    progress_callback: ProgressCb,
+    cancel_handle: &CancelHandle,
+) -> Result<ScanResult> {
+    let start = Instant::now();
+
+    let (files, mut errors) = list_files(dir, cancel_handle).await?;
+    let total = files.len();
+    log::debug!("scan {}: {total} files", dir.display());
+
+    let semaphore = Arc::new(Semaphore::new(
+        std::thread::available_parallelism().map_or(MAX_WORKERS, |n| n.get().min(MAX_WORKERS)),
+    ));
+    let done = Arc::new(AtomicUsize::new(0));
+    let mut tasks = JoinSet::new();
+
+    for path in files {
+        let permit = semaphore.clone().acquire_owned().await?;
+        if cancel_handle.is_cancelled() {
+            break;
+        }
+
+        let (done, progress_callback) = (done.clone(), progress_callback.clone());
+        tasks.spawn(async move {
+            // the inspection runs in its own task, so its panic is the error of the file
+            let inspected = {
+                let path = path.clone();
+                tokio::spawn(async move { inspect_with_limit(&path, limit).await }).await
+            };
+            drop(permit);
+
+            let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+            if done * 100 / total != (done - 1) * 100 / total {
+                progress_callback(ProgressInfo::new(
+                    Phase::Scan,
+                    done as u64,
+                    total as u64,
+                    start.elapsed(),
+                ));
+            }
+            (path, inspected)
+        });
+    }
+
+    let mut items = vec![];
+    while let Some(task) = tasks.join_next().await {
+        let (path, inspected) = task?;
+        match inspected {
+            Ok(Ok(Some(item))) => items.push(item),
+            Ok(Ok(None)) => (),
+            Ok(Err(e)) => errors.push(ScanError::new(&path, e)),
+            Err(e) => errors.push(ScanError::new(&path, e)),
+        }
+    }
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    errors.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(ScanResult {
+        items,
+        errors,
+        files: done.load(Ordering::SeqCst),
+        is_canceled: cancel_handle.is_cancelled(),
+    })
+}
+
+pub fn to_json(items: &[ScanItem]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(items)?)
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn to_csv(items: &[ScanItem]) -> String {
+    let mut csv = format!("{CSV_HEADER}\n");
+    for item in items {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&item.path),
+            item.size,
+            item.location.name(),
+            item.payload_size.map(|v| v.to_string()).unwrap_or_default(),
+        ));
+    }
+    csv
+}
+
+// the format is chosen by the extension of the output file, JSON by default
+pub async fn export(items: &[ScanItem], path: &Path) -> Result<()> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    let text = if is_csv {
+        to_csv(items)
+    } else {
+        to_json(items)?
+    };
+
+    tokio::fs::write(path, text).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn pcb(_info: ProgressInfo) {}
+
+    async fn encode_to(src_name: &str, dst_file_path: &Path, backend: Backend) -> Result<()> {
+        let cur_dir = env::current_dir()?;
+        let src_file_path = cur_dir.join("../testdata").join(src_name);
+        let append_file_path = cur_dir.join("../testdata/append-less-than-4k.dat");
+
+        let file_spec = |path: &Path, size| FileSpec {
+            path: path.to_str().unwrap().to_string(),
+            name: path.file_name().unwrap().to_str().unwrap().to_string(),
+            size,
+        };
+
+        encode::encode(
+            file_spec(
+                &src_file_path,
+                tokio::fs::metadata(&src_file_path).await?.len(),
+            ),
+            file_spec(
+                &append_file_path,
+                tokio::fs::metadata(&append_file_path).await?.len(),
+            ),
+            dst_file_path,
+            "123456",
+            backend,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan() -> Result<()> {
+        let dir = env::temp_dir().join("hidebox-scan");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(dir.join("sub/empty")).await?;
+
+        let trailer_path = dir.join("sub/trailer.dat");
+        let markup_path = dir.join("markup.svg");
+        encode_to("src.dat", &trailer_path, Backend::Trailer).await?;
+        encode_to("src.svg", &markup_path, Backend::Markup).await?;
+        tokio::fs::write(dir.join("plain.dat"), b"plain").await?;
+
+        let result = scan(&dir, Arc::new(pcb), &CancelHandle::new()).await?;
+        assert_eq!(result.files, 3);
+        assert!(!result.is_canceled);
+        assert_eq!(result.items.len(), 2);
+
+        let (markup, trailer) = (&result.items[0], &result.items[1]);
+        assert_eq!(markup.path, markup_path.to_str().unwrap());
+        assert_eq!(markup.location, Location::Embedded);
+        assert_eq!(trailer.location, Location::Trailer);
+        assert!(result.errors.is_empty());
+
+        let src_size = tokio::fs::metadata(env::current_dir()?.join("../testdata/src.dat"))
+            .await?
+            .len();
+        assert_eq!(trailer.payload_size, Some(trailer.size - src_size));
+
+        assert!(inspect(&trailer_path).await?.is_some());
+        assert!(inspect(&dir.join("plain.dat")).await?.is_none());
+
+        let limit = trailer.payload_size.unwrap() - 1;
+        let item = inspect_with_limit(&trailer_path, limit).await?.unwrap();
+        assert_eq!(item.location, Location::Trailer);
+        assert_eq!(item.payload_size, None);
+        assert!(matches!(
+            inspect_with_limit(&markup_path, 16).await,
+            Err(HideboxError::TooLarge(_))
+        ));
+
+        // the file which is not inspected is an error, the others are still inspected
+        let limit = tokio::fs::metadata(&markup_path).await?.len() - 1;
+        let limited = scan_with_limit(&dir, limit, Arc::new(pcb), &CancelHandle::new()).await?;
+        assert_eq!(limited.files, 3);
+        assert_eq!(limited.items.len(), 1);
+        assert_eq!(limited.items[0].location, Location::Trailer);
+        assert_eq!(limited.errors.len(), 1);
+        assert_eq!(limited.errors[0].path, markup_path.to_str().unwrap());
+        assert!(limited.errors[0].message.contains("too large"));
+
+        let csv = to_csv(&result.items);
+        assert!(csv.starts_with(CSV_HEADER));
+        assert_eq!(csv.lines().count(), 3);
+
+        let items: Vec<ScanItem> = serde_json::from_str(&to_json(&result.items)?)?;
+        assert_eq!(items.len(), 2);
+
+        assert!(
+            scan(&dir.join("missing"), Arc::new(pcb), &CancelHandle::new())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_csv_field() {
+        assert_eq!(csv_field("a.dat"), "a.dat");
+        assert_eq!(csv_field("a,\"b\".dat"), "\"a,\"\"b\"\".dat\"");
+    }
+}
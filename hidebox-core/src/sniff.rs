@@ -0,0 +1,242 @@
+use super::Backend;
+use anyhow::Result;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+// the magic bytes and the markup root tag are found in the head of the file
+pub(crate) const SNIFF_SIZE: usize = 1024;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// the carrier type detected from the content, the file extension is not trusted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Wav,
+    Avi,
+    WebP,
+    Pdf,
+    Zip,
+    Rar,
+    SevenZip,
+    Gzip,
+    Mp4,
+    Elf,
+    Pe,
+    Svg,
+    Html,
+    Xml,
+    Text,
+    Unknown,
+}
+
+impl FileType {
+    // the format names are not translated, the UI translates the text and the unknown types
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Png => "PNG",
+            FileType::Jpeg => "JPEG",
+            FileType::Gif => "GIF",
+            FileType::Bmp => "BMP",
+            FileType::Wav => "WAV",
+            FileType::Avi => "AVI",
+            FileType::WebP => "WebP",
+            FileType::Pdf => "PDF",
+            FileType::Zip => "ZIP",
+            FileType::Rar => "RAR",
+            FileType::SevenZip => "7z",
+            FileType::Gzip => "GZIP",
+            FileType::Mp4 => "MP4",
+            FileType::Elf => "ELF",
+            FileType::Pe => "PE",
+            FileType::Svg => "SVG",
+            FileType::Html => "HTML",
+            FileType::Xml => "XML",
+            FileType::Text => "text",
+            FileType::Unknown => "unknown",
+        }
+    }
+
+    // the backend which keeps the carrier format valid
+    pub fn backend(&self) -> Backend {
+        match self {
+            FileType::Jpeg => Backend::JpegSegment(Default::default()),
+            FileType::Zip => Backend::Zip,
+            FileType::Pdf => Backend::Pdf,
+            FileType::Mp4 => Backend::Mp4,
+            FileType::Elf => Backend::Elf,
+            FileType::Wav | FileType::Avi | FileType::WebP => Backend::Riff,
+            FileType::Svg | FileType::Html | FileType::Xml => Backend::Markup,
+            _ => Backend::Trailer,
+        }
+    }
+
+    // the steganography backend which hides the payload in the carrier content
+    pub fn stego_backend(&self) -> Option<Backend> {
+        match self {
+            FileType::Png | FileType::Bmp => Some(Backend::Lsb),
+            FileType::Jpeg => Some(Backend::Dct),
+            FileType::Wav => Some(Backend::Pcm),
+            _ => None,
+        }
+    }
+
+    // the readers ignore the data after the end of the file. The ZIP and PDF readers only
+    //  search the end of the file for the central directory and the %%EOF marker, and the
+    //  documents, texts and compressed streams show the appended data as garbage.
+    pub fn tolerates_trailer(&self) -> bool {
+        matches!(
+            self,
+            FileType::Png
+                | FileType::Jpeg
+                | FileType::Gif
+                | FileType::Bmp
+                | FileType::Wav
+                | FileType::Avi
+                | FileType::WebP
+                | FileType::Rar
+                | FileType::SevenZip
+                | FileType::Elf
+                | FileType::Pe
+        )
+    }
+}
+
+fn contains_ignore_case(data: &[u8], pattern: &[u8]) -> bool {
+    data.windows(pattern.len())
+        .any(|w| w.eq_ignore_ascii_case(pattern))
+}
+
+fn sniff_markup(data: &[u8]) -> Option<FileType> {
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    if data.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'<') {
+        return None;
+    }
+
+    let is_html =
+        contains_ignore_case(data, b"<html") || contains_ignore_case(data, b"<!doctype html");
+    if contains_ignore_case(data, b"<svg") {
+        Some(FileType::Svg)
+    } else if is_html {
+        Some(FileType::Html)
+    } else {
+        Some(FileType::Xml)
+    }
+}
+
+// the head may end in the middle of a multi-byte character
+fn is_text(data: &[u8]) -> bool {
+    let text = match std::str::from_utf8(data) {
+        Ok(v) => v,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0C'))
+}
+
+// detect the file type from the magic bytes in the head of the file
+pub fn sniff(data: &[u8]) -> FileType {
+    let data = &data[..usize::min(data.len(), SNIFF_SIZE)];
+
+    if data.starts_with(b"\x89PNG\r\n\x1A\n") {
+        FileType::Png
+    } else if data.starts_with(b"\xFF\xD8\xFF") {
+        FileType::Jpeg
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        FileType::Gif
+    } else if data.starts_with(b"BM") && data.len() >= 26 {
+        FileType::Bmp
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        FileType::Wav
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"AVI ") {
+        FileType::Avi
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        FileType::WebP
+    } else if data.starts_with(b"%PDF-") {
+        FileType::Pdf
+    } else if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        FileType::Zip
+    } else if data.starts_with(b"Rar!\x1A\x07") {
+        FileType::Rar
+    } else if data.starts_with(b"7z\xBC\xAF\x27\x1C") {
+        FileType::SevenZip
+    } else if data.starts_with(b"\x1F\x8B") {
+        FileType::Gzip
+    } else if data.get(4..8) == Some(b"ftyp") {
+        FileType::Mp4
+    } else if data.starts_with(b"\x7FELF") {
+        FileType::Elf
+    } else if data.starts_with(b"MZ") {
+        FileType::Pe
+    } else if let Some(file_type) = sniff_markup(data) {
+        file_type
+    } else if is_text(data) {
+        FileType::Text
+    } else {
+        FileType::Unknown
+    }
+}
+
+pub async fn sniff_file(path: &Path) -> Result<FileType> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut data = Vec::with_capacity(SNIFF_SIZE);
+    file.take(SNIFF_SIZE as u64).read_to_end(&mut data).await?;
+    Ok(sniff(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_sniff_file() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+        for (name, file_type) in [
+            ("src.png", FileType::Png),
+            ("src.jpg", FileType::Jpeg),
+            ("src.bmp", FileType::Bmp),
+            ("src.wav", FileType::Wav),
+            ("src.pdf", FileType::Pdf),
+            ("src.zip", FileType::Zip),
+            ("src.mp4", FileType::Mp4),
+            ("src.elf", FileType::Elf),
+            ("src.svg", FileType::Svg),
+            ("src.html", FileType::Html),
+            ("src.dat", FileType::Text),
+        ] {
+            let path = cur_dir.join("../testdata").join(name);
+            assert_eq!(sniff_file(&path).await?, file_type, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_content() {
+        // the extension is not used, a text file named .png is still a text file
+        assert_eq!(sniff(b"hello, world\n"), FileType::Text);
+        assert_eq!(sniff("你好".as_bytes()), FileType::Text);
+        assert_eq!(sniff(&"你好".as_bytes()[..4]), FileType::Text);
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF <?xml version=\"1.0\"?><a/>"),
+            FileType::Xml
+        );
+        assert_eq!(sniff(b"GIF89a\x01\x00"), FileType::Gif);
+        assert_eq!(sniff(b"\x1F\x8B\x08\x00"), FileType::Gzip);
+        assert_eq!(sniff(b"\x00\x01\x02\x03"), FileType::Unknown);
+        assert_eq!(sniff(b""), FileType::Unknown);
+
+        assert_eq!(FileType::Text.backend(), Backend::Trailer);
+        assert!(!FileType::Text.tolerates_trailer());
+        assert_eq!(FileType::Jpeg.stego_backend(), Some(Backend::Dct));
+    }
+}
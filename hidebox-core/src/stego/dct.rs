@@ -0,0 +1,305 @@
+use super::{bits, frame, frame_bits, jpeg, unframe, Positions, Report, LEN_SIZE};
+use anyhow::{anyhow, Result};
+
+// the AC coefficients of a block, the DC coefficient is not used
+const AC_SIZE: usize = jpeg::BLOCK_SIZE - 1;
+
+// the matrix encoding parameter k is written before the frame,
+//  a group of 2^k - 1 coefficients carries k bits with at most one change
+const K_BITS: usize = 4;
+const MAX_K: usize = 9;
+
+// the header bits are embedded one bit per coefficient
+const HEADER_BITS: usize = K_BITS + LEN_SIZE * 8;
+
+// the F5 value of a coefficient, the bit of a negative coefficient is inverted,
+//  so decreasing the absolute value always flips the bit
+fn coefficient_bit(value: i16) -> usize {
+    if value > 0 {
+        (value & 1) as usize
+    } else {
+        (value.unsigned_abs() & 1 ^ 1) as usize
+    }
+}
+
+// the non-zero AC coefficients in a pseudo-random order which is derived from the password
+struct Coefficients<'a> {
+    blocks: &'a mut [jpeg::Block],
+    positions: Positions,
+}
+
+impl<'a> Coefficients<'a> {
+    fn new(blocks: &'a mut [jpeg::Block], password: &str) -> Self {
+        let total = blocks.len() * AC_SIZE;
+        Self {
+            blocks,
+            positions: Positions::new(password, total),
+        }
+    }
+
+    fn value(&self, pos: usize) -> i16 {
+        self.blocks[pos / AC_SIZE][1 + pos % AC_SIZE]
+    }
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let pos = self.positions.next()?;
+            if self.value(pos) != 0 {
+                return Some(pos);
+            }
+        }
+    }
+
+    fn group(&mut self, n: usize) -> Option<Vec<usize>> {
+        (0..n).map(|_| self.next()).collect()
+    }
+
+    // the XOR of the 1-based indexes of the coefficients whose bits are 1
+    fn hash(&self, group: &[usize]) -> usize {
+        group
+            .iter()
+            .enumerate()
+            .filter(|(_, pos)| coefficient_bit(self.value(**pos)) == 1)
+            .fold(0, |hash, (i, _)| hash ^ (i + 1))
+    }
+
+    // write k bits into a group of 2^k - 1 coefficients, the absolute value of a
+    //  coefficient is decreased by one if needed. The coefficient which becomes zero
+    //  is skipped by the extractor, so the bits are written again with the next one.
+    //  Return the number of the changed coefficients.
+    fn write(&mut self, k: usize, value: usize) -> Result<usize> {
+        let too_large = || anyhow!("payload is too large for the jpeg image");
+        let mut group = self.group((1 << k) - 1).ok_or_else(too_large)?;
+        let mut changes = 0;
+
+        loop {
+            let index = self.hash(&group) ^ value;
+            if index == 0 {
+                return Ok(changes);
+            }
+
+            let pos = group[index - 1];
+            let coefficient = &mut self.blocks[pos / AC_SIZE][1 + pos % AC_SIZE];
+            *coefficient -= coefficient.signum();
+            changes += 1;
+
+            if *coefficient != 0 {
+                return Ok(changes);
+            }
+
+            group.remove(index - 1);
+            group.push(self.next().ok_or_else(too_large)?);
+        }
+    }
+
+    fn read(&mut self, k: usize) -> Option<usize> {
+        let group = self.group((1 << k) - 1)?;
+        Some(self.hash(&group))
+    }
+}
+
+// the (large, ones) counts of the non-zero AC coefficients
+fn counts(blocks: &[jpeg::Block]) -> (usize, usize) {
+    let (mut large, mut ones) = (0, 0);
+    for value in blocks.iter().flat_map(|b| b[1..].iter()) {
+        match value.unsigned_abs() {
+            0 => (),
+            1 => ones += 1,
+            _ => large += 1,
+        }
+    }
+    (large, ones)
+}
+
+// the estimated number of coefficients which can carry bits,
+//  about half of the ±1 coefficients are lost when they become zero
+fn usable_coefficients(blocks: &[jpeg::Block]) -> usize {
+    let (large, ones) = counts(blocks);
+    (large + ones * 49 / 100).saturating_sub(HEADER_BITS)
+}
+
+// the estimated bits which can be written with the matrix encoding parameter k
+fn capacity_bits(usable: usize, k: usize) -> usize {
+    usable / ((1 << k) - 1) * k
+}
+
+// the max payload size in bytes, it is an estimate because of the shrinkage
+pub fn capacity(carrier: &[u8]) -> Result<usize> {
+    let jpeg = jpeg::decode(carrier)?;
+    let usable = usable_coefficients(&jpeg.blocks);
+    Ok((capacity_bits(usable, 1) / 8).saturating_sub(LEN_SIZE))
+}
+
+// write the payload into the quantized DCT coefficients with the F5 matrix encoding,
+//  the largest k whose capacity fits the payload is used to make the fewest changes.
+//  The other segments of the file are kept, the huffman tables are optimized again.
+pub fn embed(carrier: &[u8], payload: &[u8], password: &str) -> Result<(Vec<u8>, Report)> {
+    let mut jpeg = jpeg::decode(carrier)?;
+    let usable = usable_coefficients(&jpeg.blocks);
+    let frame_bits = frame_bits(payload);
+
+    let Some(k) = (1..=MAX_K)
+        .rev()
+        .find(|k| capacity_bits(usable, *k) >= frame_bits)
+    else {
+        return Err(anyhow!(
+            "payload is too large, the capacity of the image is about {} bytes",
+            (capacity_bits(usable, 1) / 8).saturating_sub(LEN_SIZE)
+        ));
+    };
+
+    let frame = frame(payload)?;
+    let mut coefficients = Coefficients::new(&mut jpeg.blocks, password);
+    let mut changes = 0;
+
+    for i in (0..K_BITS).rev() {
+        changes += coefficients.write(1, (k >> i) & 1)?;
+    }
+
+    let mut bits = bits(&frame);
+    for bit in bits.by_ref().take(LEN_SIZE * 8) {
+        changes += coefficients.write(1, bit as usize)?;
+    }
+
+    // the last group is padded with zero bits
+    let mut bits = bits.peekable();
+    while bits.peek().is_some() {
+        let value = (0..k).fold(0, |value, _| {
+            (value << 1) | bits.next().unwrap_or(0) as usize
+        });
+        changes += coefficients.write(k, value)?;
+    }
+
+    Ok((
+        jpeg.encode(),
+        Report {
+            bits: HEADER_BITS + frame_bits,
+            changes,
+            ..Default::default()
+        },
+    ))
+}
+
+pub fn extract(data: &[u8], password: &str) -> Option<Vec<u8>> {
+    let mut jpeg = jpeg::decode(data).ok()?;
+    let (large, ones) = counts(&jpeg.blocks);
+    let mut coefficients = Coefficients::new(&mut jpeg.blocks, password);
+
+    let mut k = 0;
+    for _ in 0..K_BITS {
+        k = (k << 1) | coefficients.read(1)?;
+    }
+    if !(1..=MAX_K).contains(&k) {
+        return None;
+    }
+
+    let (mut count, mut group) = (0, vec![]);
+    unframe(
+        || {
+            count += 1;
+            if count <= LEN_SIZE * 8 {
+                return coefficients.read(1).map(|v| v as u8);
+            }
+
+            if group.is_empty() {
+                let value = coefficients.read(k)?;
+                group = (0..k).map(|i| ((value >> i) & 1) as u8).collect();
+            }
+            group.pop()
+        },
+        (large + ones) / 8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MAGIC_NUM;
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    const PASSWORD: &str = "123456";
+
+    fn carrier(name: &str) -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata").join(name))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    fn image_pixels(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(jpeg_decoder::Decoder::new(data).decode()?)
+    }
+
+    #[test]
+    fn test_dct_coefficient_bit() {
+        for value in [-5, -2, -1, 1, 2, 5] {
+            let changed = value - i16::signum(value);
+            if changed != 0 {
+                assert_ne!(coefficient_bit(value), coefficient_bit(changed));
+            }
+        }
+        assert_eq!(coefficient_bit(1), 1);
+        assert_eq!(coefficient_bit(-1), 0);
+    }
+
+    #[test]
+    fn test_dct_embed_extract() -> Result<()> {
+        let carrier = carrier("src-dct.jpg")?;
+        assert!(extract(&carrier, PASSWORD).is_none());
+
+        let capacity = capacity(&carrier)?;
+        for len in [10, capacity / 4, capacity * 3 / 4] {
+            let payload = payload(len);
+            let (output, report) = embed(&carrier, &payload, PASSWORD)?;
+            assert_eq!(extract(&output, PASSWORD).unwrap(), payload);
+            assert!(extract(&output, "654321").is_none());
+
+            // the smaller payload is written with the larger k and fewer changes
+            assert!(report.changes > 0 && report.changes < report.bits);
+            if len == 10 {
+                assert!(report.efficiency() > 2.);
+            }
+
+            // the image is still a valid jpeg file with the same size and close pixels
+            let (src_pixels, pixels) = (image_pixels(&carrier)?, image_pixels(&output)?);
+            assert_eq!(src_pixels.len(), pixels.len());
+            assert_ne!(src_pixels, pixels);
+            let diff = src_pixels
+                .iter()
+                .zip(pixels.iter())
+                .map(|(a, b)| a.abs_diff(*b) as usize)
+                .sum::<usize>();
+            assert!(diff / pixels.len() < 8);
+        }
+
+        assert!(embed(&carrier, &payload(capacity * 2), PASSWORD).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dct_metadata_stripped() -> Result<()> {
+        let carrier = carrier("src-dct.jpg")?;
+        let payload = payload(100);
+        let (output, _) = embed(&carrier, &payload, PASSWORD)?;
+
+        // remove the APP segments like the metadata tools do
+        let mut stripped = output[..2].to_vec();
+        let mut pos = 2;
+        while output[pos + 1] != 0xDA {
+            let len = u16::from_be_bytes([output[pos + 2], output[pos + 3]]) as usize;
+            if !(0xE0..=0xEF).contains(&output[pos + 1]) {
+                stripped.extend_from_slice(&output[pos..pos + 2 + len]);
+            }
+            pos += 2 + len;
+        }
+        stripped.extend_from_slice(&output[pos..]);
+
+        assert!(stripped.len() < output.len());
+        assert_eq!(extract(&stripped, PASSWORD).unwrap(), payload);
+        Ok(())
+    }
+}
@@ -0,0 +1,751 @@
+use anyhow::{anyhow, Result};
+
+const MARKER_PREFIX: u8 = 0xFF;
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+const DHT: u8 = 0xC4;
+const DAC: u8 = 0xCC;
+const SOS: u8 = 0xDA;
+const DRI: u8 = 0xDD;
+const RST0: u8 = 0xD0;
+const RST7: u8 = 0xD7;
+const TEM: u8 = 0x01;
+
+pub const BLOCK_SIZE: usize = 64;
+
+// the quantized coefficients of a block in zigzag order, the first one is the DC coefficient
+pub type Block = [i16; BLOCK_SIZE];
+
+#[derive(Clone, Debug)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+struct HuffmanTable {
+    // the number of codes of each length from 1 to 16 bits
+    bits: [u8; 16],
+    values: Vec<u8>,
+}
+
+// the decoded baseline JPEG file, the entropy coded data can be written
+//  again with the changed coefficients and the other segments are kept
+pub struct Jpeg {
+    components: Vec<Component>,
+    restart_interval: usize,
+    mcus: usize,
+
+    // the blocks in the scan order
+    pub blocks: Vec<Block>,
+
+    // the component index of each block
+    block_components: Vec<usize>,
+
+    // the segments before SOS without the DHT segments, the SOS segment and the end of the file
+    header: Vec<u8>,
+    sos: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<usize> {
+    match data.get(pos..pos + 2) {
+        Some(v) => Ok(u16::from_be_bytes([v[0], v[1]]) as usize),
+        None => Err(anyhow!("unexpected end of jpeg file")),
+    }
+}
+
+fn segment(data: &[u8], pos: usize) -> Result<&[u8]> {
+    let len = u16_at(data, pos + 2)?;
+    match data.get(pos + 4..pos + 2 + len) {
+        Some(v) if len >= 2 => Ok(v),
+        _ => Err(anyhow!("invalid jpeg segment length at {pos}")),
+    }
+}
+
+impl HuffmanTable {
+    // the canonical codes of the table: (code, length) of each value
+    fn codes(&self) -> Vec<(u8, u16, u8)> {
+        let (mut codes, mut code, mut index) = (vec![], 0_u16, 0);
+        for (i, count) in self.bits.iter().enumerate() {
+            for _ in 0..*count {
+                if let Some(value) = self.values.get(index) {
+                    codes.push((*value, code, i as u8 + 1));
+                }
+                code = code.wrapping_add(1);
+                index += 1;
+            }
+            code <<= 1;
+        }
+        codes
+    }
+
+    // JPEG Annex K.2, the code lengths are limited to 16 bits
+    fn optimal(freq: &[u32; 256]) -> Self {
+        // the reserved symbol keeps the all ones code from being used
+        let mut freq = freq.iter().map(|f| *f as u64).collect::<Vec<_>>();
+        freq.push(1);
+
+        let mut code_size = [0_usize; 257];
+        let mut others = [-1_i32; 257];
+
+        loop {
+            let min = |exclude: Option<usize>| {
+                (0..257)
+                    .filter(|i| freq[*i] > 0 && Some(*i) != exclude)
+                    .min_by_key(|i| (freq[*i], std::cmp::Reverse(*i)))
+            };
+
+            let Some(c1) = min(None) else { break };
+            let Some(c2) = min(Some(c1)) else { break };
+
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+
+            let mut c = c1;
+            code_size[c] += 1;
+            while others[c] >= 0 {
+                c = others[c] as usize;
+                code_size[c] += 1;
+            }
+
+            others[c] = c2 as i32;
+            let mut c = c2;
+            code_size[c] += 1;
+            while others[c] >= 0 {
+                c = others[c] as usize;
+                code_size[c] += 1;
+            }
+        }
+
+        let mut bits = [0_usize; 33];
+        for size in code_size.iter().filter(|s| **s > 0) {
+            bits[*size] += 1;
+        }
+
+        for i in (17..=32).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+
+        // remove the reserved symbol
+        let mut i = 16;
+        while bits[i] == 0 {
+            i -= 1;
+        }
+        bits[i] -= 1;
+
+        let mut values = vec![];
+        for size in 1..=32 {
+            for (symbol, s) in code_size.iter().enumerate().take(256) {
+                if *s == size {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+
+        let mut table = HuffmanTable {
+            values,
+            ..Default::default()
+        };
+        for (i, b) in table.bits.iter_mut().enumerate() {
+            *b = bits[i + 1] as u8;
+        }
+        table
+    }
+
+    fn segment(&self, class: u8, id: usize) -> Vec<u8> {
+        let mut data = vec![(class << 4) | id as u8];
+        data.extend_from_slice(&self.bits);
+        data.extend_from_slice(&self.values);
+        data
+    }
+}
+
+struct Decoder {
+    // (code length, code) => value
+    lookup: Vec<Vec<(u16, u8)>>,
+}
+
+impl Decoder {
+    fn new(table: &HuffmanTable) -> Self {
+        let mut lookup = vec![vec![]; 17];
+        for (value, code, len) in table.codes() {
+            lookup[len as usize].push((code, value));
+        }
+        Self { lookup }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    value: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            value: 0,
+            count: 0,
+        }
+    }
+
+    fn bit(&mut self) -> Result<u16> {
+        if self.count == 0 {
+            let byte = match self.data.get(self.pos) {
+                Some(v) => *v,
+                None => return Err(anyhow!("unexpected end of jpeg scan data")),
+            };
+
+            if byte == MARKER_PREFIX {
+                match self.data.get(self.pos + 1) {
+                    Some(0) => self.pos += 1,
+                    _ => return Err(anyhow!("unexpected jpeg marker in scan data")),
+                }
+            }
+
+            self.pos += 1;
+            self.value = byte as u32;
+            self.count = 8;
+        }
+
+        self.count -= 1;
+        Ok(((self.value >> self.count) & 1) as u16)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u16> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?;
+        }
+        Ok(value)
+    }
+
+    fn decode(&mut self, decoder: &Decoder) -> Result<u8> {
+        let mut code = 0;
+        for len in 1..=16 {
+            code = (code << 1) | self.bit()?;
+            if let Some((_, value)) = decoder.lookup[len].iter().find(|(c, _)| *c == code) {
+                return Ok(*value);
+            }
+        }
+        Err(anyhow!("invalid jpeg huffman code"))
+    }
+
+    fn receive_extend(&mut self, size: u8) -> Result<i16> {
+        if size == 0 {
+            return Ok(0);
+        }
+        if size > 15 {
+            return Err(anyhow!("invalid jpeg coefficient size"));
+        }
+
+        let value = self.bits(size)? as i32;
+        if value < 1 << (size - 1) {
+            Ok((value - (1 << size) + 1) as i16)
+        } else {
+            Ok(value as i16)
+        }
+    }
+
+    // skip the RSTn marker
+    fn restart(&mut self) -> Result<()> {
+        self.count = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([MARKER_PREFIX, marker]) if (RST0..=RST7).contains(marker) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(anyhow!("jpeg restart marker is missing")),
+        }
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    value: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u16, count: u8) {
+        for i in (0..count).rev() {
+            self.value = (self.value << 1) | ((value >> i) & 1) as u32;
+            self.count += 1;
+            if self.count == 8 {
+                self.push();
+            }
+        }
+    }
+
+    fn push(&mut self) {
+        let byte = self.value as u8;
+        self.data.push(byte);
+        if byte == MARKER_PREFIX {
+            self.data.push(0);
+        }
+        self.value = 0;
+        self.count = 0;
+    }
+
+    // pad the last byte with one bits
+    fn flush(&mut self) {
+        while self.count != 0 {
+            self.write(1, 1);
+        }
+    }
+}
+
+// the size and the bits of a coefficient value
+fn magnitude(value: i16) -> (u8, u16) {
+    let size = (16 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 {
+        (value as i32 + (1 << size) - 1) as u16
+    } else {
+        value as u16
+    };
+    (size, bits)
+}
+
+// the end of the entropy coded data which ends at a marker except RSTn
+fn scan_end(data: &[u8], start: usize) -> usize {
+    let mut pos = start;
+    while pos + 1 < data.len() {
+        let (byte, next) = (data[pos], data[pos + 1]);
+        if byte == MARKER_PREFIX
+            && next != 0
+            && next != MARKER_PREFIX
+            && !(RST0..=RST7).contains(&next)
+        {
+            return pos;
+        }
+        pos += 1;
+    }
+    data.len()
+}
+
+// LAYOUT: SOI + segments + SOS + entropy coded data + EOI;
+//  only the sequential huffman coded JPEG files with one scan are supported.
+pub fn decode(data: &[u8]) -> Result<Jpeg> {
+    if data.len() < 4 || data[0] != MARKER_PREFIX || data[1] != SOI {
+        return Err(anyhow!("not a jpeg file"));
+    }
+
+    let mut tables: [Vec<Option<HuffmanTable>>; 2] = [vec![None; 4], vec![None; 4]];
+    let (mut components, mut size, mut restart_interval) = (vec![], None, 0);
+    let mut header = vec![MARKER_PREFIX, SOI];
+    let mut pos = 2;
+
+    let sos_pos = loop {
+        if pos + 1 >= data.len() || data[pos] != MARKER_PREFIX {
+            return Err(anyhow!("invalid jpeg marker at {pos}"));
+        }
+
+        let marker = data[pos + 1];
+        if marker == MARKER_PREFIX {
+            pos += 1;
+            continue;
+        }
+
+        if marker == TEM || (RST0..=RST7).contains(&marker) {
+            header.extend_from_slice(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+
+        if marker == EOI {
+            return Err(anyhow!("jpeg scan is missing"));
+        }
+
+        let content = segment(data, pos)?;
+        let end = pos + 4 + content.len();
+
+        match marker {
+            SOS => break pos,
+            SOF0 | SOF1 => {
+                if content.len() < 6 || content[0] != 8 {
+                    return Err(anyhow!("unsupported jpeg sample precision"));
+                }
+
+                let count = content[5] as usize;
+                for i in 0..count {
+                    let c = match content.get(6 + i * 3..9 + i * 3) {
+                        Some(v) => v,
+                        None => return Err(anyhow!("invalid jpeg frame header")),
+                    };
+                    let (h, v) = ((c[1] >> 4) as usize, (c[1] & 0x0F) as usize);
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                        return Err(anyhow!("invalid jpeg sampling factor"));
+                    }
+
+                    components.push(Component {
+                        id: c[0],
+                        h,
+                        v,
+                        dc_table: 0,
+                        ac_table: 0,
+                    });
+                }
+                size = Some((u16_at(content, 3)?, u16_at(content, 1)?));
+            }
+            DHT => {
+                let mut i = 0;
+                while i < content.len() {
+                    let (class, id) = ((content[i] >> 4) as usize, (content[i] & 0x0F) as usize);
+                    if class > 1 || id > 3 || i + 17 > content.len() {
+                        return Err(anyhow!("invalid jpeg huffman table"));
+                    }
+
+                    let mut bits = [0; 16];
+                    bits.copy_from_slice(&content[i + 1..i + 17]);
+                    let count = bits.iter().map(|b| *b as usize).sum::<usize>();
+                    let values = match content.get(i + 17..i + 17 + count) {
+                        Some(v) => v.to_vec(),
+                        None => return Err(anyhow!("invalid jpeg huffman table")),
+                    };
+
+                    tables[class][id] = Some(HuffmanTable { bits, values });
+                    i += 17 + count;
+                }
+                pos = end;
+                continue;
+            }
+            DRI => restart_interval = u16_at(content, 0)?,
+            DAC => return Err(anyhow!("unsupported jpeg arithmetic coding")),
+            0xC2..=0xCF => return Err(anyhow!("unsupported jpeg coding process")),
+            _ => (),
+        }
+
+        header.extend_from_slice(&data[pos..end]);
+        pos = end;
+    };
+
+    let Some((width, height)) = size else {
+        return Err(anyhow!("jpeg frame header is missing"));
+    };
+    if width == 0 || height == 0 || components.is_empty() {
+        return Err(anyhow!("invalid jpeg frame size"));
+    }
+
+    let sos = segment(data, sos_pos)?;
+    let count = sos[0] as usize;
+    if count != components.len() || sos.len() < 4 + count * 2 {
+        return Err(anyhow!("unsupported jpeg file with multiple scans"));
+    }
+
+    let (ss, se, a) = (sos[1 + count * 2], sos[2 + count * 2], sos[3 + count * 2]);
+    if ss != 0 || se != 63 || a != 0 {
+        return Err(anyhow!("unsupported jpeg spectral selection"));
+    }
+
+    for i in 0..count {
+        let (id, t) = (sos[1 + i * 2], sos[2 + i * 2]);
+        let Some(component) = components.iter_mut().find(|c| c.id == id) else {
+            return Err(anyhow!("invalid jpeg scan component {id}"));
+        };
+        component.dc_table = (t >> 4) as usize;
+        component.ac_table = (t & 0x0F) as usize;
+        if component.dc_table > 3 || component.ac_table > 3 {
+            return Err(anyhow!("invalid jpeg huffman table id"));
+        }
+    }
+
+    // the component order of the scan is the order in the frame header
+    let scan_start = sos_pos + 4 + sos.len();
+    let end = scan_end(data, scan_start);
+
+    let table = |class: usize, id: usize| match &tables[class][id] {
+        Some(t) => Ok(Decoder::new(t)),
+        None => Err(anyhow!("jpeg huffman table is missing")),
+    };
+
+    let decoders = components
+        .iter()
+        .map(|c| Ok((table(0, c.dc_table)?, table(1, c.ac_table)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (h_max, v_max) = (
+        components.iter().map(|c| c.h).max().unwrap_or(1),
+        components.iter().map(|c| c.v).max().unwrap_or(1),
+    );
+
+    // a single component scan is not interleaved, the MCU is one block
+    let (mcus, mcu_layout) = if components.len() == 1 {
+        let c = &components[0];
+        let blocks_w = (width * c.h).div_ceil(8 * h_max);
+        let blocks_h = (height * c.v).div_ceil(8 * v_max);
+        (blocks_w * blocks_h, vec![0])
+    } else {
+        let mut layout = vec![];
+        for (i, c) in components.iter().enumerate() {
+            layout.extend(std::iter::repeat_n(i, c.h * c.v));
+        }
+        (
+            width.div_ceil(8 * h_max) * height.div_ceil(8 * v_max),
+            layout,
+        )
+    };
+
+    if mcus.saturating_mul(mcu_layout.len()) > (end - scan_start).saturating_mul(8) {
+        return Err(anyhow!("invalid jpeg scan data size"));
+    }
+
+    let mut reader = BitReader::new(&data[scan_start..end]);
+    let mut blocks = Vec::with_capacity(mcus * mcu_layout.len());
+    let mut block_components = Vec::with_capacity(mcus * mcu_layout.len());
+    let mut predictions = vec![0_i16; components.len()];
+
+    for mcu in 0..mcus {
+        if restart_interval > 0 && mcu > 0 && mcu.is_multiple_of(restart_interval) {
+            reader.restart()?;
+            predictions.fill(0);
+        }
+
+        for index in mcu_layout.iter() {
+            let (dc_decoder, ac_decoder) = &decoders[*index];
+            let mut block = [0_i16; BLOCK_SIZE];
+
+            let size = reader.decode(dc_decoder)?;
+            predictions[*index] = predictions[*index].wrapping_add(reader.receive_extend(size)?);
+            block[0] = predictions[*index];
+
+            let mut k = 1;
+            while k < BLOCK_SIZE {
+                let rs = reader.decode(ac_decoder)?;
+                let (run, size) = ((rs >> 4) as usize, rs & 0x0F);
+                if size == 0 {
+                    if run != 15 {
+                        break;
+                    }
+                    k += 16;
+                    continue;
+                }
+
+                k += run;
+                if k >= BLOCK_SIZE {
+                    return Err(anyhow!("invalid jpeg coefficient run length"));
+                }
+                block[k] = reader.receive_extend(size)?;
+                k += 1;
+            }
+
+            blocks.push(block);
+            block_components.push(*index);
+        }
+    }
+
+    Ok(Jpeg {
+        components,
+        restart_interval,
+        mcus,
+        blocks,
+        block_components,
+        header,
+        sos: data[sos_pos..scan_start].to_vec(),
+        tail: data[end..].to_vec(),
+    })
+}
+
+impl Jpeg {
+    // call the callback with the (component, dc symbol, ac symbols) of each block
+    fn symbols(&self, mut callback: impl FnMut(usize, (u8, u16), &[(u8, u16)])) {
+        let mut predictions = vec![0_i16; self.components.len()];
+        let blocks_per_mcu = self.blocks.len() / self.mcus.max(1);
+        let mut ac = Vec::with_capacity(BLOCK_SIZE);
+
+        for (i, (block, component)) in self
+            .blocks
+            .iter()
+            .zip(self.block_components.iter())
+            .enumerate()
+        {
+            let mcu = i / blocks_per_mcu;
+            if self.restart_interval > 0
+                && mcu > 0
+                && mcu.is_multiple_of(self.restart_interval)
+                && i % blocks_per_mcu == 0
+            {
+                predictions.fill(0);
+            }
+
+            let diff = block[0].wrapping_sub(predictions[*component]);
+            predictions[*component] = block[0];
+
+            ac.clear();
+            let mut run = 0;
+            for value in block[1..].iter() {
+                if *value == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    ac.push((0xF0, 0));
+                    run -= 16;
+                }
+                let (size, bits) = magnitude(*value);
+                ac.push(((run << 4) | size, bits));
+                run = 0;
+            }
+            if run > 0 {
+                ac.push((0x00, 0));
+            }
+
+            let (size, bits) = magnitude(diff);
+            callback(*component, (size, bits), &ac);
+        }
+    }
+
+    // write the file with the changed coefficients, the huffman tables are
+    //  generated again because the changed coefficients may need new codes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut freq = vec![[[0_u32; 256]; 4]; 2];
+        self.symbols(|component, dc, ac| {
+            let c = &self.components[component];
+            freq[0][c.dc_table][dc.0 as usize] += 1;
+            for (symbol, _) in ac {
+                freq[1][c.ac_table][*symbol as usize] += 1;
+            }
+        });
+
+        let mut dht = vec![];
+        let mut codes = vec![vec![vec![(0_u16, 0_u8); 256]; 4]; 2];
+        for class in 0..2 {
+            for id in 0..4 {
+                if freq[class][id].iter().all(|f| *f == 0) {
+                    continue;
+                }
+
+                let table = HuffmanTable::optimal(&freq[class][id]);
+                for (value, code, len) in table.codes() {
+                    codes[class][id][value as usize] = (code, len);
+                }
+                dht.extend_from_slice(&table.segment(class as u8, id));
+            }
+        }
+
+        let mut writer = BitWriter {
+            data: vec![],
+            value: 0,
+            count: 0,
+        };
+
+        let blocks_per_mcu = self.blocks.len() / self.mcus.max(1);
+        let mut index = 0;
+        self.symbols(|component, dc, ac| {
+            let mcu = index / blocks_per_mcu;
+            if self.restart_interval > 0
+                && mcu > 0
+                && mcu.is_multiple_of(self.restart_interval)
+                && index % blocks_per_mcu == 0
+            {
+                writer.flush();
+                let marker = RST0 + ((mcu / self.restart_interval - 1) % 8) as u8;
+                writer.data.extend_from_slice(&[MARKER_PREFIX, marker]);
+            }
+            index += 1;
+
+            let c = &self.components[component];
+            let (code, len) = codes[0][c.dc_table][dc.0 as usize];
+            writer.write(code, len);
+            writer.write(dc.1, dc.0);
+
+            for (symbol, bits) in ac {
+                let (code, len) = codes[1][c.ac_table][*symbol as usize];
+                writer.write(code, len);
+                writer.write(*bits, symbol & 0x0F);
+            }
+        });
+        writer.flush();
+
+        let mut output = Vec::with_capacity(
+            self.header.len() + writer.data.len() + self.tail.len() + dht.len() + 4,
+        );
+        output.extend_from_slice(&self.header);
+        output.extend_from_slice(&[MARKER_PREFIX, DHT]);
+        output.extend_from_slice(&((dht.len() + 2) as u16).to_be_bytes());
+        output.extend_from_slice(&dht);
+        output.extend_from_slice(&self.sos);
+        output.extend_from_slice(&writer.data);
+        output.extend_from_slice(&self.tail);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.jpg"))?)
+    }
+
+    #[test]
+    fn test_jpeg_decode_encode() -> Result<()> {
+        let carrier = carrier()?;
+        let jpeg = decode(&carrier)?;
+        assert!(!jpeg.blocks.is_empty());
+
+        let output = jpeg.encode();
+        assert_eq!(decode(&output)?.blocks, jpeg.blocks);
+
+        let image = jpeg_decoder::Decoder::new(output.as_slice()).decode()?;
+        let src_image = jpeg_decoder::Decoder::new(carrier.as_slice()).decode()?;
+        assert_eq!(image, src_image);
+
+        assert!(decode(b"not a jpeg file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jpeg_magnitude() {
+        assert_eq!(magnitude(0), (0, 0));
+        assert_eq!(magnitude(1), (1, 1));
+        assert_eq!(magnitude(-1), (1, 0));
+        assert_eq!(magnitude(5), (3, 5));
+        assert_eq!(magnitude(-5), (3, 2));
+    }
+
+    #[test]
+    fn test_jpeg_optimal_huffman_table() {
+        let mut freq = [0; 256];
+        for (i, f) in freq.iter_mut().enumerate() {
+            *f = (i as u32 % 7 + 1) * 1000 / (i as u32 + 1);
+        }
+
+        let table = HuffmanTable::optimal(&freq);
+        assert_eq!(table.values.len(), 256);
+        assert_eq!(table.bits.iter().map(|b| *b as usize).sum::<usize>(), 256);
+
+        // the codes are prefix free and the all ones codes are not used
+        let codes = table.codes();
+        assert!(codes.iter().all(|(_, code, len)| *code != (1 << len) - 1));
+        for (_, code, len) in codes.iter() {
+            assert!(
+                codes
+                    .iter()
+                    .filter(|(_, c, l)| l > len && c >> (l - len) == *code)
+                    .count()
+                    == 0
+            );
+        }
+    }
+}
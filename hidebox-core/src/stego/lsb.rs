@@ -0,0 +1,558 @@
+use super::super::MAX_FILE_SIZE;
+use super::{bits, frame, frame_bits, unframe, Positions, Report, LEN_SIZE};
+use anyhow::{anyhow, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const BMP_SIGNATURE: &[u8] = b"BM";
+
+const PNG_CHUNK_OVERHEAD: usize = 12;
+const MAX_PNG_CHUNK_SIZE: usize = i32::MAX as usize;
+const BI_RGB: u32 = 0;
+
+// the width and the height of both formats are at most i32::MAX
+const MAX_DIMENSION: usize = i32::MAX as usize;
+
+// the unfiltered and the filtered PNG rows are both kept in memory,
+//  so they are limited to a quarter of the max file size
+const MAX_PNG_ROWS_SIZE: usize = (MAX_FILE_SIZE / 4) as usize;
+
+// the color samples of the image, the alpha samples and the row padding are not used
+#[derive(Clone, Debug)]
+struct Samples {
+    offset: usize,
+    stride: usize,
+    width: usize,
+    height: usize,
+    pixel_size: usize,
+    channels: usize,
+    sample_size: usize,
+}
+
+impl Samples {
+    fn total(&self) -> usize {
+        self.width * self.height * self.channels
+    }
+
+    // the position of the least significant byte of the sample,
+    //  the 16 bits samples of PNG are big endian
+    fn byte_pos(&self, index: usize) -> usize {
+        let row_samples = self.width * self.channels;
+        let (row, col) = (index / row_samples, index % row_samples);
+        let (pixel, channel) = (col / self.channels, col % self.channels);
+
+        self.offset
+            + row * self.stride
+            + pixel * self.pixel_size
+            + channel * self.sample_size
+            + self.sample_size
+            - 1
+    }
+}
+
+// the image data is compressed again with the level and the IDAT chunk size of the carrier
+#[derive(Clone, Debug)]
+struct Png {
+    idat_start: usize,
+    idat_end: usize,
+    idat_chunk_size: usize,
+    level: u32,
+    row_size: usize,
+    bpp: usize,
+}
+
+struct Image {
+    samples: Samples,
+
+    // the BMP file, or the unfiltered PNG scanlines with the filter type bytes
+    pixels: Vec<u8>,
+    png: Option<Png>,
+}
+
+fn u16_le(data: &[u8], pos: usize) -> Result<usize> {
+    match data.get(pos..pos + 2) {
+        Some(v) => Ok(u16::from_le_bytes([v[0], v[1]]) as usize),
+        None => Err(anyhow!("unexpected end of image")),
+    }
+}
+
+fn u32_le(data: &[u8], pos: usize) -> Result<u32> {
+    match data.get(pos..pos + 4) {
+        Some(v) => Ok(u32::from_le_bytes(v.try_into()?)),
+        None => Err(anyhow!("unexpected end of image")),
+    }
+}
+
+fn u32_be(data: &[u8], pos: usize) -> Result<usize> {
+    match data.get(pos..pos + 4) {
+        Some(v) => Ok(u32::from_be_bytes(v.try_into()?) as usize),
+        None => Err(anyhow!("unexpected end of image")),
+    }
+}
+
+// LAYOUT: file header(14 bytes) + DIB header + [color table] + pixel rows;
+//  only the uncompressed 24 and 32 bits BMP files are supported.
+fn parse_bmp(data: &[u8]) -> Result<Image> {
+    let pixel_offset = u32_le(data, 10)? as usize;
+    let dib_size = u32_le(data, 14)? as usize;
+    if dib_size < 40 {
+        return Err(anyhow!("unsupported bmp header"));
+    }
+
+    let width = u32_le(data, 18)? as i32;
+    let height = u32_le(data, 22)? as i32;
+    let bit_count = u16_le(data, 28)?;
+    let compression = u32_le(data, 30)?;
+
+    if compression != BI_RGB || !(bit_count == 24 || bit_count == 32) {
+        return Err(anyhow!(
+            "unsupported bmp format, only 24 and 32 bits BMP are supported"
+        ));
+    }
+
+    // the negative height is a top-down image, i32::MIN has no positive value
+    let (width, height) = (
+        width.unsigned_abs() as usize,
+        height.unsigned_abs() as usize,
+    );
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(anyhow!("invalid bmp size"));
+    }
+
+    let stride = width
+        .checked_mul(bit_count)
+        .map(|bits| bits.div_ceil(32) * 4);
+    let pixels_end = stride
+        .and_then(|stride| stride.checked_mul(height))
+        .and_then(|size| size.checked_add(pixel_offset));
+    let stride = match (stride, pixels_end) {
+        (Some(stride), Some(end)) if end <= data.len() => stride,
+        _ => return Err(anyhow!("invalid bmp size")),
+    };
+
+    Ok(Image {
+        samples: Samples {
+            offset: pixel_offset,
+            stride,
+            width,
+            height,
+            pixel_size: bit_count / 8,
+            channels: 3,
+            sample_size: 1,
+        },
+        pixels: data.to_vec(),
+        png: None,
+    })
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn predict(filter: u8, a: u8, b: u8, c: u8) -> Result<u8> {
+    Ok(match filter {
+        0 => 0,
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        4 => paeth(a, b, c),
+        _ => return Err(anyhow!("invalid png filter type {filter}")),
+    })
+}
+
+// the neighbours of the byte: left(a), up(b) and up-left(c)
+fn neighbours(rows: &[u8], png: &Png, row: usize, col: usize) -> (u8, u8, u8) {
+    let stride = png.row_size + 1;
+    let pos = row * stride + 1 + col;
+    let a = if col >= png.bpp {
+        rows[pos - png.bpp]
+    } else {
+        0
+    };
+    let b = if row > 0 { rows[pos - stride] } else { 0 };
+    let c = if row > 0 && col >= png.bpp {
+        rows[pos - stride - png.bpp]
+    } else {
+        0
+    };
+    (a, b, c)
+}
+
+fn unfilter(rows: &mut [u8], png: &Png, height: usize) -> Result<()> {
+    let stride = png.row_size + 1;
+    for row in 0..height {
+        let filter = rows[row * stride];
+        for col in 0..png.row_size {
+            let (a, b, c) = neighbours(rows, png, row, col);
+            let pos = row * stride + 1 + col;
+            rows[pos] = rows[pos].wrapping_add(predict(filter, a, b, c)?);
+        }
+    }
+    Ok(())
+}
+
+// filter the rows with their original filter types
+fn filter(rows: &[u8], png: &Png, height: usize) -> Result<Vec<u8>> {
+    let stride = png.row_size + 1;
+    let mut output = rows.to_vec();
+    for row in 0..height {
+        let filter = rows[row * stride];
+        for col in 0..png.row_size {
+            let (a, b, c) = neighbours(rows, png, row, col);
+            let pos = row * stride + 1 + col;
+            output[pos] = rows[pos].wrapping_sub(predict(filter, a, b, c)?);
+        }
+    }
+    Ok(output)
+}
+
+// LAYOUT: signature + IHDR + chunks + IDAT chunks + chunks + IEND;
+//  only the non-interlaced 8 and 16 bits grayscale and truecolor PNG files are supported.
+fn parse_png(data: &[u8]) -> Result<Image> {
+    let mut pos = PNG_SIGNATURE.len();
+    let (mut header, mut idat_start, mut idat_end) = (None, None, None);
+    let mut idat_chunk_size = None;
+    let mut compressed = vec![];
+
+    while pos < data.len() {
+        let len = u32_be(data, pos)?;
+        let kind = data.get(pos + 4..pos + 8).unwrap_or_default();
+        let end = pos + PNG_CHUNK_OVERHEAD + len;
+        if end > data.len() {
+            return Err(anyhow!("invalid png chunk length at {pos}"));
+        }
+
+        let content = &data[pos + 8..pos + 8 + len];
+        match kind {
+            b"IHDR" => header = Some(content),
+            b"IDAT" => {
+                if idat_end.is_some_and(|e| e != pos) {
+                    return Err(anyhow!("invalid png, the IDAT chunks are not consecutive"));
+                }
+                idat_start.get_or_insert(pos);
+                idat_chunk_size.get_or_insert(len);
+                idat_end = Some(end);
+                compressed.extend_from_slice(content);
+            }
+            b"IEND" => break,
+            _ => (),
+        }
+        pos = end;
+    }
+
+    let (header, idat_start, idat_end) = match (header, idat_start, idat_end) {
+        (Some(h), Some(s), Some(e)) if h.len() >= 13 => (h, s, e),
+        _ => return Err(anyhow!("invalid png, IHDR or IDAT is missing")),
+    };
+
+    let (width, height) = (u32_be(header, 0)?, u32_be(header, 4)?);
+    let (bit_depth, color_type, interlace) = (header[8] as usize, header[9], header[12]);
+
+    // (all channels, color channels)
+    let (total_channels, channels) = match color_type {
+        0 => (1, 1),
+        2 => (3, 3),
+        4 => (2, 1),
+        6 => (4, 3),
+        _ => return Err(anyhow!("unsupported png color type {color_type}")),
+    };
+
+    if !(bit_depth == 8 || bit_depth == 16) || interlace != 0 {
+        return Err(anyhow!(
+            "unsupported png format, only non-interlaced 8 and 16 bits PNG are supported"
+        ));
+    }
+
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(anyhow!("invalid png size"));
+    }
+
+    // the size comes from IHDR, so it is checked before the image data is inflated
+    let sample_size = bit_depth / 8;
+    let pixel_size = total_channels * sample_size;
+    let row_size = width
+        .checked_mul(pixel_size)
+        .ok_or_else(|| anyhow!("invalid png size"))?;
+    let rows_size = row_size
+        .checked_add(1)
+        .and_then(|stride| stride.checked_mul(height))
+        .ok_or_else(|| anyhow!("invalid png size"))?;
+    if rows_size > MAX_PNG_ROWS_SIZE {
+        return Err(anyhow!(
+            "png image is too large, the image data is larger than {MAX_PNG_ROWS_SIZE} bytes"
+        ));
+    }
+
+    // the FLEVEL field of the zlib header, the zlib levels 1, 5, 6 and 9 write the same value
+    let level = match compressed.get(1).map(|flags| flags >> 6) {
+        Some(0) => 1,
+        Some(1) => 5,
+        Some(2) => 6,
+        _ => 9,
+    };
+
+    let mut pixels = Vec::with_capacity(usize::min(rows_size, compressed.len() * 1032));
+    ZlibDecoder::new(compressed.as_slice())
+        .take(rows_size as u64)
+        .read_to_end(&mut pixels)?;
+    if pixels.len() != rows_size {
+        return Err(anyhow!("invalid png image data size"));
+    }
+
+    let png = Png {
+        idat_start,
+        idat_end,
+        idat_chunk_size: match idat_chunk_size {
+            Some(size) if size > 0 => size.min(MAX_PNG_CHUNK_SIZE),
+            _ => MAX_PNG_CHUNK_SIZE,
+        },
+        level,
+        row_size,
+        bpp: pixel_size,
+    };
+    unfilter(&mut pixels, &png, height)?;
+
+    Ok(Image {
+        samples: Samples {
+            offset: 1,
+            stride: row_size + 1,
+            width,
+            height,
+            pixel_size,
+            channels,
+            sample_size,
+        },
+        pixels,
+        png: Some(png),
+    })
+}
+
+fn parse_image(data: &[u8]) -> Result<Image> {
+    if data.starts_with(PNG_SIGNATURE) {
+        parse_png(data)
+    } else if data.starts_with(BMP_SIGNATURE) {
+        parse_bmp(data)
+    } else {
+        Err(anyhow!("not a png or bmp file"))
+    }
+}
+
+// replace the IDAT chunks with the compressed image data, the other chunks are not changed.
+//  The rows keep their filters, the compressed size changes with the changed bits.
+fn make_png(carrier: &[u8], image: &Image, png: &Png) -> Result<Vec<u8>> {
+    let rows = filter(&image.pixels, png, image.samples.height)?;
+    let mut encoder = ZlibEncoder::new(vec![], Compression::new(png.level));
+    encoder.write_all(&rows)?;
+    let compressed = encoder.finish()?;
+
+    let mut output = Vec::with_capacity(carrier.len() + compressed.len());
+    output.extend_from_slice(&carrier[..png.idat_start]);
+    for content in compressed.chunks(png.idat_chunk_size) {
+        let chunk = [b"IDAT", content].concat();
+        output.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        output.extend_from_slice(&chunk);
+        output.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    }
+    output.extend_from_slice(&carrier[png.idat_end..]);
+
+    Ok(output)
+}
+
+// the max payload size in bytes
+pub fn capacity(carrier: &[u8]) -> Result<usize> {
+    let image = parse_image(carrier)?;
+    Ok((image.samples.total() / 8).saturating_sub(LEN_SIZE))
+}
+
+// write the payload bits into the least significant bits of the color samples,
+//  the samples are chosen in a pseudo-random order which is derived from the password.
+//  BMP files keep the same size, PNG files only have the image data compressed again,
+//  so their size changes slightly and the change is in the report.
+pub fn embed(carrier: &[u8], payload: &[u8], password: &str) -> Result<(Vec<u8>, Report)> {
+    let mut image = parse_image(carrier)?;
+    let total = image.samples.total();
+
+    if frame_bits(payload) > total {
+        return Err(anyhow!(
+            "payload is too large, the capacity of the image is {} bytes",
+            (total / 8).saturating_sub(LEN_SIZE)
+        ));
+    }
+
+    let frame = frame(payload)?;
+    let mut report = Report {
+        bits: frame.len() * 8,
+        ..Default::default()
+    };
+    for (bit, index) in bits(&frame).zip(Positions::new(password, total)) {
+        let pos = image.samples.byte_pos(index);
+        let Some(sample) = image.pixels.get_mut(pos) else {
+            return Err(anyhow!("invalid image, sample {index} is out of the image"));
+        };
+
+        if *sample & 1 != bit {
+            *sample ^= 1;
+            report.changes += 1;
+        }
+    }
+
+    let output = match image.png.clone() {
+        Some(png) => make_png(carrier, &image, &png)?,
+        None => image.pixels,
+    };
+    Ok((output, report))
+}
+
+pub fn extract(data: &[u8], password: &str) -> Option<Vec<u8>> {
+    let image = parse_image(data).ok()?;
+    let total = image.samples.total();
+    let mut positions = Positions::new(password, total);
+
+    unframe(
+        || {
+            let pos = image.samples.byte_pos(positions.next()?);
+            Some(image.pixels.get(pos)? & 1)
+        },
+        (total / 8).saturating_sub(LEN_SIZE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MAGIC_NUM;
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    const PASSWORD: &str = "123456";
+
+    fn carrier(name: &str) -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata").join(name))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    fn png_pixels(data: &[u8]) -> Result<Vec<u8>> {
+        let mut reader = png::Decoder::new(std::io::Cursor::new(data)).read_info()?;
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels)?;
+        Ok(pixels)
+    }
+
+    #[test]
+    fn test_lsb_png() -> Result<()> {
+        let carrier = carrier("src.png")?;
+        assert!(extract(&carrier, PASSWORD).is_none());
+
+        let capacity = capacity(&carrier)?;
+        let payload = payload(capacity - MAGIC_NUM.len() * 2);
+        let (output, report) = embed(&carrier, &payload, PASSWORD)?;
+        assert_eq!(extract(&output, PASSWORD).unwrap(), payload);
+        assert!(extract(&output, "654321").is_none());
+
+        // the pixels are only changed in the least significant bits
+        let (src_pixels, pixels) = (png_pixels(&carrier)?, png_pixels(&output)?);
+        assert_eq!(src_pixels.len(), pixels.len());
+        assert!(src_pixels
+            .iter()
+            .zip(pixels.iter())
+            .all(|(a, b)| a >> 1 == b >> 1));
+        assert_ne!(src_pixels, pixels);
+
+        // about half of the bits are already the same
+        let changes = src_pixels
+            .iter()
+            .zip(pixels.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(report.changes, changes);
+        assert!(report.efficiency() > 1.5);
+
+        // the image data is compressed with the level and the chunk size of the carrier
+        let (src_png, png) = (
+            parse_png(&carrier)?.png.unwrap(),
+            parse_png(&output)?.png.unwrap(),
+        );
+        assert_eq!(png.level, src_png.level);
+        assert_eq!(
+            output[png.idat_start + 8 + 1],
+            carrier[src_png.idat_start + 8 + 1]
+        );
+        if png.idat_end - png.idat_start > src_png.idat_chunk_size + PNG_CHUNK_OVERHEAD {
+            assert_eq!(png.idat_chunk_size, src_png.idat_chunk_size);
+        }
+
+        assert!(embed(&carrier, &super::super::frame(&payload)?, PASSWORD).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_bmp() -> Result<()> {
+        let carrier = carrier("src.bmp")?;
+        assert!(extract(&carrier, PASSWORD).is_none());
+
+        let payload = payload(100);
+        let (output, _) = embed(&carrier, &payload, PASSWORD)?;
+        assert_eq!(extract(&output, PASSWORD).unwrap(), payload);
+        assert_eq!(output.len(), carrier.len());
+
+        let header_size = u32_le(&carrier, 10)? as usize;
+        assert_eq!(output[..header_size], carrier[..header_size]);
+        assert!(carrier
+            .iter()
+            .zip(output.iter())
+            .all(|(a, b)| a >> 1 == b >> 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_invalid_size() -> Result<()> {
+        // the top-down BMP height of i32::MIN has no positive value
+        let mut bmp = carrier("src.bmp")?;
+        bmp[22..26].copy_from_slice(&i32::MIN.to_le_bytes());
+        assert!(parse_bmp(&bmp).is_err());
+        assert!(extract(&bmp, PASSWORD).is_none());
+
+        // the size of a 32 bits image of i32::MIN * i32::MIN overflows
+        bmp[18..22].copy_from_slice(&i32::MIN.to_le_bytes());
+        bmp[28..30].copy_from_slice(&32_u16.to_le_bytes());
+        assert!(parse_bmp(&bmp).is_err());
+        assert!(extract(&bmp, PASSWORD).is_none());
+
+        // the image data of a huge IHDR size is not inflated
+        let mut png = carrier("src.png")?;
+        let ihdr = PNG_SIGNATURE.len() + 8;
+        png[ihdr..ihdr + 4].copy_from_slice(&20000_u32.to_be_bytes());
+        png[ihdr + 4..ihdr + 8].copy_from_slice(&20000_u32.to_be_bytes());
+        let e = parse_png(&png).err().unwrap();
+        assert!(e.to_string().contains("too large"));
+        assert!(extract(&png, PASSWORD).is_none());
+
+        png[ihdr..ihdr + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_png(&png).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_unsupported() {
+        assert!(capacity(b"not an image").is_err());
+        assert!(embed(b"not an image", b"data", PASSWORD).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use super::{Backend, CHUNK_LEN_SIZE, CHUNK_SIZE, HASH_TEXT_SIZE, MAGIC_NUM};
+use anyhow::{anyhow, Result};
+use crypto_hash::{digest, Algorithm};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::HashMap;
+
+pub mod dct;
+pub mod jpeg;
+pub mod lsb;
+pub mod pcm;
+pub mod text;
+
+// the payload length is written before the payload
+const LEN_SIZE: usize = 4;
+
+// the encrypted hide spec data with a file name up to 255 bytes
+const MAX_HIDE_SPEC_SIZE: usize = 640;
+
+// the AES block size, the encrypted text is hex encoded
+const BLOCK_SIZE: usize = 16;
+
+// the embedding result, the efficiency is the number of the embedded bits per change
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub bits: usize,
+    pub changes: usize,
+
+    // the output size minus the carrier size, the PNG and JPEG image data is compressed again
+    pub size_change: i64,
+}
+
+impl Report {
+    pub fn efficiency(&self) -> f64 {
+        self.bits as f64 / usize::max(self.changes, 1) as f64
+    }
+}
+
+// hide the payload in the carrier data, the positions are derived from the password
+pub fn embed(
+    backend: Backend,
+    carrier: &[u8],
+    payload: &[u8],
+    password: &str,
+) -> Result<(Vec<u8>, Report)> {
+    let (output, mut report) = match backend {
+        Backend::Lsb => lsb::embed(carrier, payload, password)?,
+        Backend::Dct => dct::embed(carrier, payload, password)?,
+        Backend::Pcm => pcm::embed(carrier, payload, password)?,
+        _ => return Err(anyhow!("{backend:?} is not a steganography backend")),
+    };
+
+    report.size_change = output.len() as i64 - carrier.len() as i64;
+    Ok((output, report))
+}
+
+// find the payload written by any steganography backend
+pub fn extract(data: &[u8], password: &str) -> Option<Vec<u8>> {
+    lsb::extract(data, password)
+        .or_else(|| dct::extract(data, password))
+        .or_else(|| pcm::extract(data, password))
+}
+
+// the carrier may contain a steganography payload which needs the password to be found
+pub fn is_carrier(data: &[u8]) -> bool {
+    lsb::capacity(data).is_ok() || dct::capacity(data).is_ok() || pcm::capacity(data).is_ok()
+}
+
+// the max payload size in bytes which can be hidden in the carrier
+pub fn capacity(backend: Backend, carrier: &[u8]) -> Result<usize> {
+    match backend {
+        Backend::Lsb => lsb::capacity(carrier),
+        Backend::Dct => dct::capacity(carrier),
+        Backend::Pcm => pcm::capacity(carrier),
+        _ => Err(anyhow!("{backend:?} is not a steganography backend")),
+    }
+}
+
+// the max size of the append file whose payload fits in the capacity
+pub fn max_append_size(capacity: usize) -> usize {
+    let overhead = MAGIC_NUM.len() * 2 + CHUNK_LEN_SIZE + MAX_HIDE_SPEC_SIZE;
+    let Some(size) = capacity.checked_sub(overhead) else {
+        return 0;
+    };
+
+    // a full chunk is encrypted to 4096 + 16 bytes
+    let chunk_overhead = CHUNK_LEN_SIZE + HASH_TEXT_SIZE;
+    let full_chunk_size = chunk_overhead + (CHUNK_SIZE + BLOCK_SIZE) * 2;
+    let (chunks, remain) = (size / full_chunk_size, size % full_chunk_size);
+
+    let blocks = remain.saturating_sub(chunk_overhead) / (BLOCK_SIZE * 2);
+    chunks * CHUNK_SIZE + (blocks * BLOCK_SIZE).saturating_sub(1)
+}
+
+// the payload which is stored in the carrier
+//  LAYOUT: payload_len(4 bytes, big endian) + payload;
+pub fn frame(payload: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(payload.len()).map_err(|_| anyhow!("payload is too large"))?;
+    Ok([&len.to_be_bytes()[..], payload].concat())
+}
+
+// the frame size in bits which is needed to store the payload
+pub fn frame_bits(payload: &[u8]) -> usize {
+    (LEN_SIZE + payload.len()) * 8
+}
+
+// read the payload from the bits, return None if it is not a hidebox payload
+pub fn unframe(mut next_bit: impl FnMut() -> Option<u8>, capacity: usize) -> Option<Vec<u8>> {
+    let mut read_byte = || {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | next_bit()?;
+        }
+        Some(byte)
+    };
+
+    let mut len = [0; LEN_SIZE];
+    for b in len.iter_mut() {
+        *b = read_byte()?;
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > capacity || len <= MAGIC_NUM.len() * 2 {
+        return None;
+    }
+
+    let mut payload = Vec::with_capacity(len);
+    for _ in 0..len {
+        payload.push(read_byte()?);
+        if payload.len() == MAGIC_NUM.len() && payload != MAGIC_NUM.as_bytes() {
+            return None;
+        }
+    }
+
+    if payload.ends_with(MAGIC_NUM.as_bytes()) {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+// the bits of a byte from the most significant bit
+pub fn bits(data: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    data.iter()
+        .flat_map(|b| (0..8).rev().map(move |i| (b >> i) & 1))
+}
+
+// a pseudo-random permutation of 0..total which is derived from the password,
+//  it is a lazy Fisher-Yates shuffle, so only the used positions are kept in memory.
+pub struct Positions {
+    rng: ChaCha20Rng,
+    total: usize,
+    index: usize,
+    swapped: HashMap<usize, usize>,
+}
+
+impl Positions {
+    pub fn new(password: &str, total: usize) -> Self {
+        let seed = digest(
+            Algorithm::SHA256,
+            format!("hidebox-stego:{password}").as_bytes(),
+        );
+        let mut key = [0; 32];
+        key.copy_from_slice(&seed);
+
+        Self {
+            rng: ChaCha20Rng::from_seed(key),
+            total,
+            index: 0,
+            swapped: HashMap::new(),
+        }
+    }
+}
+
+impl Iterator for Positions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.index >= self.total {
+            return None;
+        }
+
+        let (i, j) = (self.index, self.rng.gen_range(self.index..self.total));
+        let value_i = *self.swapped.get(&i).unwrap_or(&i);
+        let value_j = *self.swapped.get(&j).unwrap_or(&j);
+
+        self.swapped.insert(j, value_i);
+        self.swapped.remove(&i);
+        self.index += 1;
+        Some(value_j)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_stego_positions() {
+        let positions = Positions::new("123456", 1000).collect::<Vec<_>>();
+        assert_eq!(positions.len(), 1000);
+        assert_eq!(positions.iter().collect::<HashSet<_>>().len(), 1000);
+        assert!(positions.iter().all(|p| *p < 1000));
+        assert_ne!(positions, (0..1000).collect::<Vec<_>>());
+
+        let positions_2 = Positions::new("123456", 1000).take(100).collect::<Vec<_>>();
+        assert_eq!(positions[..100], positions_2);
+
+        let positions_3 = Positions::new("654321", 1000).take(100).collect::<Vec<_>>();
+        assert_ne!(positions[..100], positions_3);
+    }
+
+    #[test]
+    fn test_stego_frame() -> Result<()> {
+        let payload = [MAGIC_NUM.as_bytes(), b"data", MAGIC_NUM.as_bytes()].concat();
+        let frame = frame(&payload)?;
+        assert_eq!(frame_bits(&payload), frame.len() * 8);
+
+        let mut bits = bits(&frame);
+        assert_eq!(unframe(|| bits.next(), 100).unwrap(), payload);
+
+        let mut bits = super::bits(&frame);
+        assert!(unframe(|| bits.next(), 10).is_none());
+
+        let mut bits = super::bits(&[0xFF; 100]);
+        assert!(unframe(|| bits.next(), 100).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stego_size_change() -> Result<()> {
+        let cur_dir = std::env::current_dir()?;
+        let payload = [MAGIC_NUM.as_bytes(), b"data", MAGIC_NUM.as_bytes()].concat();
+
+        for (name, is_changed) in [("src.png", true), ("src.bmp", false)] {
+            let carrier = std::fs::read(cur_dir.join("../testdata").join(name))?;
+            let (output, report) = embed(Backend::Lsb, &carrier, &payload, "123456")?;
+            assert_eq!(
+                report.size_change,
+                output.len() as i64 - carrier.len() as i64
+            );
+            assert_eq!(report.size_change != 0, is_changed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stego_max_append_size() {
+        assert_eq!(max_append_size(100), 0);
+        for size in [1, 15, 16, 4095, 4096, 4097, 10000, 100000] {
+            let chunks = size / CHUNK_SIZE;
+            let remain = size % CHUNK_SIZE;
+            let mut payload_size = MAGIC_NUM.len() * 2 + CHUNK_LEN_SIZE + MAX_HIDE_SPEC_SIZE;
+            payload_size +=
+                chunks * (CHUNK_LEN_SIZE + HASH_TEXT_SIZE + (CHUNK_SIZE + BLOCK_SIZE) * 2);
+            if remain > 0 {
+                payload_size +=
+                    CHUNK_LEN_SIZE + HASH_TEXT_SIZE + (remain / BLOCK_SIZE + 1) * BLOCK_SIZE * 2;
+            }
+
+            assert!(max_append_size(payload_size) >= size);
+            assert!(max_append_size(payload_size - 1) < size);
+        }
+    }
+}
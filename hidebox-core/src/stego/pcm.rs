@@ -0,0 +1,198 @@
+use super::{bits, frame, frame_bits, unframe, Positions, Report, LEN_SIZE};
+use anyhow::{anyhow, Result};
+
+const RIFF_ID: &[u8] = b"RIFF";
+const WAVE_ID: &[u8] = b"WAVE";
+const CHUNK_HEADER_SIZE: usize = 8;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const SAMPLE_SIZE: usize = 2;
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16> {
+    match data.get(pos..pos + 2) {
+        Some(v) => Ok(u16::from_le_bytes([v[0], v[1]])),
+        None => Err(anyhow!("unexpected end of wav file")),
+    }
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<usize> {
+    match data.get(pos..pos + 4) {
+        Some(v) => Ok(u32::from_le_bytes(v.try_into()?) as usize),
+        None => Err(anyhow!("unexpected end of wav file")),
+    }
+}
+
+// LAYOUT: RIFF + size(4 bytes) + WAVE + chunks;
+//  fmt: format(2 bytes) + channels(2 bytes) + sample rate(4 bytes) + byte rate(4 bytes)
+//  + block align(2 bytes) + bits per sample(2 bytes) + [extension];
+//  the 16 bits samples of the data chunk are little endian.
+// Return the start and the number of the samples.
+fn parse_wav(data: &[u8]) -> Result<(usize, usize)> {
+    if data.len() < CHUNK_HEADER_SIZE + 4 || !data.starts_with(RIFF_ID) || &data[8..12] != WAVE_ID {
+        return Err(anyhow!("not a wav file"));
+    }
+
+    let riff_end = usize::min(CHUNK_HEADER_SIZE + u32_at(data, 4)?, data.len());
+    let (mut format, mut samples) = (None, None);
+    let mut pos = CHUNK_HEADER_SIZE + 4;
+
+    while pos + CHUNK_HEADER_SIZE <= riff_end {
+        let size = u32_at(data, pos + 4)?;
+        let start = pos + CHUNK_HEADER_SIZE;
+        let end = start.saturating_add(size);
+        if end > riff_end {
+            return Err(anyhow!("invalid wav chunk size at {pos}"));
+        }
+
+        match &data[pos..pos + 4] {
+            b"fmt " => format = Some(&data[start..end]),
+            b"data" => samples = Some((start, size / SAMPLE_SIZE)),
+            _ => (),
+        }
+        pos = end + size % 2;
+    }
+
+    let (Some(format), Some(samples)) = (format, samples) else {
+        return Err(anyhow!("invalid wav file, fmt or data chunk is missing"));
+    };
+
+    let mut tag = u16_at(format, 0)?;
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        // the first 2 bytes of the sub format GUID is the format tag
+        tag = u16_at(format, 24)?;
+    }
+
+    if tag != WAVE_FORMAT_PCM || u16_at(format, 14)? as usize != SAMPLE_SIZE * 8 {
+        return Err(anyhow!(
+            "unsupported wav format, only 16 bits PCM wav files are supported"
+        ));
+    }
+
+    Ok(samples)
+}
+
+// the max payload size in bytes
+pub fn capacity(carrier: &[u8]) -> Result<usize> {
+    let (_, total) = parse_wav(carrier)?;
+    Ok((total / 8).saturating_sub(LEN_SIZE))
+}
+
+// write the payload bits into the least significant bits of the samples which are chosen in a
+//  pseudo-random order derived from the password, a sample is changed by one at most.
+//  The file size and the other chunks are not changed.
+pub fn embed(carrier: &[u8], payload: &[u8], password: &str) -> Result<(Vec<u8>, Report)> {
+    let (start, total) = parse_wav(carrier)?;
+
+    if frame_bits(payload) > total {
+        return Err(anyhow!(
+            "payload is too large, the capacity of the wav file is {} bytes",
+            (total / 8).saturating_sub(LEN_SIZE)
+        ));
+    }
+
+    let frame = frame(payload)?;
+    let mut output = carrier.to_vec();
+    let mut report = Report {
+        bits: frame.len() * 8,
+        ..Default::default()
+    };
+
+    for (bit, index) in bits(&frame).zip(Positions::new(password, total)) {
+        let pos = start + index * SAMPLE_SIZE;
+        if output[pos] & 1 != bit {
+            output[pos] ^= 1;
+            report.changes += 1;
+        }
+    }
+
+    Ok((output, report))
+}
+
+pub fn extract(data: &[u8], password: &str) -> Option<Vec<u8>> {
+    let (start, total) = parse_wav(data).ok()?;
+    let mut positions = Positions::new(password, total);
+
+    unframe(
+        || Some(data[start + positions.next()? * SAMPLE_SIZE] & 1),
+        (total / 8).saturating_sub(LEN_SIZE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MAGIC_NUM;
+    use super::*;
+    use crate::util;
+    use std::env;
+
+    const PASSWORD: &str = "123456";
+
+    fn carrier() -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata/src.wav"))?)
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        let text = util::str::random_string(len);
+        [MAGIC_NUM.as_bytes(), text.as_bytes(), MAGIC_NUM.as_bytes()].concat()
+    }
+
+    fn samples(data: &[u8]) -> Result<Vec<i16>> {
+        let reader = hound::WavReader::new(data)?;
+        Ok(reader
+            .into_samples::<i16>()
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    #[test]
+    fn test_pcm_embed_extract() -> Result<()> {
+        let carrier = carrier()?;
+        assert!(extract(&carrier, PASSWORD).is_none());
+
+        let capacity = capacity(&carrier)?;
+        let payload = payload(capacity - MAGIC_NUM.len() * 2);
+        let (output, report) = embed(&carrier, &payload, PASSWORD)?;
+        assert_eq!(extract(&output, PASSWORD).unwrap(), payload);
+        assert!(extract(&output, "654321").is_none());
+        assert_eq!(output.len(), carrier.len());
+
+        // the samples are changed by one at most
+        let (src_samples, samples) = (samples(&carrier)?, samples(&output)?);
+        assert_eq!(src_samples.len(), samples.len());
+        assert!(src_samples
+            .iter()
+            .zip(samples.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= 1));
+        let changes = src_samples
+            .iter()
+            .zip(samples.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(report.changes, changes);
+
+        assert!(embed(&carrier, &super::super::frame(&payload)?, PASSWORD).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pcm_unsupported() -> Result<()> {
+        assert!(capacity(b"not a wav file").is_err());
+
+        let mut buffer = std::io::Cursor::new(vec![]);
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 8,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::new(&mut buffer, spec)?;
+        for i in 0..1000 {
+            writer.write_sample((i % 100) as i8)?;
+        }
+        writer.finalize()?;
+
+        assert!(capacity(buffer.get_ref()).is_err());
+        Ok(())
+    }
+}
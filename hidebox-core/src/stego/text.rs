@@ -0,0 +1,145 @@
+use crate::util;
+use anyhow::{anyhow, Result};
+
+// every zero-width character carries 2 bits
+const ZERO_WIDTH_CHARS: [char; 4] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}'];
+
+// the message is short because it is pasted into a chat message
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+
+// the first bytes of the keyed hash, it tells a wrong password from a damaged text
+const CHECK_SIZE: usize = 4;
+
+fn is_zero_width(c: char) -> bool {
+    ZERO_WIDTH_CHARS.contains(&c)
+}
+
+fn check(password: &str, encrypt_text: &str) -> Result<Vec<u8>> {
+    let hash = util::crypto::hash(&format!("{password}{encrypt_text}"));
+    Ok(hex::decode(&hash[..CHECK_SIZE * 2])?)
+}
+
+// LAYOUT: encrypt_data + check(4 bytes);
+//  the bytes are written as zero-width characters from the most significant bits,
+//  and they are spread between the visible characters of the cover text.
+pub fn encode(cover: &str, message: &str, password: &str) -> Result<String> {
+    let cover = cover
+        .chars()
+        .filter(|c| !is_zero_width(*c))
+        .collect::<Vec<_>>();
+    if cover.len() < 2 {
+        return Err(anyhow!("cover text is too short"));
+    }
+
+    if message.is_empty() || message.len() > MAX_MESSAGE_SIZE {
+        return Err(anyhow!(
+            "message size should be from 1 to {MAX_MESSAGE_SIZE} bytes"
+        ));
+    }
+
+    let encrypt_text = util::crypto::encrypt(password, message.as_bytes())?;
+    let mut data = hex::decode(&encrypt_text)?;
+    data.extend(check(password, &encrypt_text)?);
+
+    let hidden = data
+        .iter()
+        .flat_map(|b| {
+            (0..4)
+                .rev()
+                .map(move |i| ZERO_WIDTH_CHARS[((b >> (i * 2)) & 3) as usize])
+        })
+        .collect::<Vec<_>>();
+
+    // the hidden characters are put after every visible character except the last one
+    let gaps = cover.len() - 1;
+    let mut text = String::with_capacity(cover.len() * 4 + hidden.len() * 3);
+    let mut hidden = hidden.iter();
+    let (per_gap, remain) = (hidden.len() / gaps, hidden.len() % gaps);
+
+    for (i, c) in cover.iter().enumerate() {
+        text.push(*c);
+        if i < gaps {
+            let count = per_gap + usize::from(i < remain);
+            text.extend(hidden.by_ref().take(count));
+        }
+    }
+
+    Ok(text)
+}
+
+// the message hidden in the pasted text, the visible characters are ignored
+pub fn decode(text: &str, password: &str) -> Result<String> {
+    let hidden = text
+        .chars()
+        .filter_map(|c| ZERO_WIDTH_CHARS.iter().position(|z| *z == c))
+        .collect::<Vec<_>>();
+
+    if hidden.is_empty() {
+        return Err(anyhow!("no hidden message is found"));
+    }
+
+    if hidden.len() % 4 != 0 || hidden.len() / 4 < CHECK_SIZE + 16 {
+        return Err(anyhow!("hidden message is damaged"));
+    }
+
+    let data = hidden
+        .chunks(4)
+        .map(|c| c.iter().fold(0_u8, |b, v| (b << 2) | *v as u8))
+        .collect::<Vec<_>>();
+
+    let (encrypt_data, check_data) = data.split_at(data.len() - CHECK_SIZE);
+    let encrypt_text = hex::encode(encrypt_data);
+    if check(password, &encrypt_text)? != check_data {
+        return Err(anyhow!("wrong password or the hidden message is damaged"));
+    }
+
+    let message = util::crypto::decrypt(password, &encrypt_text)?;
+    Ok(String::from_utf8(message)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "123456";
+    const COVER: &str = "See you at the station tomorrow.";
+
+    #[test]
+    fn test_text_encode_decode() -> Result<()> {
+        for message in ["hi", "明天下午三点见", &"x".repeat(MAX_MESSAGE_SIZE)] {
+            let text = encode(COVER, message, PASSWORD)?;
+            assert_eq!(
+                text.chars()
+                    .filter(|c| !is_zero_width(*c))
+                    .collect::<String>(),
+                COVER
+            );
+            assert!(text.starts_with('S') && text.ends_with('.'));
+            assert_eq!(decode(&text, PASSWORD)?, message);
+
+            // the text is pasted with other words around it
+            let pasted = format!("> {text}\nok");
+            assert_eq!(decode(&pasted, PASSWORD)?, message);
+
+            assert!(decode(&text, "654321").is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_invalid() -> Result<()> {
+        assert!(encode("a", "hi", PASSWORD).is_err());
+        assert!(encode(COVER, "", PASSWORD).is_err());
+        assert!(encode(COVER, &"x".repeat(MAX_MESSAGE_SIZE + 1), PASSWORD).is_err());
+        assert!(decode(COVER, PASSWORD).is_err());
+
+        // the hidden characters of the cover text are replaced
+        let text = encode(COVER, "first", PASSWORD)?;
+        let text = encode(&text, "second", PASSWORD)?;
+        assert_eq!(decode(&text, PASSWORD)?, "second");
+
+        let damaged = text.replacen('\u{200B}', "", 1);
+        assert!(decode(&damaged, PASSWORD).is_err());
+        Ok(())
+    }
+}
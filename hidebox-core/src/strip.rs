@@ -0,0 +1,158 @@
+use super::error::{HideboxError, Result};
+use super::*;
+use std::path::Path;
+
+fn hex_len(data: &[u8], pos: usize) -> Option<usize> {
+    let text = std::str::from_utf8(data.get(pos..pos + CHUNK_LEN_SIZE)?).ok()?;
+    usize::from_str_radix(text.trim(), 16).ok()
+}
+
+// the position of the first MAGIC_NUM of the trailer, the payload is found by its
+//  structure, so the password is not needed. A candidate is the start of the payload
+//  if its chunks end exactly at the hide spec data.
+pub fn trailer_start(data: &[u8]) -> Option<usize> {
+    let spec_len_pos = data.len().checked_sub(CHUNK_LEN_SIZE + MAGIC_NUM.len())?;
+    let spec_start = spec_len_pos.checked_sub(hex_len(data, spec_len_pos)?)?;
+
+    let magic = MAGIC_NUM.as_bytes();
+    let candidates = data[..spec_start]
+        .windows(magic.len())
+        .enumerate()
+        .filter(|(_, w)| *w == magic)
+        .map(|(pos, _)| pos)
+        .collect::<Vec<_>>();
+
+    candidates.into_iter().rev().find(|start| {
+        let mut pos = start + magic.len();
+        while pos < spec_start {
+            match hex_len(data, pos) {
+                Some(len) if len >= HASH_TEXT_SIZE => pos += CHUNK_LEN_SIZE + len,
+                _ => return false,
+            }
+        }
+        pos == spec_start
+    })
+}
+
+// the payloads which are embedded in the file structure can not be removed, the file is
+//  checked before it is changed so that no payload is left behind silently
+async fn check_embedded(file_spec: &FileSpec) -> Result<()> {
+    let mut file = tokio::fs::File::open(&file_spec.path).await?;
+    let Some(data) = decode::read_carrier(&mut file, decode::MAX_CARRIER_SIZE).await? else {
+        return Ok(());
+    };
+
+    if backend::extract(&data).is_some() {
+        return Err(HideboxError::unsupported(format!(
+            "the payload of the {} backend can not be stripped",
+            sniff::sniff(&data).backend().name()
+        )));
+    }
+    Ok(())
+}
+
+// remove the trailer and the extended attributes payloads from the file in place,
+//  return false if the file does not contain them. The embedded payloads are not removed,
+//  an unsupported error is returned for them and the file is not changed.
+pub async fn strip(file_spec: &FileSpec) -> Result<bool> {
+    let path = Path::new(&file_spec.path);
+    if !decode::has_trailer(file_spec).await? {
+        check_embedded(file_spec).await?;
+    }
+
+    let mut is_stripped = attr::strip(path)?;
+
+    if decode::has_trailer(file_spec).await? {
+        let data = tokio::fs::read(path).await?;
+        let Some(start) = trailer_start(&data) else {
+            return Err(HideboxError::corrupt(
+                "invalid trailer, the start of the payload is not found",
+            ));
+        };
+
+        let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+        file.set_len(start as u64).await?;
+        file.sync_all().await?;
+        is_stripped = true;
+    }
+
+    Ok(is_stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::env;
+    use std::sync::Arc;
+
+    fn pcb(_info: ProgressInfo) {}
+
+    async fn file_spec(path: &Path) -> Result<FileSpec> {
+        Ok(FileSpec {
+            path: path.to_str().unwrap().to_string(),
+            name: path.file_name().unwrap().to_str().unwrap().to_string(),
+            size: tokio::fs::metadata(path).await?.len(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_strip_trailer() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+        let append_file_path = cur_dir.join("../testdata/append-more-than-4k.dat");
+
+        // the carrier contains the magic number
+        let src = [MAGIC_NUM.as_bytes(), b"carrier"].concat();
+        let src_file_path = env::temp_dir().join("hidebox-strip-src.dat");
+        let dst_file_path = env::temp_dir().join("hidebox-strip-dst.dat");
+        tokio::fs::write(&src_file_path, &src).await?;
+
+        encode::encode(
+            file_spec(&src_file_path).await?,
+            file_spec(&append_file_path).await?,
+            &dst_file_path,
+            "123456",
+            Backend::Trailer,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let data = tokio::fs::read(&dst_file_path).await?;
+        assert_eq!(trailer_start(&data), Some(src.len()));
+        assert_eq!(trailer_start(&src), None);
+
+        assert!(strip(&file_spec(&dst_file_path).await?).await?);
+        assert_eq!(tokio::fs::read(&dst_file_path).await?, src);
+        assert!(!strip(&file_spec(&dst_file_path).await?).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_strip_embedded() -> Result<()> {
+        let cur_dir = env::current_dir()?;
+        let src_file_path = cur_dir.join("../testdata/src.zip");
+        let append_file_path = cur_dir.join("../testdata/append-less-than-4k.dat");
+        let dst_file_path = env::temp_dir().join("hidebox-strip-embedded.zip");
+
+        encode::encode(
+            file_spec(&src_file_path).await?,
+            file_spec(&append_file_path).await?,
+            &dst_file_path,
+            "123456",
+            Backend::Zip,
+            Arc::new(pcb),
+            &CancelHandle::new(),
+        )
+        .await?;
+
+        let data = tokio::fs::read(&dst_file_path).await?;
+        let result = strip(&file_spec(&dst_file_path).await?).await;
+        assert!(matches!(result, Err(HideboxError::Unsupported(_))));
+        assert_eq!(tokio::fs::read(&dst_file_path).await?, data);
+
+        // a carrier without a payload is not stripped
+        assert!(!strip(&file_spec(&src_file_path).await?).await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use super::{backend, sniff, sniff::FileType};
+use anyhow::{anyhow, Result};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1A\n";
+const PNG_CHUNK_HEADER_SIZE: usize = 8;
+const PNG_CRC_SIZE: usize = 4;
+
+// LAYOUT: signature + chunks, chunk = len(4 bytes) + type(4 bytes) + data + crc(4 bytes);
+//  the crc covers the type and the data, the chunks after IEND are ignored by the readers.
+fn verify_png(data: &[u8]) -> Result<()> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(anyhow!("not a png file"));
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    let mut has_idat = false;
+    loop {
+        let Some(header) = data.get(pos..pos + PNG_CHUNK_HEADER_SIZE) else {
+            return Err(anyhow!("png IEND chunk is not found"));
+        };
+
+        let len = u32::from_be_bytes(header[..4].try_into()?) as usize;
+        let kind = &header[4..];
+        if !kind.iter().all(|c| c.is_ascii_alphabetic()) {
+            return Err(anyhow!("invalid png chunk type at {pos}"));
+        }
+
+        if pos == PNG_SIGNATURE.len() && kind != b"IHDR" {
+            return Err(anyhow!("png IHDR chunk is not the first chunk"));
+        }
+
+        let data_end = pos + PNG_CHUNK_HEADER_SIZE + len;
+        let Some(crc) = data.get(data_end..data_end + PNG_CRC_SIZE) else {
+            return Err(anyhow!("unexpected end of png chunk at {pos}"));
+        };
+
+        if crc32fast::hash(&data[pos + 4..data_end]).to_be_bytes() != crc {
+            return Err(anyhow!(
+                "png {} chunk crc mismatch at {pos}",
+                String::from_utf8_lossy(kind)
+            ));
+        }
+
+        match kind {
+            b"IDAT" => has_idat = true,
+            b"IEND" if has_idat => return Ok(()),
+            b"IEND" => return Err(anyhow!("png IDAT chunk is not found")),
+            _ => (),
+        }
+
+        pos = data_end + PNG_CRC_SIZE;
+    }
+}
+
+// the types which have a validator, the others are not read to be verified
+pub fn has_validator(file_type: FileType) -> bool {
+    matches!(
+        file_type,
+        FileType::Png | FileType::Jpeg | FileType::Zip | FileType::Pdf | FileType::Mp4
+    )
+}
+
+// parse the carrier with the validator of its type, return false if
+//  there is no validator for the type.
+pub fn verify(data: &[u8]) -> Result<bool> {
+    match sniff::sniff(data) {
+        FileType::Png => verify_png(data)?,
+        FileType::Jpeg => backend::jpeg::verify(data)?,
+        FileType::Zip => backend::zip::verify(data)?,
+        FileType::Pdf => backend::pdf::verify(data)?,
+        FileType::Mp4 => backend::mp4::verify(data)?,
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn carrier(name: &str) -> Result<Vec<u8>> {
+        let cur_dir = env::current_dir()?;
+        Ok(std::fs::read(cur_dir.join("../testdata").join(name))?)
+    }
+
+    #[test]
+    fn test_verify_carrier() -> Result<()> {
+        for name in [
+            "src.png",
+            "src.jpg",
+            "src-dct.jpg",
+            "src.zip",
+            "src.pdf",
+            "src-xref-stream.pdf",
+            "src.mp4",
+        ] {
+            assert!(verify(&carrier(name)?)?, "{name}");
+        }
+
+        assert!(!verify(&carrier("src.dat")?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_broken_carrier() -> Result<()> {
+        let trailer = vec![b'x'; 70000];
+
+        // the png and jpeg readers ignore the data after the end of the image
+        for name in ["src.png", "src.jpg"] {
+            let data = [carrier(name)?, trailer.clone()].concat();
+            assert!(verify(&data)?, "{name}");
+        }
+
+        // the zip, pdf and mp4 readers look for their structures at the end of the file
+        for name in ["src.zip", "src.pdf", "src.mp4"] {
+            let data = [carrier(name)?, trailer.clone()].concat();
+            assert!(verify(&data).is_err(), "{name}");
+        }
+
+        let mut data = carrier("src.png")?;
+        data[PNG_SIGNATURE.len() + PNG_CHUNK_HEADER_SIZE] ^= 0x01;
+        assert!(verify(&data).is_err());
+
+        let data = carrier("src.jpg")?;
+        assert!(verify(&data[..data.len() / 2]).is_err());
+        Ok(())
+    }
+}
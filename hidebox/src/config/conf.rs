@@ -20,6 +20,10 @@ pub fn ui() -> data::UI {
     CONFIG.lock().unwrap().borrow().ui.clone()
 }
 
+pub fn history() -> data::History {
+    CONFIG.lock().unwrap().borrow().history.clone()
+}
+
 #[allow(unused)]
 pub fn conf_path() -> String {
     let conf = CONFIG.lock().unwrap();
@@ -28,7 +32,6 @@ pub fn conf_path() -> String {
     conf.config_path.clone()
 }
 
-#[allow(unused)]
 pub fn db_path() -> String {
     let conf = CONFIG.lock().unwrap();
     let conf = conf.borrow();
@@ -106,6 +109,7 @@ impl Config {
             Ok(text) => match serde_json::from_str::<Config>(&text) {
                 Ok(c) => {
                     self.ui = c.ui;
+                    self.history = c.history;
                     Ok(())
                 }
                 Err(e) => Err(e.into()),
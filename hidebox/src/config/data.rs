@@ -10,6 +10,9 @@ pub struct Config {
     pub cache_dir: String,
 
     pub ui: UI,
+
+    #[serde(default)]
+    pub history: History,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -32,3 +35,9 @@ impl Default for UI {
         }
     }
 }
+
+// the operation history is opt-in, nothing is written to the database until it is enabled
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct History {
+    pub enabled: bool,
+}
@@ -1,4 +1,4 @@
 mod conf;
 mod data;
 
-pub use conf::{cache_dir, config, init, save, ui};
+pub use conf::{cache_dir, config, db_path, history, init, save, ui};
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use sqlx::{Pool, Sqlite};
+
+// the search returns the latest entries only
+const MAX_SEARCH_ENTRIES: i64 = 1000;
+
+// one operation of the encoding, decoding or stripping. The passwords are never stored.
+//  - encode: the payload is hidden in the carrier and written to the output
+//  - decode: the payload of the carrier is written to the output
+//  - strip: the payloads are removed from the carrier in place
+#[derive(sqlx::FromRow, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub id: i64,
+    pub timestamp: String,
+    pub operation: String,
+    pub carrier: String,
+    pub payload: String,
+    pub payload_name: String,
+    pub output: String,
+
+    // "stego" or "xattr" if the option of the encoding is checked
+    pub mode: String,
+
+    pub size: i64,
+
+    // "done", "failed" or "cancelled", the message is the reason of the failure
+    pub status: String,
+    pub message: String,
+}
+
+pub async fn new(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS history (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             timestamp TEXT NOT NULL,
+             operation TEXT NOT NULL,
+             carrier TEXT NOT NULL,
+             payload TEXT NOT NULL,
+             payload_name TEXT NOT NULL,
+             output TEXT NOT NULL,
+             mode TEXT NOT NULL,
+             size INTEGER NOT NULL,
+             status TEXT NOT NULL,
+             message TEXT NOT NULL
+         )",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// the id of the entry is ignored, the id of the inserted entry is returned
+pub async fn insert(pool: &Pool<Sqlite>, entry: &Entry) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT INTO history (timestamp, operation, carrier, payload, payload_name, output, mode, size, status, message)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&entry.timestamp)
+    .bind(&entry.operation)
+    .bind(&entry.carrier)
+    .bind(&entry.payload)
+    .bind(&entry.payload_name)
+    .bind(&entry.output)
+    .bind(&entry.mode)
+    .bind(entry.size)
+    .bind(&entry.status)
+    .bind(&entry.message)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn select(pool: &Pool<Sqlite>, id: i64) -> Result<Option<Entry>> {
+    Ok(
+        sqlx::query_as::<_, Entry>("SELECT * FROM history WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+// the keyword matches the paths, the payload name, the operation and the message,
+//  the latest entries come first
+pub async fn search(pool: &Pool<Sqlite>, keyword: &str) -> Result<Vec<Entry>> {
+    let pattern = format!("%{}%", keyword.trim());
+
+    Ok(sqlx::query_as::<_, Entry>(
+        "SELECT * FROM history
+         WHERE carrier LIKE ?1 OR payload LIKE ?1 OR payload_name LIKE ?1
+             OR output LIKE ?1 OR operation LIKE ?1 OR message LIKE ?1
+         ORDER BY id DESC LIMIT ?2",
+    )
+    .bind(pattern)
+    .bind(MAX_SEARCH_ENTRIES)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn delete(pool: &Pool<Sqlite>, id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM history WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_all(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query("DELETE FROM history").execute(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_history() -> Result<()> {
+        let db_path =
+            std::env::temp_dir().join(format!("hidebox-history-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&db_path);
+        let pool = crate::db::open(db_path.to_str().unwrap()).await?;
+
+        let encode = Entry {
+            timestamp: "2024-01-01 00:00:00".to_string(),
+            operation: "encode".to_string(),
+            carrier: "/tmp/cover.png".to_string(),
+            payload: "/tmp/secret.txt".to_string(),
+            payload_name: "secret.txt".to_string(),
+            output: "/tmp/cover-secret.png".to_string(),
+            mode: "stego".to_string(),
+            size: 42,
+            status: "done".to_string(),
+            ..Default::default()
+        };
+        let decode = Entry {
+            operation: "decode".to_string(),
+            carrier: "/tmp/cover-secret.png".to_string(),
+            status: "failed".to_string(),
+            message: "Password Invalid".to_string(),
+            ..encode.clone()
+        };
+
+        let encode_id = insert(&pool, &encode).await?;
+        let decode_id = insert(&pool, &decode).await?;
+
+        let entries = search(&pool, "").await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, decode_id);
+        assert_eq!(
+            entries[1],
+            Entry {
+                id: encode_id,
+                ..encode.clone()
+            }
+        );
+
+        let entries = search(&pool, " invalid ").await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, "decode");
+        assert_eq!(search(&pool, "nothing").await?.len(), 0);
+
+        delete(&pool, decode_id).await?;
+        assert!(select(&pool, decode_id).await?.is_none());
+        assert_eq!(
+            select(&pool, encode_id).await?.unwrap().payload_name,
+            "secret.txt"
+        );
+
+        delete_all(&pool).await?;
+        assert!(search(&pool, "").await?.is_empty());
+
+        pool.close().await;
+        let _ = std::fs::remove_file(&db_path);
+        Ok(())
+    }
+}
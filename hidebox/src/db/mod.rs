@@ -0,0 +1,38 @@
+pub mod history;
+
+use crate::config;
+use anyhow::Result;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref POOL: Mutex<Option<Pool<Sqlite>>> = Mutex::new(None);
+}
+
+// the database is opened on the first use, so it is not created until the history is enabled
+pub async fn pool() -> Result<Pool<Sqlite>> {
+    let pool = POOL.lock().unwrap().clone();
+    if let Some(pool) = pool {
+        return Ok(pool);
+    }
+
+    let pool = open(&config::db_path()).await?;
+    *POOL.lock().unwrap() = Some(pool.clone());
+    Ok(pool)
+}
+
+// create the database file and the tables if they do not exist
+pub async fn open(db_path: &str) -> Result<Pool<Sqlite>> {
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true);
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(3)
+        .connect_with(options)
+        .await?;
+
+    history::new(&pool).await?;
+    Ok(pool)
+}
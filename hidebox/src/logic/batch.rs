@@ -0,0 +1,699 @@
+use crate::db::history::Entry;
+use crate::logic::{encode::select_backend, history, util::progress_detail};
+use crate::message::{async_message_success, error_message, is_cancelled};
+use crate::message_warn;
+use crate::slint_generatedAppWindow::{AppWindow, BatchItem, Logic, Store};
+use crate::util::translator::tr;
+use anyhow::{anyhow, Result};
+use hidebox_core::{CancelHandle, FileSpec, Job};
+use native_dialog::FileDialog;
+use slint::{ComponentHandle, Model, ModelRc, VecModel, Weak};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tokio::task::spawn;
+
+// the number of the jobs which run at the same time
+const MAX_CONCURRENT_JOBS: usize = 4;
+
+// only one batch runs at the same time, the jobs can not be changed while it runs
+static IS_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Failed => "failed",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    fn text(&self) -> String {
+        tr(match self {
+            Status::Pending => "等待",
+            Status::Running => "运行中",
+            Status::Done => "完成",
+            Status::Failed => "失败",
+            Status::Cancelled => "已取消",
+        })
+    }
+}
+
+// the decoding jobs have no append file, their dst_file is renamed to the
+//  original name of the payload after the decoding
+#[derive(Clone, Debug)]
+struct BatchJob {
+    src_file: String,
+    append_file: String,
+    dst_file: String,
+    status: Status,
+}
+
+#[derive(Clone, Debug, Default)]
+struct BatchFiles {
+    src_files: Vec<String>,
+    append_files: Vec<String>,
+}
+
+lazy_static! {
+    // the files which are picked for the next batch
+    static ref BATCH_FILES: Mutex<BatchFiles> = Mutex::new(BatchFiles::default());
+
+    // the jobs of the batch, the rows of the jobs panel have the same order
+    static ref BATCH_JOBS: Mutex<Vec<BatchJob>> = Mutex::new(vec![]);
+
+    // the cancel handles of the running batches and their running jobs
+    static ref BATCH_CANCEL_HANDLES: Mutex<Vec<CancelHandle>> = Mutex::new(vec![]);
+}
+
+pub fn init(ui: &AppWindow) {
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_load_batch_src_files(move || {
+        let ui = ui_handle.unwrap();
+
+        match FileDialog::new()
+            .set_location("~")
+            .show_open_multiple_file()
+        {
+            Ok(files) if !files.is_empty() => {
+                BATCH_FILES.lock().unwrap().src_files = path_strings(files);
+                ui.global::<Logic>().invoke_update_batch_jobs();
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
+            }
+            _ => (),
+        };
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_load_batch_append_files(move || {
+        let ui = ui_handle.unwrap();
+
+        match FileDialog::new()
+            .set_location("~")
+            .show_open_multiple_file()
+        {
+            Ok(files) if !files.is_empty() => {
+                BATCH_FILES.lock().unwrap().append_files = path_strings(files);
+                ui.global::<Logic>().invoke_update_batch_jobs();
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
+            }
+            _ => (),
+        };
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_load_batch_dst_dir(move || {
+        let ui = ui_handle.unwrap();
+
+        match FileDialog::new().set_location("~").show_open_single_dir() {
+            Ok(Some(dir)) => {
+                let mut spec = ui.global::<Store>().get_batch_spec();
+                spec.dst_dir = dir.to_str().unwrap().into();
+                ui.global::<Store>().set_batch_spec(spec);
+                ui.global::<Logic>().invoke_update_batch_jobs();
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
+            }
+            _ => (),
+        };
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_update_batch_jobs(move || {
+        let ui = ui_handle.unwrap();
+        if is_running() {
+            message_warn!(&ui, tr("批处理正在运行"));
+            return;
+        }
+
+        let mut spec = ui.global::<Store>().get_batch_spec();
+        let files = BATCH_FILES.lock().unwrap().clone();
+        let jobs = make_jobs(
+            &files,
+            Path::new(spec.dst_dir.as_str()),
+            spec.is_decode,
+            spec.rule.as_str(),
+        );
+
+        spec.src_count = files.src_files.len() as i32;
+        spec.append_count = files.append_files.len() as i32;
+        spec.progress = 0.0;
+        spec.items = ModelRc::new(VecModel::from(jobs.iter().map(ui_item).collect::<Vec<_>>()));
+        *BATCH_JOBS.lock().unwrap() = jobs;
+        spec.summary = summary().into();
+        ui.global::<Store>().set_batch_spec(spec);
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_clear_batch(move || {
+        let ui = ui_handle.unwrap();
+        if is_running() {
+            message_warn!(&ui, tr("批处理正在运行"));
+            return;
+        }
+
+        *BATCH_FILES.lock().unwrap() = BatchFiles::default();
+        BATCH_JOBS.lock().unwrap().clear();
+
+        let mut spec = ui.global::<Store>().get_batch_spec();
+        spec.src_count = 0;
+        spec.append_count = 0;
+        spec.progress = 0.0;
+        spec.summary = Default::default();
+        spec.items = ModelRc::default();
+        ui.global::<Store>().set_batch_spec(spec);
+    });
+
+    ui.global::<Logic>().on_cancel_batch(move || {
+        for cancel_handle in BATCH_CANCEL_HANDLES.lock().unwrap().drain(..) {
+            cancel_handle.cancel();
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_batch(move |password| {
+        let ui = ui_handle.unwrap();
+        let indexes = BATCH_JOBS
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| job.status != Status::Done)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        start(&ui, indexes, password.to_string());
+    });
+
+    // the index is -1 to retry all the failed and the canceled jobs
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_retry_batch(move |password, index| {
+        let ui = ui_handle.unwrap();
+        let indexes = BATCH_JOBS
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(i, job)| {
+                (index < 0 || *i == index as usize)
+                    && matches!(job.status, Status::Failed | Status::Cancelled)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        start(&ui, indexes, password.to_string());
+    });
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Settings {
+    is_decode: bool,
+    stego: bool,
+    xattr: bool,
+}
+
+fn is_running() -> bool {
+    IS_RUNNING.load(Ordering::SeqCst)
+}
+
+fn start(ui: &AppWindow, indexes: Vec<usize>, password: String) {
+    if is_running() {
+        message_warn!(ui, tr("批处理正在运行"));
+        return;
+    }
+
+    if indexes.is_empty() {
+        message_warn!(ui, tr("没有可运行的任务"));
+        return;
+    }
+
+    let spec = ui.global::<Store>().get_batch_spec();
+    if spec.dst_dir.is_empty() {
+        message_warn!(ui, tr("目录为空"));
+        return;
+    }
+
+    let settings = Settings {
+        is_decode: spec.is_decode,
+        stego: spec.stego,
+        xattr: spec.xattr,
+    };
+
+    // the jobs are pending until they get a slot of the queue
+    for &index in indexes.iter() {
+        BATCH_JOBS.lock().unwrap()[index].status = Status::Pending;
+        update_item(ui, index, Status::Pending, Some(0.0), String::default());
+    }
+
+    IS_RUNNING.store(true, Ordering::SeqCst);
+    let ui = ui.as_weak();
+    spawn(async move {
+        let message = run(ui.clone(), indexes, password, settings).await;
+        IS_RUNNING.store(false, Ordering::SeqCst);
+        async_message_success(ui.clone(), message);
+    });
+}
+
+// run the jobs in the order of the indexes, at most MAX_CONCURRENT_JOBS at the same time
+async fn run(
+    ui: Weak<AppWindow>,
+    indexes: Vec<usize>,
+    password: String,
+    settings: Settings,
+) -> String {
+    let batch_handle = CancelHandle::new();
+    BATCH_CANCEL_HANDLES
+        .lock()
+        .unwrap()
+        .push(batch_handle.clone());
+
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_JOBS));
+    let mut tasks = vec![];
+
+    for index in indexes {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        if batch_handle.is_cancelled() {
+            set_status(&ui, index, Status::Cancelled, String::default());
+            continue;
+        }
+
+        set_status(&ui, index, Status::Running, String::default());
+
+        let (ui, password) = (ui.clone(), password.clone());
+        let batch_handle = batch_handle.clone();
+        let job = BATCH_JOBS.lock().unwrap()[index].clone();
+        tasks.push(spawn(async move {
+            let entry = history_entry(&job, settings);
+            let result = run_job(ui.clone(), index, job, password, settings, &batch_handle).await;
+            drop(permit);
+
+            let entry = match &result {
+                Ok((_, output)) if settings.is_decode => {
+                    history::decode_entry(&entry.carrier, output, &file_name(output))
+                }
+                _ => entry,
+            };
+            history::record(ui.clone(), entry, &result);
+
+            match result {
+                Ok((message, _)) => set_status(&ui, index, Status::Done, message),
+                Err(e) if is_cancelled(&e) => {
+                    set_status(&ui, index, Status::Cancelled, String::default())
+                }
+                Err(e) => set_status(&ui, index, Status::Failed, error_message(&e)),
+            }
+        }));
+    }
+
+    for task in tasks {
+        let _ = task.await;
+    }
+
+    BATCH_CANCEL_HANDLES
+        .lock()
+        .unwrap()
+        .retain(|h| *h != batch_handle);
+
+    format!("{}. {}", tr("批处理完成"), summary())
+}
+
+async fn run_job(
+    ui: Weak<AppWindow>,
+    index: usize,
+    job: BatchJob,
+    password: String,
+    settings: Settings,
+    batch_handle: &CancelHandle,
+) -> Result<(String, String)> {
+    let src_spec = file_spec(&job.src_file).await?;
+    let dst_file = PathBuf::from(&job.dst_file);
+
+    if settings.is_decode {
+        let job = hidebox_core::decode::spawn(src_spec, dst_file.clone(), password, pcb(ui, index));
+        let outcome = wait(job, batch_handle).await?;
+        let dst_file = rename_to_payload_name(&dst_file, &outcome.append_name).await?;
+        let dst_file = dst_file.to_string_lossy().to_string();
+        return Ok((format!("{}: {dst_file}", tr("附件")), dst_file));
+    }
+
+    let append_spec = file_spec(&job.append_file).await?;
+    let file_type = hidebox_core::sniff::sniff_file(Path::new(&src_spec.path)).await?;
+    let backend = select_backend(file_type, settings.stego, settings.xattr, "")?;
+
+    let job = hidebox_core::encode::spawn(
+        src_spec,
+        append_spec,
+        dst_file.clone(),
+        password,
+        backend,
+        pcb(ui, index),
+    );
+    let outcome = wait(job, batch_handle).await?;
+
+    let dst_file = dst_file.to_string_lossy().to_string();
+    let mut message = dst_file.clone();
+    if outcome.is_verified {
+        message = format!("{message}. {}", tr("载体校验通过"));
+    }
+
+    Ok((message, dst_file))
+}
+
+// the entry of the decoding is replaced after the payload is renamed
+fn history_entry(job: &BatchJob, settings: Settings) -> Entry {
+    if settings.is_decode {
+        history::decode_entry(&job.src_file, &job.dst_file, "")
+    } else {
+        history::encode_entry(
+            &job.src_file,
+            &job.append_file,
+            &job.dst_file,
+            settings.stego,
+            settings.xattr,
+        )
+    }
+}
+
+// the job is canceled with the batch, even if the batch is canceled before the job starts
+async fn wait<T: Send + 'static>(job: Job<T>, batch_handle: &CancelHandle) -> Result<T> {
+    let cancel_handle = job.cancel_handle();
+    BATCH_CANCEL_HANDLES
+        .lock()
+        .unwrap()
+        .push(cancel_handle.clone());
+    if batch_handle.is_cancelled() {
+        cancel_handle.cancel();
+    }
+
+    let outcome = job.wait().await;
+    BATCH_CANCEL_HANDLES
+        .lock()
+        .unwrap()
+        .retain(|h| *h != cancel_handle);
+
+    Ok(outcome?)
+}
+
+async fn file_spec(path: &str) -> Result<FileSpec> {
+    let meta = tokio::fs::metadata(path).await?;
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("{}: {path}", tr("非法文件")))?
+        .to_string();
+
+    Ok(FileSpec {
+        path: path.to_string(),
+        name,
+        size: meta.len(),
+    })
+}
+
+// the decoded payload takes its original name unless a file already has the name
+async fn rename_to_payload_name(dst_file: &Path, append_name: &str) -> Result<PathBuf> {
+    let Some(dir) = dst_file.parent() else {
+        return Ok(dst_file.to_path_buf());
+    };
+
+    let path = dir.join(append_name);
+    if append_name.is_empty() || tokio::fs::try_exists(&path).await? {
+        return Ok(dst_file.to_path_buf());
+    }
+
+    tokio::fs::rename(dst_file, &path).await?;
+    Ok(path)
+}
+
+fn path_strings(files: Vec<PathBuf>) -> Vec<String> {
+    files
+        .into_iter()
+        .filter_map(|file| file.to_str().map(|file| file.to_string()))
+        .collect()
+}
+
+fn file_stem(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// the "name" rule pairs the files with the same stem and skips the payloads without
+//  a carrier, the "order" rule pairs the files in order and reuses the carriers
+//  if there are less carriers than payloads.
+fn pair_files(carriers: &[String], payloads: &[String], rule: &str) -> Vec<(String, String)> {
+    if carriers.is_empty() {
+        return vec![];
+    }
+
+    if rule == "name" {
+        return payloads
+            .iter()
+            .filter_map(|payload| {
+                carriers
+                    .iter()
+                    .find(|carrier| file_stem(carrier) == file_stem(payload))
+                    .map(|carrier| (carrier.clone(), payload.clone()))
+            })
+            .collect();
+    }
+
+    payloads
+        .iter()
+        .enumerate()
+        .map(|(i, payload)| (carriers[i % carriers.len()].clone(), payload.clone()))
+        .collect()
+}
+
+// the output names are unique in the batch, the later ones get a number prefix
+fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut n = 1;
+    while !used.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{n}-{name}");
+    }
+
+    unique
+}
+
+// the encoded carrier is named after the carrier and the payload and keeps the
+//  carrier extension, the decoded payload is renamed after it is decoded
+fn make_jobs(files: &BatchFiles, dst_dir: &Path, is_decode: bool, rule: &str) -> Vec<BatchJob> {
+    let mut used = HashSet::new();
+
+    let pairs = if is_decode {
+        files
+            .src_files
+            .iter()
+            .map(|src| (src.clone(), String::default()))
+            .collect()
+    } else {
+        pair_files(&files.src_files, &files.append_files, rule)
+    };
+
+    pairs
+        .into_iter()
+        .map(|(src_file, append_file)| {
+            let name = if is_decode {
+                format!("{}.payload", file_name(&src_file))
+            } else {
+                let ext = Path::new(&src_file)
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                format!("{}-{}{ext}", file_stem(&src_file), file_stem(&append_file))
+            };
+
+            BatchJob {
+                dst_file: dst_dir
+                    .join(unique_name(&mut used, name))
+                    .to_string_lossy()
+                    .to_string(),
+                src_file,
+                append_file,
+                status: Status::Pending,
+            }
+        })
+        .collect()
+}
+
+fn ui_item(job: &BatchJob) -> BatchItem {
+    let name = if job.append_file.is_empty() {
+        file_name(&job.src_file)
+    } else {
+        format!(
+            "{} → {}",
+            file_name(&job.append_file),
+            file_name(&job.src_file)
+        )
+    };
+
+    BatchItem {
+        name: name.into(),
+        status: job.status.text().into(),
+        status_type: job.status.name().into(),
+        progress: 0.0,
+        message: Default::default(),
+    }
+}
+
+fn summary() -> String {
+    let jobs = BATCH_JOBS.lock().unwrap();
+    let count = |status| jobs.iter().filter(|job| job.status == status).count();
+
+    format!(
+        "{}: {}, {}: {}, {}: {}, {}: {}",
+        tr("任务"),
+        jobs.len(),
+        tr("完成"),
+        count(Status::Done),
+        tr("失败"),
+        count(Status::Failed),
+        tr("已取消"),
+        count(Status::Cancelled),
+    )
+}
+
+// the progress of the batch is the part of the jobs which are finished
+fn batch_progress() -> f32 {
+    let jobs = BATCH_JOBS.lock().unwrap();
+    if jobs.is_empty() {
+        return 0.0;
+    }
+
+    let finished = jobs
+        .iter()
+        .filter(|job| {
+            matches!(
+                job.status,
+                Status::Done | Status::Failed | Status::Cancelled
+            )
+        })
+        .count();
+    (finished * 100 / jobs.len()) as f32
+}
+
+// the progress of the item is not changed if it is none
+fn update_item(
+    ui: &AppWindow,
+    index: usize,
+    status: Status,
+    progress: Option<f32>,
+    message: String,
+) {
+    let mut spec = ui.global::<Store>().get_batch_spec();
+    let Some(mut item) = spec.items.row_data(index) else {
+        return;
+    };
+
+    item.status = status.text().into();
+    item.status_type = status.name().into();
+    item.message = message.into();
+    if let Some(progress) = progress {
+        item.progress = progress;
+    }
+    spec.items.set_row_data(index, item);
+
+    spec.progress = batch_progress();
+    spec.summary = summary().into();
+    ui.global::<Store>().set_batch_spec(spec);
+}
+
+fn set_status(ui: &Weak<AppWindow>, index: usize, status: Status, message: String) {
+    BATCH_JOBS.lock().unwrap()[index].status = status;
+
+    let progress = match status {
+        Status::Running => Some(0.0),
+        Status::Done => Some(100.0),
+        _ => None,
+    };
+
+    let ui = ui.clone();
+    let _ = slint::invoke_from_event_loop(move || {
+        update_item(&ui.unwrap(), index, status, progress, message);
+    });
+}
+
+fn pcb(ui: Weak<AppWindow>, index: usize) -> hidebox_core::ProgressCb {
+    Arc::new(move |info| {
+        let ui = ui.clone();
+        let _ = slint::invoke_from_event_loop(move || {
+            let ui = ui.unwrap();
+            let items = ui.global::<Store>().get_batch_spec().items;
+            if let Some(mut item) = items.row_data(index) {
+                item.progress = info.percent() as f32;
+                item.message = progress_detail(&info).into();
+                items.set_row_data(index, item);
+            }
+        });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_make_jobs() {
+        let files = BatchFiles {
+            src_files: vec!["/a/x.png".to_string(), "/a/y.jpg".to_string()],
+            append_files: vec![
+                "/b/x.txt".to_string(),
+                "/b/y.txt".to_string(),
+                "/b/z".to_string(),
+            ],
+        };
+
+        let dst_files = |jobs: Vec<BatchJob>| {
+            jobs.into_iter()
+                .map(|job| (file_name(&job.append_file), file_name(&job.dst_file)))
+                .collect::<Vec<_>>()
+        };
+
+        let jobs = make_jobs(&files, Path::new("/out"), false, "order");
+        assert_eq!(
+            dst_files(jobs),
+            [
+                ("x.txt".to_string(), "x-x.png".to_string()),
+                ("y.txt".to_string(), "y-y.jpg".to_string()),
+                ("z".to_string(), "x-z.png".to_string()),
+            ]
+        );
+
+        let jobs = make_jobs(&files, Path::new("/out"), false, "name");
+        assert_eq!(jobs.len(), 2);
+
+        let jobs = make_jobs(&files, Path::new("/out"), true, "order");
+        assert_eq!(jobs[1].dst_file, "/out/y.jpg.payload");
+
+        let mut used = HashSet::new();
+        assert_eq!(unique_name(&mut used, "a.png".to_string()), "a.png");
+        assert_eq!(unique_name(&mut used, "a.png".to_string()), "2-a.png");
+    }
+}
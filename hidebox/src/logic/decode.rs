@@ -1,5 +1,8 @@
-use crate::file;
-use crate::message::{async_message_success, async_message_warn};
+use crate::logic::{
+    history,
+    util::{progress_detail, PanelJob},
+};
+use crate::message::{async_message_success, async_message_warn, error_message, is_cancelled};
 use crate::message_warn;
 use crate::slint_generatedAppWindow::{AppWindow, DecodeSpec, Logic, Store};
 use crate::util::translator::tr;
@@ -7,19 +10,37 @@ use anyhow::Result;
 use native_dialog::FileDialog;
 use slint::{ComponentHandle, Weak};
 use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::task::spawn;
 
+lazy_static! {
+    // the cancel handle of the running decoding started from the panel,
+    //  a new decoding is not started until it ends
+    static ref DECODE_JOB: Mutex<Option<hidebox_core::CancelHandle>> = Mutex::new(None);
+}
+
+// the panel shows the progress of its job, the progress of a canceled job is dropped
+static NEXT_JOB_ID: AtomicI32 = AtomicI32::new(1);
+
 pub fn init(ui: &AppWindow) {
     let ui_handle = ui.as_weak();
     ui.global::<Logic>().on_cancel_decode(move || {
-        file::decode::cancel();
+        let job_id = ui_handle
+            .unwrap()
+            .global::<Store>()
+            .get_decode_spec()
+            .job_id;
+        PanelJob::cancel(&DECODE_JOB);
 
         let ui = ui_handle.clone();
         slint::Timer::single_shot(Duration::from_millis(200), move || {
             let ui = ui.unwrap();
-            ui.global::<Store>().set_decode_spec(DecodeSpec::default());
+            if ui.global::<Store>().get_decode_spec().job_id == job_id {
+                ui.global::<Store>().set_decode_spec(DecodeSpec::default());
+            }
         });
     });
 
@@ -35,10 +56,7 @@ pub fn init(ui: &AppWindow) {
                 spawn(async move {
                     match inner_load_decode_src_file(&file_path).await {
                         Err(e) => {
-                            async_message_warn(
-                                ui.clone(),
-                                format!("{}. {}: {e:?}", tr("出错"), tr("原因")),
-                            );
+                            async_message_warn(ui.clone(), error_message(&e));
                             return;
                         }
                         Ok(false) => {
@@ -90,7 +108,7 @@ pub fn init(ui: &AppWindow) {
         let ui = ui_handle.unwrap();
         let password = password.to_string();
 
-        let spec = ui.global::<Store>().get_decode_spec();
+        let mut spec = ui.global::<Store>().get_decode_spec();
         let src_file_path = spec.src_file.to_string();
         let dst_file_path = spec.dst_file.to_string();
 
@@ -99,13 +117,70 @@ pub fn init(ui: &AppWindow) {
             return;
         }
 
+        let Some(job) = PanelJob::start(&DECODE_JOB) else {
+            message_warn!(&ui, tr("解码正在进行中"));
+            return;
+        };
+
+        spec.job_id = NEXT_JOB_ID.fetch_add(1, Ordering::SeqCst);
+        let job_id = spec.job_id;
+        ui.global::<Store>().set_decode_spec(spec);
+
         let ui = ui.as_weak();
         spawn(async move {
-            match inner_decode(ui.clone(), src_file_path, dst_file_path, password).await {
-                Ok(v) => async_message_success(ui.clone(), v),
-                Err(e) => {
-                    async_message_warn(ui.clone(), format!("{}. {}: {e:?}", tr("出错"), tr("原因")))
-                }
+            let result = inner_decode(
+                ui.clone(),
+                job_id,
+                src_file_path.clone(),
+                dst_file_path.clone(),
+                password,
+                &job.cancel_handle,
+            )
+            .await;
+            drop(job);
+
+            let payload_name = match &result {
+                Ok(outcome) => outcome.append_name.as_str(),
+                _ => "",
+            };
+            let entry = history::decode_entry(&src_file_path, &dst_file_path, payload_name);
+            history::record(ui.clone(), entry, &result);
+
+            match result {
+                Ok(outcome) => async_message_success(
+                    ui.clone(),
+                    format!(
+                        "{}. {}: {}",
+                        tr("解码成功"),
+                        tr("附件"),
+                        outcome.append_name
+                    ),
+                ),
+                Err(e) if is_cancelled(&e) => async_message_success(ui.clone(), tr("取消成功")),
+                Err(e) => async_message_warn(ui.clone(), error_message(&e)),
+            }
+        });
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_strip_decode_src_file(move || {
+        let ui = ui_handle.unwrap();
+        let src_file_path = ui.global::<Store>().get_decode_spec().src_file.to_string();
+
+        if src_file_path.is_empty() {
+            message_warn!(&ui, tr("文件名为空"));
+            return;
+        }
+
+        let ui = ui.as_weak();
+        spawn(async move {
+            let result = inner_strip(&src_file_path).await;
+            history::record(ui.clone(), history::strip_entry(&src_file_path), &result);
+
+            match result {
+                Ok(true) => async_message_success(ui.clone(), tr("清除成功")),
+                Ok(false) => async_message_warn(ui.clone(), tr("没有可清除的数据")),
+                Err(e) => async_message_warn(ui.clone(), error_message(&e)),
             }
         });
     });
@@ -113,10 +188,12 @@ pub fn init(ui: &AppWindow) {
 
 async fn inner_decode(
     ui: Weak<AppWindow>,
+    job_id: i32,
     src_file_path: String,
     dst_file_path: String,
     password: String,
-) -> Result<String> {
+    cancel_handle: &hidebox_core::CancelHandle,
+) -> Result<hidebox_core::DecodeOutcome> {
     let src_file = File::open(&src_file_path).await?;
     let src_meta = src_file.metadata().await?;
     let src_name = Path::new(&src_file_path)
@@ -125,42 +202,67 @@ async fn inner_decode(
         .to_str()
         .unwrap()
         .to_string();
-    let src_spec = file::FileSpec {
+    let src_spec = hidebox_core::FileSpec {
         path: src_file_path,
         name: src_name,
         size: src_meta.len(),
     };
 
-    file::decode(
+    let outcome = hidebox_core::decode::decode(
         src_spec,
         Path::new(&dst_file_path),
         &password,
-        pcb,
-        file::ProgressCbArg {
-            ui: Some(ui),
-            ..Default::default()
-        },
+        pcb(ui, job_id),
+        cancel_handle,
     )
-    .await
+    .await?;
+    Ok(outcome)
 }
 
 async fn inner_load_decode_src_file(file_path: &str) -> Result<bool> {
     let file = File::open(&file_path).await?;
     let meta = file.metadata().await?;
-    let spec = file::FileSpec {
+    let spec = hidebox_core::FileSpec {
         path: file_path.to_string(),
         name: String::default(),
         size: meta.len(),
     };
 
-    file::decode::has_append_file(&spec).await
+    if hidebox_core::decode::has_append_file(&spec).await? {
+        return Ok(true);
+    }
+
+    // the steganography payload can only be found with the password
+    let data = tokio::fs::read(file_path).await?;
+    Ok(hidebox_core::stego::is_carrier(&data))
 }
 
-fn pcb(arg: file::ProgressCbArg) {
-    let _ = slint::invoke_from_event_loop(move || {
-        let ui = arg.ui.unwrap().unwrap();
-        let mut spec = ui.global::<Store>().get_decode_spec();
-        spec.progress = arg.progress as f32;
-        ui.global::<Store>().set_decode_spec(spec);
-    });
+// the progress of the job is dropped if the panel shows another job
+fn pcb(ui: Weak<AppWindow>, job_id: i32) -> hidebox_core::ProgressCb {
+    Arc::new(move |info| {
+        let ui = ui.clone();
+        let _ = slint::invoke_from_event_loop(move || {
+            let ui = ui.unwrap();
+            let mut spec = ui.global::<Store>().get_decode_spec();
+            if spec.job_id != job_id {
+                return;
+            }
+
+            spec.progress = info.percent() as f32;
+            spec.progress_detail = progress_detail(&info).into();
+            ui.global::<Store>().set_decode_spec(spec);
+        });
+    })
+}
+
+// remove the trailer and the extended attributes payloads from the file in place
+async fn inner_strip(src_file_path: &str) -> Result<bool> {
+    let src_meta = tokio::fs::metadata(src_file_path).await?;
+    let src_spec = hidebox_core::FileSpec {
+        path: src_file_path.to_string(),
+        size: src_meta.len(),
+        ..Default::default()
+    };
+
+    Ok(hidebox_core::strip(&src_spec).await?)
 }
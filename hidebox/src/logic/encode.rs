@@ -1,25 +1,46 @@
-use crate::file;
-use crate::message::{async_message_success, async_message_warn};
+use crate::logic::{
+    history,
+    util::{progress_detail, PanelJob},
+};
+use crate::message::{async_message_success, async_message_warn, error_message, is_cancelled};
 use crate::message_warn;
 use crate::slint_generatedAppWindow::{AppWindow, EncodeSpec, Logic, Store};
-use crate::util::translator::tr;
-use anyhow::Result;
+use crate::util::{number::format_number_with_commas, translator::tr};
+use anyhow::{anyhow, Result};
 use native_dialog::FileDialog;
 use slint::{ComponentHandle, Weak};
 use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::task::spawn;
 
+lazy_static! {
+    // the cancel handle of the running encoding started from the panel,
+    //  a new encoding is not started until it ends
+    static ref ENCODE_JOB: Mutex<Option<hidebox_core::CancelHandle>> = Mutex::new(None);
+}
+
+// the panel shows the progress of its job, the progress of a canceled job is dropped
+static NEXT_JOB_ID: AtomicI32 = AtomicI32::new(1);
+
 pub fn init(ui: &AppWindow) {
     let ui_handle = ui.as_weak();
     ui.global::<Logic>().on_cancel_encode(move || {
-        file::encode::cancel();
+        let job_id = ui_handle
+            .unwrap()
+            .global::<Store>()
+            .get_encode_spec()
+            .job_id;
+        PanelJob::cancel(&ENCODE_JOB);
 
         let ui = ui_handle.clone();
         slint::Timer::single_shot(Duration::from_millis(200), move || {
             let ui = ui.unwrap();
-            ui.global::<Store>().set_encode_spec(EncodeSpec::default());
+            if ui.global::<Store>().get_encode_spec().job_id == job_id {
+                ui.global::<Store>().set_encode_spec(EncodeSpec::default());
+            }
         });
     });
 
@@ -27,21 +48,42 @@ pub fn init(ui: &AppWindow) {
     ui.global::<Logic>().on_load_encode_src_file(move || {
         let ui = ui_handle.unwrap();
 
-        // write data to end of these files can not effect file format
-        match FileDialog::new()
-            .set_location("~")
-            .add_filter(
-                "Image",
-                &[
-                    "bmp", "png", "jpg", "gif", "exe", "pdf", "jar", "rar", "mp4",
-                ],
-            )
-            .show_open_single_file()
-        {
+        // the carrier type is detected from the content, so the extension is not filtered
+        match FileDialog::new().set_location("~").show_open_single_file() {
             Ok(Some(file)) => {
+                let src_file_path = file.to_str().unwrap().to_string();
                 let mut spec = ui.global::<Store>().get_encode_spec();
-                spec.src_file = file.to_str().unwrap().into();
+                spec.src_file = src_file_path.clone().into();
+                spec.src_info = Default::default();
+                spec.is_jpeg = false;
                 ui.global::<Store>().set_encode_spec(spec);
+                ui.global::<Logic>().invoke_update_encode_capacity();
+
+                let ui = ui.as_weak();
+                spawn(async move {
+                    let (src_info, tolerates_trailer, is_jpeg) =
+                        match inner_encode_src_info(&src_file_path).await {
+                            Ok((src_info, file_type)) => (
+                                src_info,
+                                file_type.backend() != hidebox_core::Backend::Trailer
+                                    || file_type.tolerates_trailer(),
+                                file_type == hidebox_core::sniff::FileType::Jpeg,
+                            ),
+                            Err(e) => (format!("{}: {e}", tr("出错")), true, false),
+                        };
+
+                    if !tolerates_trailer {
+                        async_message_warn(ui.clone(), tr("该文件格式不能容忍追加的数据"));
+                    }
+
+                    let _ = slint::invoke_from_event_loop(move || {
+                        let ui = ui.unwrap();
+                        let mut spec = ui.global::<Store>().get_encode_spec();
+                        spec.src_info = src_info.into();
+                        spec.is_jpeg = is_jpeg;
+                        ui.global::<Store>().set_encode_spec(spec);
+                    });
+                });
             }
             Err(e) => {
                 message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
@@ -89,48 +131,91 @@ pub fn init(ui: &AppWindow) {
         };
     });
 
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_update_encode_capacity(move || {
+        let ui = ui_handle.unwrap();
+        let spec = ui.global::<Store>().get_encode_spec();
+        if !spec.stego || spec.src_file.is_empty() {
+            return;
+        }
+
+        let src_file_path = spec.src_file.to_string();
+        let ui = ui.as_weak();
+        spawn(async move {
+            let capacity = match inner_encode_capacity(&src_file_path).await {
+                Ok(v) => v,
+                Err(e) => format!("{}: {e}", tr("出错")),
+            };
+
+            let _ = slint::invoke_from_event_loop(move || {
+                let ui = ui.unwrap();
+                let mut spec = ui.global::<Store>().get_encode_spec();
+                spec.capacity = capacity.into();
+                ui.global::<Store>().set_encode_spec(spec);
+            });
+        });
+    });
+
     let ui_handle = ui.as_weak();
     ui.global::<Logic>().on_encode(move |password| {
         let ui = ui_handle.unwrap();
         let password = password.to_string();
 
-        let spec = ui.global::<Store>().get_encode_spec();
+        let mut spec = ui.global::<Store>().get_encode_spec();
         let src_file_path = spec.src_file.to_string();
         let append_file_path = spec.append_file.to_string();
         let dst_file_path = spec.dst_file.to_string();
+        let (stego, xattr) = (spec.stego, spec.xattr);
 
         if src_file_path.is_empty() || append_file_path.is_empty() || dst_file_path.is_empty() {
             message_warn!(&ui, tr("文件名为空"));
             return;
         }
 
+        let Some(job) = PanelJob::start(&ENCODE_JOB) else {
+            message_warn!(&ui, tr("编码正在进行中"));
+            return;
+        };
+
+        let entry = history::encode_entry(
+            &src_file_path,
+            &append_file_path,
+            &dst_file_path,
+            stego,
+            xattr,
+        );
+
+        spec.job_id = NEXT_JOB_ID.fetch_add(1, Ordering::SeqCst);
+        ui.global::<Store>().set_encode_spec(spec.clone());
+
         let ui = ui.as_weak();
         spawn(async move {
-            match inner_encode(
-                ui.clone(),
-                src_file_path,
-                append_file_path,
-                dst_file_path,
-                password,
-            )
-            .await
-            {
+            let result = inner_encode(ui.clone(), spec, password, &job.cancel_handle).await;
+            drop(job);
+            history::record(ui.clone(), entry, &result);
+
+            match result {
                 Ok(v) => async_message_success(ui.clone(), v),
-                Err(e) => {
-                    async_message_warn(ui.clone(), format!("{}. {}: {e:?}", tr("出错"), tr("原因")))
-                }
+                Err(e) if is_cancelled(&e) => async_message_success(ui.clone(), tr("取消成功")),
+                Err(e) => async_message_warn(ui.clone(), error_message(&e)),
             }
         });
     });
 }
 
+// encode the files of the spec, its job id keys the progress
 async fn inner_encode(
     ui: Weak<AppWindow>,
-    src_file_path: String,
-    append_file_path: String,
-    dst_file_path: String,
+    spec: EncodeSpec,
     password: String,
+    cancel_handle: &hidebox_core::CancelHandle,
 ) -> Result<String> {
+    let (job_id, stego, xattr) = (spec.job_id, spec.stego, spec.xattr);
+    let jpeg_segment = spec.jpeg_segment.to_string();
+    let src_file_path = spec.src_file.to_string();
+    let append_file_path = spec.append_file.to_string();
+    let dst_file_path = spec.dst_file.to_string();
+
     let src_file = File::open(&src_file_path).await?;
     let src_meta = src_file.metadata().await?;
     let src_name = Path::new(&src_file_path)
@@ -139,7 +224,7 @@ async fn inner_encode(
         .to_str()
         .unwrap()
         .to_string();
-    let src_spec = file::FileSpec {
+    let src_spec = hidebox_core::FileSpec {
         path: src_file_path,
         name: src_name,
         size: src_meta.len(),
@@ -153,31 +238,162 @@ async fn inner_encode(
         .to_str()
         .unwrap()
         .to_string();
-    let append_spec = file::FileSpec {
+    let append_spec = hidebox_core::FileSpec {
         path: append_file_path,
         name: append_name,
         size: append_meta.len(),
     };
 
-    file::encode(
+    let file_type = hidebox_core::sniff::sniff_file(Path::new(&src_spec.path)).await?;
+    let backend = select_backend(file_type, stego, xattr, &jpeg_segment)?;
+
+    let outcome = hidebox_core::encode::encode(
         src_spec,
         append_spec,
         Path::new(&dst_file_path),
         &password,
-        pcb,
-        file::ProgressCbArg {
-            ui: Some(ui),
-            ..Default::default()
-        },
+        backend,
+        pcb(ui, job_id),
+        cancel_handle,
     )
-    .await
+    .await?;
+
+    let mut message = tr("写入成功");
+    if let Some(report) = outcome.report {
+        message = format!(
+            "{message}. {}: {:.2} {}",
+            tr("嵌入效率"),
+            report.efficiency(),
+            tr("位/修改")
+        );
+
+        if report.size_change != 0 {
+            message = format!(
+                "{message}. {}: {:+} {}",
+                tr("载体大小变化"),
+                report.size_change,
+                tr("字节")
+            );
+        }
+    }
+
+    if outcome.is_verified {
+        message = format!("{message}. {}", tr("载体校验通过"));
+    }
+
+    Ok(message)
 }
 
-fn pcb(arg: file::ProgressCbArg) {
-    let _ = slint::invoke_from_event_loop(move || {
-        let ui = arg.ui.unwrap().unwrap();
-        let mut spec = ui.global::<Store>().get_encode_spec();
-        spec.progress = arg.progress as f32;
-        ui.global::<Store>().set_encode_spec(spec);
-    });
+// the backend of the options, the carrier type decides it if no option is checked.
+//  The JPEG segment is the name of its marker, the empty name is the default marker.
+pub fn select_backend(
+    file_type: hidebox_core::sniff::FileType,
+    stego: bool,
+    xattr: bool,
+    jpeg_segment: &str,
+) -> Result<hidebox_core::Backend> {
+    if xattr {
+        Ok(hidebox_core::Backend::Xattr)
+    } else if stego {
+        match file_type.stego_backend() {
+            Some(v) => Ok(v),
+            None => Err(anyhow!(tr("隐写模式不支持该文件格式"))),
+        }
+    } else {
+        match file_type.backend() {
+            hidebox_core::Backend::JpegSegment(_) => Ok(hidebox_core::Backend::JpegSegment(
+                hidebox_core::backend::jpeg::Marker::from_name(jpeg_segment).unwrap_or_default(),
+            )),
+            backend => Ok(backend),
+        }
+    }
+}
+
+// the capacity of the steganography carrier and the max size of the append file
+async fn inner_encode_capacity(src_file_path: &str) -> Result<String> {
+    let file_type = hidebox_core::sniff::sniff_file(Path::new(src_file_path)).await?;
+    let backend = match file_type.stego_backend() {
+        Some(v) => v,
+        None => return Err(anyhow!(tr("隐写模式不支持该文件格式"))),
+    };
+
+    let data = tokio::fs::read(src_file_path).await?;
+    let capacity = hidebox_core::stego::capacity(backend, &data)?;
+    let max_append_size = hidebox_core::stego::max_append_size(capacity);
+
+    Ok(format!(
+        "{}: {} {}, {}: {} {}",
+        tr("容量"),
+        format_number_with_commas(&capacity.to_string()),
+        tr("字节"),
+        tr("最大附件"),
+        format_number_with_commas(&max_append_size.to_string()),
+        tr("字节"),
+    ))
+}
+
+// the detected carrier type and the recommended backends
+async fn inner_encode_src_info(
+    src_file_path: &str,
+) -> Result<(String, hidebox_core::sniff::FileType)> {
+    let file_type = hidebox_core::sniff::sniff_file(Path::new(src_file_path)).await?;
+    let backend = file_type.backend();
+
+    let mut backends = vec![backend_name(backend)];
+    if let Some(stego_backend) = file_type.stego_backend() {
+        backends.push(backend_name(stego_backend));
+    }
+
+    let src_info = format!(
+        "{}: {}, {}: {}",
+        tr("类型"),
+        file_type_name(file_type),
+        tr("推荐"),
+        backends.join(" / ")
+    );
+
+    Ok((src_info, file_type))
+}
+
+fn file_type_name(file_type: hidebox_core::sniff::FileType) -> String {
+    match file_type {
+        hidebox_core::sniff::FileType::Text => tr("文本"),
+        hidebox_core::sniff::FileType::Unknown => tr("未知"),
+        _ => file_type.name().to_string(),
+    }
+}
+
+fn backend_name(backend: hidebox_core::Backend) -> String {
+    tr(match backend {
+        hidebox_core::Backend::Trailer => "文件尾部",
+        hidebox_core::Backend::JpegSegment(_) => "JPEG段",
+        hidebox_core::Backend::Zip => "ZIP中央目录",
+        hidebox_core::Backend::Pdf => "PDF增量更新",
+        hidebox_core::Backend::Mp4 => "MP4 free box",
+        hidebox_core::Backend::Elf => "ELF section",
+        hidebox_core::Backend::Riff => "RIFF chunk",
+        hidebox_core::Backend::Markup => "注释",
+        hidebox_core::Backend::Xattr => "扩展属性",
+        hidebox_core::Backend::Lsb => "LSB隐写",
+        hidebox_core::Backend::Dct => "DCT隐写",
+        hidebox_core::Backend::Pcm => "PCM隐写",
+    })
+}
+
+// the progress of the job is dropped if the panel shows another job
+fn pcb(ui: Weak<AppWindow>, job_id: i32) -> hidebox_core::ProgressCb {
+    Arc::new(move |info| {
+        let ui = ui.clone();
+        let _ = slint::invoke_from_event_loop(move || {
+            let ui = ui.unwrap();
+            let mut spec = ui.global::<Store>().get_encode_spec();
+            if spec.job_id != job_id {
+                return;
+            }
+
+            spec.progress = info.percent() as f32;
+            spec.progress_detail = progress_detail(&info).into();
+            ui.global::<Store>().set_encode_spec(spec);
+        });
+    })
 }
@@ -0,0 +1,316 @@
+use crate::db::{self, history::Entry};
+use crate::message::{async_message_success, async_message_warn, error_message, is_cancelled};
+use crate::slint_generatedAppWindow::{
+    AppWindow, DecodeSpec, EncodeSpec, HistoryItem, Logic, OkCancelDialogSetting, Password, Store,
+};
+use crate::util::{number::format_bytes, time::local_now, translator::tr};
+use crate::{config, message_success, message_warn};
+use anyhow::Result;
+use slint::{ComponentHandle, ModelRc, VecModel, Weak};
+use std::path::Path;
+use tokio::task::spawn;
+
+pub fn init(ui: &AppWindow) {
+    let mut spec = ui.global::<Store>().get_history_spec();
+    spec.enabled = config::history().enabled;
+    ui.global::<Store>().set_history_spec(spec);
+    ui.global::<Logic>().invoke_search_history();
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_set_history_enabled(move |enabled| {
+        let ui = ui_handle.unwrap();
+        let mut config = config::config();
+        config.history.enabled = enabled;
+
+        match config::save(config) {
+            Err(e) => {
+                message_warn!(ui, format!("{}, {}: {:?}", tr("保存失败"), tr("原因"), e));
+            }
+            _ => {
+                let mut spec = ui.global::<Store>().get_history_spec();
+                spec.enabled = enabled;
+                ui.global::<Store>().set_history_spec(spec);
+                ui.global::<Logic>().invoke_search_history();
+                message_success!(ui, tr("保存成功"));
+            }
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_search_history(move || {
+        let ui = ui_handle.unwrap();
+        if !has_history() {
+            return;
+        }
+
+        let keyword = ui.global::<Store>().get_history_spec().keyword.to_string();
+        let ui = ui.as_weak();
+        spawn(async move {
+            let entries = match inner_search(&keyword).await {
+                Ok(v) => v,
+                Err(e) => {
+                    async_message_warn(ui.clone(), error_message(&e));
+                    return;
+                }
+            };
+
+            let _ = slint::invoke_from_event_loop(move || {
+                let ui = ui.unwrap();
+                let mut spec = ui.global::<Store>().get_history_spec();
+                spec.items = ModelRc::new(VecModel::from(
+                    entries.iter().map(ui_item).collect::<Vec<_>>(),
+                ));
+                ui.global::<Store>().set_history_spec(spec);
+            });
+        });
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_reopen_history(move |id| {
+        with_entry(ui_handle.clone(), id, reopen);
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_rerun_history(move |id| {
+        with_entry(ui_handle.clone(), id, |ui, entry| {
+            reopen(ui, entry);
+
+            // the password is asked again, the stripping is confirmed again
+            if entry.operation == "strip" {
+                let translator = ui.global::<Store>().get_translator();
+                ui.global::<Store>()
+                    .set_ok_cancel_dialog_setting(OkCancelDialogSetting {
+                        title_type: "warning".into(),
+                        title_text: translator.warning,
+                        body_text: translator.strip_or_not,
+                        handle_type: "strip".into(),
+                        handle_uuid: Default::default(),
+                    });
+            } else {
+                ui.global::<Store>().set_password_dialog_config(Password {
+                    show: true,
+                    handle_type: entry.operation.clone().into(),
+                    handle_uuid: Default::default(),
+                });
+            }
+        });
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_delete_history(move |id| {
+        let ui = ui_handle.clone();
+        spawn(async move {
+            match inner_delete(Some(id as i64)).await {
+                Err(e) => async_message_warn(ui.clone(), error_message(&e)),
+                _ => {
+                    refresh(ui.clone());
+                    async_message_success(ui, tr("删除成功"));
+                }
+            }
+        });
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_purge_history(move || {
+        let ui = ui_handle.clone();
+        if !has_history() {
+            return;
+        }
+
+        spawn(async move {
+            match inner_delete(None).await {
+                Err(e) => async_message_warn(ui.clone(), error_message(&e)),
+                _ => {
+                    refresh(ui.clone());
+                    async_message_success(ui, tr("清空成功"));
+                }
+            }
+        });
+    });
+}
+
+pub fn encode_entry(carrier: &str, payload: &str, output: &str, stego: bool, xattr: bool) -> Entry {
+    let mode = if xattr {
+        "xattr"
+    } else if stego {
+        "stego"
+    } else {
+        ""
+    };
+
+    Entry {
+        operation: "encode".to_string(),
+        carrier: carrier.to_string(),
+        payload: payload.to_string(),
+        payload_name: file_name(payload),
+        output: output.to_string(),
+        mode: mode.to_string(),
+        ..Default::default()
+    }
+}
+
+// the payload name is the original name of the payload if it is decoded
+pub fn decode_entry(carrier: &str, output: &str, payload_name: &str) -> Entry {
+    Entry {
+        operation: "decode".to_string(),
+        carrier: carrier.to_string(),
+        payload_name: payload_name.to_string(),
+        output: output.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn strip_entry(carrier: &str) -> Entry {
+    Entry {
+        operation: "strip".to_string(),
+        carrier: carrier.to_string(),
+        ..Default::default()
+    }
+}
+
+// add the entry with the result of the operation if the history is enabled
+pub fn record<T>(ui: Weak<AppWindow>, mut entry: Entry, result: &Result<T>) {
+    if !config::history().enabled {
+        return;
+    }
+
+    entry.timestamp = local_now("%Y-%m-%d %H:%M:%S");
+    (entry.status, entry.message) = match result {
+        Ok(_) => ("done".to_string(), String::default()),
+        Err(e) if is_cancelled(e) => ("cancelled".to_string(), String::default()),
+        Err(e) => ("failed".to_string(), error_message(e)),
+    };
+
+    spawn(async move {
+        // the size of the decoded payload is only known after it is written
+        let path = match entry.operation.as_str() {
+            "encode" => entry.payload.clone(),
+            "decode" if entry.status == "done" => entry.output.clone(),
+            _ => String::default(),
+        };
+
+        if !path.is_empty() {
+            if let Ok(meta) = tokio::fs::metadata(&path).await {
+                entry.size = meta.len() as i64;
+            }
+        }
+
+        match inner_insert(&entry).await {
+            Err(e) => log::warn!("{e:?}"),
+            _ => refresh(ui),
+        }
+    });
+}
+
+// the database is not created just to be searched or purged
+fn has_history() -> bool {
+    config::history().enabled || Path::new(&config::db_path()).exists()
+}
+
+fn refresh(ui: Weak<AppWindow>) {
+    let _ = slint::invoke_from_event_loop(move || {
+        ui.unwrap().global::<Logic>().invoke_search_history();
+    });
+}
+
+// run the function in the event loop with the entry of the id
+fn with_entry(ui: Weak<AppWindow>, id: i32, f: impl FnOnce(&AppWindow, &Entry) + Send + 'static) {
+    spawn(async move {
+        let entry = match inner_select(id as i64).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                async_message_warn(ui.clone(), tr("记录不存在"));
+                return;
+            }
+            Err(e) => {
+                async_message_warn(ui.clone(), error_message(&e));
+                return;
+            }
+        };
+
+        let _ = slint::invoke_from_event_loop(move || {
+            f(&ui.unwrap(), &entry);
+        });
+    });
+}
+
+// load the files and the options of the entry into its panel
+fn reopen(ui: &AppWindow, entry: &Entry) {
+    if entry.operation == "encode" {
+        ui.global::<Store>().set_encode_spec(EncodeSpec {
+            src_file: entry.carrier.clone().into(),
+            append_file: entry.payload.clone().into(),
+            dst_file: entry.output.clone().into(),
+            stego: entry.mode == "stego",
+            xattr: entry.mode == "xattr",
+            ..Default::default()
+        });
+        ui.global::<Store>().set_panel_type("encode".into());
+        ui.global::<Logic>().invoke_update_encode_capacity();
+    } else {
+        ui.global::<Store>().set_decode_spec(DecodeSpec {
+            src_file: entry.carrier.clone().into(),
+            dst_file: entry.output.clone().into(),
+            ..Default::default()
+        });
+        ui.global::<Store>().set_panel_type("decode".into());
+    }
+}
+
+fn ui_item(entry: &Entry) -> HistoryItem {
+    let operation = match entry.operation.as_str() {
+        "encode" => tr("编码"),
+        "decode" => tr("解码"),
+        _ => tr("清除"),
+    };
+
+    let status = match entry.status.as_str() {
+        "done" => tr("完成"),
+        "cancelled" => tr("已取消"),
+        _ => tr("失败"),
+    };
+
+    HistoryItem {
+        id: entry.id as i32,
+        timestamp: entry.timestamp.clone().into(),
+        operation: operation.into(),
+        carrier: entry.carrier.clone().into(),
+        payload_name: entry.payload_name.clone().into(),
+        size: if entry.size > 0 {
+            format_bytes(entry.size as u64).into()
+        } else {
+            Default::default()
+        },
+        status: status.into(),
+        status_type: entry.status.clone().into(),
+        message: entry.message.clone().into(),
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+async fn inner_search(keyword: &str) -> Result<Vec<Entry>> {
+    db::history::search(&db::pool().await?, keyword).await
+}
+
+async fn inner_select(id: i64) -> Result<Option<Entry>> {
+    db::history::select(&db::pool().await?, id).await
+}
+
+async fn inner_insert(entry: &Entry) -> Result<i64> {
+    db::history::insert(&db::pool().await?, entry).await
+}
+
+// delete the entry of the id, or all the entries
+async fn inner_delete(id: Option<i64>) -> Result<()> {
+    let pool = db::pool().await?;
+    match id {
+        Some(id) => db::history::delete(&pool, id).await,
+        None => db::history::delete_all(&pool).await,
+    }
+}
@@ -1,4 +1,6 @@
 use crate::slint_generatedAppWindow::{AppWindow, Logic, MessageItem, Store};
+use crate::util::translator::tr;
+use hidebox_core::HideboxError;
 use slint::ComponentHandle;
 use slint::{Timer, TimerMode, Weak};
 
@@ -51,6 +53,38 @@ pub fn async_message_info(ui: Weak<AppWindow>, msg: String) {
     });
 }
 
+// the translated message of the error, the file APIs never put the password into their errors
+pub fn error_message(e: &anyhow::Error) -> String {
+    let Some(e) = e.downcast_ref::<HideboxError>() else {
+        return format!("{}. {}: {e}", tr("出错"), tr("原因"));
+    };
+
+    let (message, reason) = match e {
+        HideboxError::WrongPassword => (tr("密码错误"), None),
+        HideboxError::NotACarrier => (tr("不包含隐藏的数据"), None),
+        HideboxError::Corrupt(reason) => (tr("数据已损坏"), Some(reason.clone())),
+        HideboxError::Unsupported(reason) => (tr("不支持的载体"), Some(reason.clone())),
+        HideboxError::TooLarge(reason) => (tr("文件太大"), Some(reason.clone())),
+        HideboxError::BrokenCarrier(reason) => {
+            (tr("载体校验失败，输出文件未写入"), Some(reason.clone()))
+        }
+        HideboxError::Cancelled => (tr("取消成功"), None),
+        HideboxError::Io(e) => (tr("读写文件失败"), Some(e.to_string())),
+    };
+
+    match reason {
+        Some(reason) => format!("{message}. {}: {reason}", tr("原因")),
+        None => message,
+    }
+}
+
+pub fn is_cancelled(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<HideboxError>(),
+        Some(HideboxError::Cancelled)
+    )
+}
+
 pub fn init(ui: &AppWindow) {
     let timer = Timer::default();
     let ui_handle = ui.as_weak();
@@ -1,11 +1,15 @@
 pub mod about;
 pub mod base;
+pub mod batch;
 pub mod clipboard;
 pub mod decode;
 pub mod encode;
+pub mod history;
 pub mod message;
 pub mod ok_cancel_dialog;
 pub mod password_dialog;
+pub mod scan;
 pub mod setting;
+pub mod text;
 pub mod util;
 pub mod window;
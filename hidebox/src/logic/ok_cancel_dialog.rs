@@ -14,6 +14,12 @@ pub fn init(ui: &AppWindow) {
                 "decode" => {
                     ui.global::<Logic>().invoke_cancel_decode();
                 }
+                "strip" => {
+                    ui.global::<Logic>().invoke_strip_decode_src_file();
+                }
+                "purge-history" => {
+                    ui.global::<Logic>().invoke_purge_history();
+                }
                 _ => (),
             }
         });
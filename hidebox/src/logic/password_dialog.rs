@@ -4,7 +4,7 @@ use slint::ComponentHandle;
 pub fn init(ui: &AppWindow) {
     let ui_handle = ui.as_weak();
     ui.global::<Logic>()
-        .on_handle_password_dialog(move |handle_type, _handle_uuid, password| {
+        .on_handle_password_dialog(move |handle_type, handle_uuid, password| {
             let ui = ui_handle.unwrap();
 
             match handle_type.as_str() {
@@ -14,6 +14,20 @@ pub fn init(ui: &AppWindow) {
                 "decode" => {
                     ui.global::<Logic>().invoke_decode(password);
                 }
+                "text-encode" => {
+                    ui.global::<Logic>().invoke_text_encode(password);
+                }
+                "text-decode" => {
+                    ui.global::<Logic>().invoke_text_decode(password);
+                }
+                "batch" => {
+                    ui.global::<Logic>().invoke_batch(password);
+                }
+                "batch-retry" => {
+                    // the uuid is the index of the job, or -1 for all the failed jobs
+                    let index = handle_uuid.parse::<i32>().unwrap_or(-1);
+                    ui.global::<Logic>().invoke_retry_batch(password, index);
+                }
                 _ => (),
             }
         });
@@ -0,0 +1,183 @@
+use crate::message::{async_message_success, async_message_warn};
+use crate::message_warn;
+use crate::slint_generatedAppWindow::{AppWindow, Logic, ScanItem as UIScanItem, Store};
+use crate::util::{number::format_number_with_commas, translator::tr};
+use anyhow::Result;
+use hidebox_core::ScanItem;
+use native_dialog::FileDialog;
+use slint::{ComponentHandle, ModelRc, VecModel, Weak};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::task::spawn;
+
+lazy_static! {
+    // the items of the last scan which are exported
+    static ref SCAN_ITEMS: Mutex<Vec<ScanItem>> = Mutex::new(vec![]);
+
+    // the cancel handles of the running scans
+    static ref SCAN_JOBS: Mutex<Vec<hidebox_core::CancelHandle>> = Mutex::new(vec![]);
+}
+
+pub fn init(ui: &AppWindow) {
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_load_scan_dir(move || {
+        let ui = ui_handle.unwrap();
+
+        match FileDialog::new().set_location("~").show_open_single_dir() {
+            Ok(Some(dir)) => {
+                let mut spec = ui.global::<Store>().get_scan_spec();
+                spec.dir = dir.to_str().unwrap().into();
+                ui.global::<Store>().set_scan_spec(spec);
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
+            }
+            _ => (),
+        };
+    });
+
+    ui.global::<Logic>().on_cancel_scan(move || {
+        for cancel_handle in SCAN_JOBS.lock().unwrap().drain(..) {
+            cancel_handle.cancel();
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_scan(move || {
+        let ui = ui_handle.unwrap();
+        let mut spec = ui.global::<Store>().get_scan_spec();
+        let dir = spec.dir.to_string();
+
+        if dir.is_empty() {
+            message_warn!(&ui, tr("目录为空"));
+            return;
+        }
+
+        spec.progress = 0.0;
+        spec.summary = Default::default();
+        spec.items = ModelRc::default();
+        ui.global::<Store>().set_scan_spec(spec);
+
+        let ui = ui.as_weak();
+        spawn(async move {
+            match inner_scan(ui.clone(), &dir).await {
+                Ok(v) => async_message_success(ui.clone(), v),
+                Err(e) => {
+                    async_message_warn(ui.clone(), format!("{}. {}: {e:?}", tr("出错"), tr("原因")))
+                }
+            }
+        });
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_export_scan(move || {
+        let ui = ui_handle.unwrap();
+        let items = SCAN_ITEMS.lock().unwrap().clone();
+
+        if items.is_empty() {
+            message_warn!(&ui, tr("没有可导出的结果"));
+            return;
+        }
+
+        match FileDialog::new()
+            .set_location("~")
+            .set_filename("hidebox-scan.json")
+            .add_filter("JSON", &["json"])
+            .add_filter("CSV", &["csv"])
+            .show_save_single_file()
+        {
+            Ok(Some(file)) => {
+                let ui = ui.as_weak();
+                spawn(async move {
+                    match hidebox_core::scan::export(&items, &file).await {
+                        Ok(_) => async_message_success(ui.clone(), tr("导出成功")),
+                        Err(e) => async_message_warn(
+                            ui.clone(),
+                            format!("{}. {}: {e:?}", tr("导出失败"), tr("原因")),
+                        ),
+                    }
+                });
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}{:?}", tr("打开文件失败"), e));
+            }
+            _ => (),
+        };
+    });
+}
+
+async fn inner_scan(ui: Weak<AppWindow>, dir: &str) -> Result<String> {
+    let cancel_handle = hidebox_core::CancelHandle::new();
+    SCAN_JOBS.lock().unwrap().push(cancel_handle.clone());
+    let result = hidebox_core::scan::scan(Path::new(dir), pcb(ui.clone()), &cancel_handle).await;
+    SCAN_JOBS.lock().unwrap().retain(|h| *h != cancel_handle);
+    let result = result?;
+
+    let mut summary = format!(
+        "{}: {}, {}: {}",
+        tr("已扫描文件"),
+        format_number_with_commas(&result.files.to_string()),
+        tr("载体"),
+        format_number_with_commas(&result.items.len().to_string()),
+    );
+
+    // the files which are not inspected are logged by the core
+    if !result.errors.is_empty() {
+        summary = format!(
+            "{summary}, {}: {}",
+            tr("未检查"),
+            format_number_with_commas(&result.errors.len().to_string()),
+        );
+    }
+
+    let ui_items = result
+        .items
+        .iter()
+        .map(|item| UIScanItem {
+            path: item.path.clone().into(),
+            location: item.location.name().into(),
+            payload_size: item
+                .payload_size
+                .map(|v| {
+                    format!(
+                        "{} {}",
+                        format_number_with_commas(&v.to_string()),
+                        tr("字节")
+                    )
+                })
+                .unwrap_or_else(|| tr("未知大小"))
+                .into(),
+        })
+        .collect::<Vec<_>>();
+
+    *SCAN_ITEMS.lock().unwrap() = result.items;
+
+    let message = if result.is_canceled {
+        tr("取消成功")
+    } else {
+        tr("扫描完成")
+    };
+
+    let ui_summary = summary.clone();
+    let _ = slint::invoke_from_event_loop(move || {
+        let ui = ui.unwrap();
+        let mut spec = ui.global::<Store>().get_scan_spec();
+        spec.summary = ui_summary.into();
+        spec.items = ModelRc::new(VecModel::from(ui_items));
+        ui.global::<Store>().set_scan_spec(spec);
+    });
+
+    Ok(format!("{message}. {summary}"))
+}
+
+fn pcb(ui: Weak<AppWindow>) -> hidebox_core::ProgressCb {
+    Arc::new(move |info| {
+        let ui = ui.clone();
+        let _ = slint::invoke_from_event_loop(move || {
+            let ui = ui.unwrap();
+            let mut spec = ui.global::<Store>().get_scan_spec();
+            spec.progress = info.percent() as f32;
+            ui.global::<Store>().set_scan_spec(spec);
+        });
+    })
+}
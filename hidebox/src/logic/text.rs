@@ -0,0 +1,51 @@
+use crate::slint_generatedAppWindow::{AppWindow, Logic, Store};
+use crate::util::translator::tr;
+use crate::{message_success, message_warn};
+use hidebox_core::stego::text;
+use slint::ComponentHandle;
+
+pub fn init(ui: &AppWindow) {
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_text_encode(move |password| {
+        let ui = ui_handle.unwrap();
+        let mut spec = ui.global::<Store>().get_text_spec();
+
+        if spec.cover.is_empty() || spec.message.is_empty() {
+            message_warn!(&ui, tr("文本为空"));
+            return;
+        }
+
+        match text::encode(&spec.cover, &spec.message, &password) {
+            Ok(v) => {
+                spec.output = v.into();
+                ui.global::<Store>().set_text_spec(spec.clone());
+                ui.global::<Logic>().invoke_copy_to_clipboard(spec.output);
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}. {}: {e:?}", tr("出错"), tr("原因")));
+            }
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    ui.global::<Logic>().on_text_decode(move |password| {
+        let ui = ui_handle.unwrap();
+        let mut spec = ui.global::<Store>().get_text_spec();
+
+        if spec.cover.is_empty() {
+            message_warn!(&ui, tr("文本为空"));
+            return;
+        }
+
+        match text::decode(&spec.cover, &password) {
+            Ok(v) => {
+                spec.message = v.into();
+                ui.global::<Store>().set_text_spec(spec);
+                message_success!(&ui, tr("解码成功"));
+            }
+            Err(e) => {
+                message_warn!(&ui, format!("{}. {}: {e:?}", tr("出错"), tr("原因")));
+            }
+        }
+    });
+}
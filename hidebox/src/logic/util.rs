@@ -1,7 +1,9 @@
 use crate::slint_generatedAppWindow::{AppWindow, Util};
-use crate::util::{self, number, time};
+use crate::util::{self, number, time, translator::tr};
+use hidebox_core::{CancelHandle, Phase, ProgressInfo};
 use slint::ComponentHandle;
 use std::path::Path;
+use std::sync::Mutex;
 
 pub fn init(ui: &AppWindow) {
     ui.global::<Util>().on_string_fixed2(move |n| {
@@ -35,3 +37,68 @@ pub fn init(ui: &AppWindow) {
         }
     });
 }
+
+// the running job of a panel, one job runs at a time so that the cancel button of the panel
+//  cancels it. The slot of the panel is cleared when the job is dropped, even by a panic.
+pub struct PanelJob {
+    slot: &'static Mutex<Option<CancelHandle>>,
+    pub cancel_handle: CancelHandle,
+}
+
+impl PanelJob {
+    // none if a job of the panel is already running
+    pub fn start(slot: &'static Mutex<Option<CancelHandle>>) -> Option<Self> {
+        let mut running = slot.lock().unwrap();
+        if running.is_some() {
+            return None;
+        }
+
+        let cancel_handle = CancelHandle::new();
+        *running = Some(cancel_handle.clone());
+        Some(Self {
+            slot,
+            cancel_handle,
+        })
+    }
+
+    pub fn cancel(slot: &Mutex<Option<CancelHandle>>) {
+        if let Some(cancel_handle) = slot.lock().unwrap().as_ref() {
+            cancel_handle.cancel();
+        }
+    }
+}
+
+impl Drop for PanelJob {
+    fn drop(&mut self) {
+        *self.slot.lock().unwrap() = None;
+    }
+}
+
+fn phase_name(phase: Phase) -> String {
+    tr(match phase {
+        Phase::CopyCarrier => "复制载体",
+        Phase::Encrypt => "加密",
+        Phase::WriteTrailer => "写入尾部",
+        Phase::Embed => "嵌入载体",
+        Phase::Verify => "校验载体",
+        Phase::Decrypt => "解密",
+        Phase::Scan => "扫描",
+    })
+}
+
+// the phase, the processed size, the throughput and the remaining time of a job
+pub fn progress_detail(info: &ProgressInfo) -> String {
+    let mut detail = format!(
+        "{}: {} / {}, {}/s",
+        phase_name(info.phase),
+        number::format_bytes(info.done),
+        number::format_bytes(info.total),
+        number::format_bytes(info.throughput as u64),
+    );
+
+    if let Some(eta) = info.eta {
+        detail = format!("{detail}, {}: {}", tr("剩余"), time::format_duration(eta));
+    }
+
+    detail
+}
@@ -9,12 +9,12 @@ extern crate serde_derive;
 extern crate lazy_static;
 
 mod config;
-mod file;
+mod db;
 mod logic;
 mod util;
 mod version;
 
-use logic::{about, clipboard, message, ok_cancel_dialog, password_dialog, setting, window, encode, decode};
+use logic::{about, clipboard, message, ok_cancel_dialog, password_dialog, setting, window, encode, decode, text, scan, batch, history};
 
 use anyhow::Result;
 use chrono::Local;
@@ -44,6 +44,10 @@ async fn main() -> Result<()> {
 
     encode::init(&ui);
     decode::init(&ui);
+    text::init(&ui);
+    scan::init(&ui);
+    batch::init(&ui);
+    history::init(&ui);
 
     ui.run().unwrap();
 
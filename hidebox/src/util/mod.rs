@@ -1,4 +1,3 @@
-pub mod crypto;
 pub mod fs;
 pub mod number;
 pub mod str;
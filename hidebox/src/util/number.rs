@@ -1,4 +1,3 @@
-#[allow(dead_code)]
 pub fn format_number_with_commas(number_str: &str) -> String {
     if number_str.is_empty() {
         return String::default();
@@ -30,6 +29,24 @@ pub fn format_number_with_commas(number_str: &str) -> String {
     format!("{}{}", chs.iter().rev().collect::<String>(), right_part)
 }
 
+// the size in the largest unit that keeps the number not less than 1, e.g. 1.50 MB
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[0])
+    } else {
+        format!("{size:.2} {}", UNITS[unit])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,4 +66,12 @@ mod tests {
 
         assert_eq!(verify, output);
     }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.50 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.00 GB");
+    }
 }
@@ -1,5 +1,18 @@
 use chrono::Local;
+use std::time::Duration;
 
 pub fn local_now(format: &str) -> String {
     return Local::now().format(format).to_string();
 }
+
+// HH:MM:SS, the hours are omitted if it is less than an hour
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours:02}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
@@ -43,8 +43,81 @@ pub fn tr(text: &str) -> String {
     items.insert("写入成功", "Write file success");
     items.insert("取消成功", "Cancel success");
     items.insert("解码成功", "Decode success");
+    items.insert("编码正在进行中", "An encoding is running");
+    items.insert("解码正在进行中", "A decoding is running");
     items.insert("文件名为空", "File name is empty");
     items.insert("非法文件", "Invalid file");
+    items.insert("容量", "Capacity");
+    items.insert("最大附件", "Max attachment");
+    items.insert("字节", "bytes");
+    items.insert(
+        "隐写模式不支持该文件格式",
+        "Steganography does not support the file format",
+    );
+    items.insert("嵌入效率", "Embedding efficiency");
+    items.insert("位/修改", "bits/change");
+    items.insert("载体大小变化", "Carrier size change");
+    items.insert("文本为空", "Text is empty");
+    items.insert("清除成功", "Strip success");
+    items.insert("没有可清除的数据", "No hidden data to strip");
+    items.insert("类型", "Type");
+    items.insert("推荐", "Recommended");
+    items.insert("文本", "Text");
+    items.insert("未知", "Unknown");
+    items.insert("文件尾部", "trailer");
+    items.insert("JPEG段", "JPEG segment");
+    items.insert("ZIP中央目录", "ZIP central directory");
+    items.insert("PDF增量更新", "PDF incremental update");
+    items.insert("注释", "comment");
+    items.insert("扩展属性", "extended attributes");
+    items.insert("LSB隐写", "LSB steganography");
+    items.insert("DCT隐写", "DCT steganography");
+    items.insert("PCM隐写", "PCM steganography");
+    items.insert("载体校验通过", "Carrier check passed");
+    items.insert("目录为空", "Directory is empty");
+    items.insert("已扫描文件", "Scanned files");
+    items.insert("未检查", "Not inspected");
+    items.insert("未知大小", "unknown size");
+    items.insert("载体", "Carriers");
+    items.insert("扫描完成", "Scan finished");
+    items.insert("导出成功", "Export success");
+    items.insert("导出失败", "Export failed");
+    items.insert("没有可导出的结果", "No results to export");
+    items.insert("批处理正在运行", "The batch is running");
+    items.insert("没有可运行的任务", "No jobs to run");
+    items.insert("批处理完成", "Batch finished");
+    items.insert("任务", "Jobs");
+    items.insert("等待", "Pending");
+    items.insert("运行中", "Running");
+    items.insert("完成", "Done");
+    items.insert("失败", "Failed");
+    items.insert("已取消", "Cancelled");
+    items.insert("编码", "Encode");
+    items.insert("解码", "Decode");
+    items.insert("清除", "Strip");
+    items.insert("记录不存在", "The history entry does not exist");
+    items.insert("不包含隐藏的数据", "The file does not contain hidden data");
+    items.insert("数据已损坏", "The hidden data is corrupt");
+    items.insert("不支持的载体", "Unsupported carrier");
+    items.insert("文件太大", "The file is too large");
+    items.insert("读写文件失败", "Read or write file failed");
+    items.insert("附件", "Attachment");
+    items.insert("复制载体", "Copying carrier");
+    items.insert("加密", "Encrypting");
+    items.insert("写入尾部", "Writing trailer");
+    items.insert("嵌入载体", "Embedding");
+    items.insert("校验载体", "Verifying");
+    items.insert("解密", "Decrypting");
+    items.insert("扫描", "Scanning");
+    items.insert("剩余", "ETA");
+    items.insert(
+        "载体校验失败，输出文件未写入",
+        "The carrier check failed, the output file is not written",
+    );
+    items.insert(
+        "该文件格式不能容忍追加的数据",
+        "The file format does not tolerate appended data, the output may be broken",
+    );
     items.insert("刷新...", "Flush...");
     items.insert("在线", "Online");
     items.insert("正忙", "Busy");